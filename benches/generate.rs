@@ -0,0 +1,88 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use flaken::format::{encode_str_into, Radix};
+use flaken::Flaken;
+
+fn single_thread(c: &mut Criterion) {
+    let mut group = c.benchmark_group("single_thread");
+    group.throughput(Throughput::Elements(1));
+    let mut flake = Flaken::default();
+    group.bench_function("next", |b| b.iter(|| flake.next()));
+    group.finish();
+}
+
+fn contended(c: &mut Criterion) {
+    const THREADS: usize = 4;
+    const IDS: usize = 1000;
+    let mut group = c.benchmark_group("contended");
+    group.throughput(Throughput::Elements((THREADS * IDS) as u64));
+    let flake = Arc::new(Mutex::new(Flaken::default()));
+    group.bench_function("mutex_next", |b| {
+        b.iter(|| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    let flake = flake.clone();
+                    thread::spawn(move || {
+                        for _ in 0..IDS {
+                            std::hint::black_box(flake.lock().unwrap().next());
+                        }
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+fn batch(c: &mut Criterion) {
+    const LEN: usize = 4096;
+    let mut group = c.benchmark_group("batch");
+    group.throughput(Throughput::Elements(LEN as u64));
+    let mut flake = Flaken::default();
+    let ids: Vec<u64> = (0..LEN).map(|_| flake.next()).collect();
+    let fields = flake.decode_many(&ids);
+    let ts: Vec<u64> = fields.iter().map(|f| f.0).collect();
+    let node: Vec<u64> = fields.iter().map(|f| f.1).collect();
+    let seq: Vec<u64> = fields.iter().map(|f| f.2).collect();
+    group.bench_function("encode_many", |b| b.iter(|| flake.encode_many(&fields)));
+    group.bench_function("decode_many", |b| b.iter(|| flake.decode_many(&ids)));
+    group.bench_function("encode_batch", |b| {
+        b.iter_batched_ref(
+            || vec![0; LEN],
+            |out| flake.encode_batch(&ts, &node, &seq, out),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("decode_batch", |b| {
+        b.iter_batched_ref(
+            || (vec![0; LEN], vec![0; LEN], vec![0; LEN]),
+            |out| flake.decode_batch(&ids, &mut out.0, &mut out.1, &mut out.2),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+    group.throughput(Throughput::Elements(1));
+    let id = Flaken::default().next();
+    group.bench_function("decimal_into", |b| {
+        let mut buf = [0; 20];
+        b.iter(|| encode_str_into(std::hint::black_box(id), &mut buf, Radix::Decimal).len())
+    });
+    group.bench_function("itoa", |b| {
+        let mut buf = itoa::Buffer::new();
+        b.iter(|| buf.format(std::hint::black_box(id)).len())
+    });
+    group.bench_function("to_string", |b| b.iter(|| std::hint::black_box(id).to_string()));
+    group.finish();
+}
+
+criterion_group!(benches, single_thread, contended, batch, render);
+criterion_main!(benches);
@@ -0,0 +1,26 @@
+//! Decode arbitrary ids under arbitrary valid layouts
+//!
+//! Decoding must never panic, the decoded fields must fit their widths, and
+//! encoding them again must give back the same id.
+
+#![no_main]
+
+use flaken::Flaken;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (u8, u8, u64, u64)| {
+    let (ts_bits, node_bits, epoch, id) = input;
+    let ts_bits = 1 + u64::from(ts_bits) % 62;
+    let node_bits = u64::from(node_bits) % (64 - ts_bits);
+    let seq_bits = 64 - ts_bits - node_bits;
+    // Epochs are millisecond timestamps, 41 bits last until 2039
+    let epoch = epoch & ((1 << 41) - 1);
+    let flake = Flaken::default().epoch(epoch).bitwidths(ts_bits, node_bits);
+
+    let (ts, node, seq) = flake.decode(id);
+    assert!(ts >= epoch);
+    assert!(ts - epoch < 1 << ts_bits);
+    assert!(node < 1 << node_bits);
+    assert!(seq < 1 << seq_bits);
+    assert_eq!(flake.encode(ts, node, seq), id);
+});
@@ -0,0 +1,44 @@
+//! Feed arbitrary strings into the string parsers
+//!
+//! Parsing must never panic and anything accepted must parse the same way
+//! again once rendered back into its canonical form. Base32 must read the
+//! confusable characters as the digits they stand for.
+
+#![no_main]
+
+use flaken::format::{Format, Hashids, Radix};
+use flaken::node;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    if let Some(ordinal) = node::parse_ordinal(input) {
+        assert!(input.ends_with(&ordinal.to_string()));
+        assert_eq!(node::parse_ordinal(&format!("pod-{}", ordinal)), Some(ordinal));
+    }
+    for &radix in [Radix::Decimal, Radix::Base32, Radix::Base62, Radix::Safe].iter() {
+        for &check in [false, true].iter() {
+            for &fixed in [false, true].iter() {
+                let format = Format::new(radix).check_char(check).fixed_width(fixed);
+                if let Ok(id) = format.parse(input) {
+                    assert_eq!(format.parse(&format.encode(id)), Ok(id));
+                }
+            }
+        }
+    }
+    let canonical: String = input
+        .chars()
+        .map(|c| match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            upper => upper,
+        })
+        .collect();
+    for &check in [false, true].iter() {
+        let format = Format::new(Radix::Base32).check_char(check);
+        assert_eq!(format.parse(input).ok(), format.parse(&canonical).ok());
+    }
+    let hashids = Hashids::new("fuzz").min_length(8);
+    if let Ok(id) = hashids.parse(input) {
+        assert_eq!(hashids.encode(id), input);
+    }
+});
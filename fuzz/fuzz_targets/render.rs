@@ -0,0 +1,31 @@
+//! Render arbitrary ids in the sortable and safe formats
+//!
+//! Sortable renderings must order as the ids do, and a single mistyped
+//! character in a safe rendering with a check character must not parse.
+
+#![no_main]
+
+use flaken::format::{Format, Radix};
+use libfuzzer_sys::fuzz_target;
+
+const SAFE: &[u8] = b"256789BCDFGHJKLMNPQRSTVWXZ";
+
+fuzz_target!(|input: (u64, u64, u8, u8)| {
+    let (a, b, pos, typo) = input;
+    let sortable = Format::sortable();
+    let (x, y) = (sortable.encode(a), sortable.encode(b));
+    assert_eq!(x.len(), Radix::Base32.max_len());
+    assert_eq!(x.cmp(&y), a.cmp(&b));
+    assert_eq!(sortable.parse(&x), Ok(a));
+
+    let safe = Format::new(Radix::Safe).check_char(true);
+    let s = safe.encode(a);
+    assert_eq!(safe.parse(&s), Ok(a));
+    let mut mistyped = s.into_bytes();
+    let pos = usize::from(pos) % mistyped.len();
+    let typo = SAFE[usize::from(typo) % SAFE.len()];
+    if mistyped[pos] != typo {
+        mistyped[pos] = typo;
+        assert!(safe.parse(std::str::from_utf8(&mistyped).unwrap()).is_err());
+    }
+});
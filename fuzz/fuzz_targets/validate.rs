@@ -0,0 +1,25 @@
+//! Check arbitrary ids against layouts with reserved bits
+//!
+//! Validation must accept exactly the ids with clear reserved bits, and
+//! checked decoding must agree with plain decoding on anything it accepts.
+
+#![no_main]
+
+use flaken::Flaken;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (u8, u64, u64)| {
+    let (reserved, epoch, id) = input;
+    let reserved = u64::from(reserved) % 8;
+    // Epochs are millisecond timestamps, 41 bits last until 2039
+    let epoch = epoch & ((1 << 41) - 1);
+    let flake = Flaken::default().epoch(epoch).bitwidths(41, 10).reserved_bits(reserved);
+
+    let clear = reserved == 0 || id >> (64 - reserved) == 0;
+    assert_eq!(flake.validate(id).is_ok(), clear);
+    if let Ok(fields) = flake.decode_checked(id) {
+        assert!(clear);
+        assert_eq!(fields, flake.decode(id));
+        assert!(fields.0 >= epoch);
+    }
+});
@@ -0,0 +1,211 @@
+//! Moving a generator's time forward to follow the wall clock
+//!
+//! Generators read the wall clock once when built and then follow their
+//! monotonic clock, see [`on_skew`](../struct.Flaken.html#method.on_skew).
+//! When the wall clock runs ahead, the generator's anchor may be moved
+//! forward to catch up, gradually with [`smear`] or at once after a large
+//! step such as an NTP correction at boot with [`reanchor_after`], or when
+//! asked to with [`recalibrate`]. [`skip_to`] moves it forward to any
+//! later time. It is never moved backward, that would reissue timestamps,
+//! so a wall clock falling behind is not followed.
+//!
+//! [`smear`]: ../struct.Flaken.html#method.smear
+//! [`reanchor_after`]: ../struct.Flaken.html#method.reanchor_after
+//! [`recalibrate`]: ../struct.Flaken.html#method.recalibrate
+//! [`skip_to`]: ../struct.Flaken.html#method.skip_to
+
+use std::time::Duration;
+
+use crate::clock::CHECK_INTERVAL_MS;
+use crate::skew::wall_ms;
+use crate::{AsMillis, Error, Flaken};
+
+/// How a generator follows the wall clock
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Anchor {
+    smear: Option<Smear>,
+    reanchor_after: Option<Duration>,
+    next_check: u64,
+    /// How far the generator's time was moved forward since it was built
+    pub(crate) adjusted: Duration,
+}
+
+/// A bound on how fast a generator catches up with the wall clock
+#[derive(Clone, Copy, Debug)]
+struct Smear {
+    max_ppm: u64,
+    /// Microseconds the generator may still be moved forward
+    credit_us: u64,
+    /// Uptime in milliseconds when credit was last added
+    last_ms: u64,
+}
+
+impl Flaken {
+    /// Follow the wall clock when it runs ahead by slewing rather than stepping
+    ///
+    /// The generator's time is moved forward by at most `max_ppm` millionths
+    /// of the time elapsed, so leap second smears, NTP slews, and even large
+    /// steps of the wall clock turn into a gradual speed up of generated
+    /// timestamps instead of a jump. Google's leap smear runs at 11.6 ppm and
+    /// NTP slews at up to 500 ppm. The clocks are compared about once a
+    /// second, on the first id of a tick.
+    pub fn smear(mut self, max_ppm: u32) -> Flaken {
+        self.anchor.smear = Some(Smear {
+            max_ppm: max_ppm.into(),
+            credit_us: 0,
+            last_ms: self.uptime().as_millis(),
+        });
+        self
+    }
+
+    /// Move the generator's time forward at once when the wall clock is
+    /// ahead by more than `threshold`
+    ///
+    /// A large forward step of the wall clock, typically NTP correcting the
+    /// clock shortly after boot, otherwise leaves generated timestamps
+    /// lagging behind real time for the generator's whole life. Smaller
+    /// differences are left alone, or to [`smear`](#method.smear) if set.
+    pub fn reanchor_after(mut self, threshold: Duration) -> Flaken {
+        self.anchor.reanchor_after = Some(threshold);
+        self
+    }
+
+    /// Move the generator's time forward to the wall clock now, returning how
+    /// far it was moved
+    ///
+    /// For services choosing when the adjustment happens, such as during idle
+    /// periods. Nothing is corrected when the wall clock is behind the
+    /// generator, as moving back could reissue ids.
+    pub fn recalibrate(&mut self) -> Duration {
+        let generator_ms = self.start_ts + self.elapsed().as_millis();
+        let step = Duration::from_millis(wall_ms().saturating_sub(generator_ms));
+        if step > Duration::from_millis(0) {
+            trace_event!(debug, node = self.node, step_ms = step.as_millis(), "recalibrated generator time to the wall clock");
+            self.advance(step);
+        }
+        step
+    }
+
+    /// Move the generator's time forward to `ts`, in milliseconds since the
+    /// unix epoch, returning how far it was moved
+    ///
+    /// Useful after restoring from a snapshot, skipping past the last id it
+    /// held, or to leave a safety gap after a suspected clock incident.
+    /// Times before the generator's are left alone, it never moves backward.
+    /// Fails with [`Error::OutOfRange`] when the layout can not encode `ts`.
+    pub fn skip_to(&mut self, ts: u64) -> Result<Duration, Error> {
+        if ts > self.layout.max_ts() {
+            return Err(Error::OutOfRange(ts));
+        }
+        let generator_ms = self.start_ts + self.elapsed().as_millis();
+        let step = Duration::from_millis(ts.saturating_sub(generator_ms));
+        self.advance(step);
+        Ok(step)
+    }
+
+    /// Catch up with the wall clock if a check is due at `tick`
+    pub(crate) fn follow_wall(&mut self, tick: u64) {
+        if (self.anchor.smear.is_none() && self.anchor.reanchor_after.is_none()) || tick < self.anchor.next_check {
+            return;
+        }
+        self.anchor.next_check = tick + CHECK_INTERVAL_MS.div_ceil(self.layout.tick_ms);
+        let generator_ms = self.start_ts + self.elapsed().as_millis();
+        let uptime_ms = self.uptime().as_millis();
+        let behind = wall_ms().saturating_sub(generator_ms);
+        let mut step = 0;
+        if let Some(ref mut smear) = self.anchor.smear {
+            if behind == 0 {
+                smear.credit_us = 0;
+            } else {
+                smear.credit_us += uptime_ms.saturating_sub(smear.last_ms) * smear.max_ppm / 1000;
+            }
+            smear.last_ms = uptime_ms;
+            step = behind.min(smear.credit_us / 1000);
+            smear.credit_us -= step * 1000;
+        }
+        match self.anchor.reanchor_after {
+            Some(threshold) if behind > threshold.as_millis() => {
+                trace_event!(warn, node = self.node, step_ms = behind, "wall clock stepped forward, moving generator time with it");
+                if let Some(ref mut smear) = self.anchor.smear {
+                    smear.credit_us = 0;
+                }
+                self.advance(Duration::from_millis(behind));
+            }
+            _ if step > 0 => {
+                trace_event!(debug, node = self.node, step_ms = step, "moved generator time forward to the wall clock");
+                self.advance(Duration::from_millis(step));
+            }
+            _ => {}
+        }
+    }
+
+    /// Move the generator's time forward
+    pub(crate) fn advance(&mut self, step: Duration) {
+        self.anchor.adjusted += step;
+    }
+}
+
+#[test]
+fn test_smear() {
+    use crate::clock::ManualClock;
+
+    let clock = ManualClock::new();
+    let mut flake = Flaken::default().clock(clock.clone()).smear(100_000);
+    // Built ten seconds ago, since when the wall clock was stepped forward
+    flake.start_ts -= 10_000;
+    let mut last = flake.next();
+    for i in 1..=5 {
+        clock.set(Duration::from_secs(i));
+        let id = flake.next();
+        let step = flake.decode(id).0 - flake.decode(last).0;
+        assert!((1000..=1100).contains(&step));
+        last = id;
+    }
+    // A tenth of the five seconds elapsed
+    assert_eq!(flake.anchor.adjusted, Duration::from_millis(500));
+}
+
+#[test]
+fn test_reanchor_after() {
+    let mut flake = Flaken::default().reanchor_after(Duration::from_secs(5));
+    flake.start_ts -= 2_000;
+    flake.next();
+    assert_eq!(flake.anchor.adjusted, Duration::from_millis(0));
+
+    let mut flake = Flaken::default().reanchor_after(Duration::from_secs(5));
+    flake.start_ts -= 10_000;
+    let before = flake.next();
+    assert!(flake.anchor.adjusted >= Duration::from_millis(10_000));
+    let after = flake.next();
+    assert!(flake.decode(after).0.abs_diff(wall_ms()) < 100);
+    assert!(after > before);
+}
+
+#[test]
+fn test_recalibrate() {
+    let mut flake = Flaken::default();
+    flake.start_ts -= 3_000;
+    let before = flake.next();
+    let corrected = flake.recalibrate();
+    assert!(corrected >= Duration::from_millis(3_000) && corrected < Duration::from_millis(3_100));
+    let after = flake.next();
+    assert!(after > before);
+    assert!(flake.decode(after).0.abs_diff(wall_ms()) < 100);
+
+    flake.start_ts += 60_000;
+    assert_eq!(flake.recalibrate(), Duration::from_millis(0));
+}
+
+#[test]
+fn test_skip_to() {
+    let mut flake = Flaken::default();
+    let before = flake.next();
+    let gap = flake.decode(before).0 + 60_000;
+    let skipped = flake.skip_to(gap).unwrap();
+    assert!(skipped > Duration::from_millis(59_000));
+    let after = flake.next();
+    assert!(flake.decode(after).0 >= gap);
+    assert_eq!(flake.skip_to(gap - 10_000).unwrap(), Duration::from_millis(0));
+    assert!(flake.next() > after);
+    assert!(matches!(flake.skip_to(u64::MAX), Err(Error::OutOfRange(_))));
+}
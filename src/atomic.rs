@@ -0,0 +1,270 @@
+//! Lock free id generation shared between threads
+//!
+//! The last issued millisecond and sequence value are packed into a single
+//! atomic which is advanced with a compare and swap loop. The atomics come
+//! from loom when built with `--cfg loom`, so the same code is model checked
+//! by running
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --lib loom
+//! ```
+
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicU64, Ordering};
+#[cfg(not(loom))]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::layout::field_max;
+use crate::{Error, Flaken, TsOverflow};
+
+/// Flaken ID generator which may be shared between threads
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+/// use flaken::{AtomicFlaken, Flaken};
+///
+/// let flake = Arc::new(AtomicFlaken::new(Flaken::default().node(2)));
+/// let handles: Vec<_> = (0..4).map(|_| {
+///     let flake = flake.clone();
+///     thread::spawn(move || flake.next())
+/// }).collect();
+/// for handle in handles {
+///     let (_, node, _) = flake.decode(handle.join().unwrap());
+///     assert_eq!(node, 2);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AtomicFlaken {
+    flake: Flaken,
+    /// `(millisecond + 1) << seq_bits | seq` of the last issued id, 0 before the first
+    state: AtomicU64,
+    /// The strict ordering fence of the layout
+    fence: Arc<std::sync::atomic::AtomicU64>,
+}
+
+impl AtomicFlaken {
+    /// Share a configured generator between threads
+    ///
+    /// Generation continues from where `flake` left off. A maximum rate,
+    /// random sequence step, sink, skew hook, node rotation, smearing,
+    /// generating ahead, real-time checks, saturating timestamp overflow, or
+    /// moving on through an epoch table set on `flake` is not applied.
+    ///
+    /// The tick and sequence share one atomic, so panics when the sequence
+    /// bits leave too few bits to count the ticks up to the latest timestamp
+    /// the layout can encode.
+    pub fn new(flake: Flaken) -> AtomicFlaken {
+        let last_tick = flake.layout.max_ts().saturating_sub(flake.tick_ts(0)) / flake.layout.tick_ms;
+        assert!(
+            last_tick < max_tick(flake.layout.seq_bits),
+            "{} sequence bits leave too few bits to count the ticks of the layout",
+            flake.layout.seq_bits
+        );
+        let state = if flake.seq == 0 {
+            0
+        } else {
+            ((flake.duration + 1) << flake.layout.seq_bits) | (flake.seq - 1).min(flake.max_seq())
+        };
+        AtomicFlaken {
+            fence: crate::fence::of(&flake.layout_at(0)),
+            flake,
+            state: AtomicU64::new(state),
+        }
+    }
+
+    /// Generate the next id
+    ///
+    /// Ids returned to any one thread always increase. Once every sequence
+    /// value of the current millisecond has been used this blocks until the
+    /// next millisecond using the generator's wait strategy. Panics when the
+    /// node id does not fit the layout, or once the clock has run past the
+    /// latest timestamp the layout can encode unless the timestamp wraps.
+    /// Wrapping, it panics once the generator has run longer than the state
+    /// can count ticks rather than issue ids again.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&self) -> u64 {
+        let seq_bits = self.flake.layout.seq_bits;
+        let max_seq = self.flake.max_seq();
+        let mut cur = self.state.load(Ordering::Acquire);
+        loop {
+            let now = self.flake.elapsed_ticks();
+            let (tick, seq) = if cur == 0 {
+                (now, 0)
+            } else {
+                let last_tick = (cur >> seq_bits) - 1;
+                let last_seq = cur & max_seq;
+                if now > last_tick {
+                    (now, 0)
+                } else if last_seq < max_seq {
+                    // Another thread may have read a later time, never go back from it
+                    (last_tick, last_seq + 1)
+                } else {
+                    trace_event!(debug, node = self.flake.node, tick = last_tick, "sequence exhausted, waiting for the next tick");
+                    let waiting = Instant::now();
+                    self.pause(self.flake.tick_start(last_tick + 1));
+                    crate::metrics::sequence_wait(waiting.elapsed());
+                    cur = self.state.load(Ordering::Acquire);
+                    continue;
+                }
+            };
+            if tick >= max_tick(seq_bits) {
+                panic!("generator ran past the {} ticks its state can count", max_tick(seq_bits));
+            }
+            let next = ((tick + 1) << seq_bits) | seq;
+            match self.state.compare_exchange_weak(cur, next, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => {
+                    let id = match self.flake.encode_generated(self.flake.tick_ts(tick), seq) {
+                        Ok(id) => id,
+                        Err(err) => {
+                            if let (Error::OutOfRange(ts), TsOverflow::Handler(handler)) = (&err, &self.flake.overflow) {
+                                handler(*ts);
+                            }
+                            panic!("{}", err);
+                        }
+                    };
+                    if self.flake.strict && !crate::fence::publish(&self.fence, id) {
+                        // A larger id was returned elsewhere, use up the tick and wait for the next
+                        let exhausted = ((tick + 1) << seq_bits) | max_seq;
+                        let _ = self.state.compare_exchange(next, exhausted, Ordering::AcqRel, Ordering::Acquire);
+                        cur = self.state.load(Ordering::Acquire);
+                        continue;
+                    }
+                    crate::metrics::ids_generated(1);
+                    return id;
+                }
+                Err(actual) => cur = actual,
+            }
+        }
+    }
+
+    #[cfg(not(loom))]
+    fn pause(&self, next_tick: Duration) {
+        self.flake.wait.pause(next_tick.saturating_sub(self.flake.elapsed()));
+    }
+
+    #[cfg(loom)]
+    fn pause(&self, _next_tick: Duration) {
+        loom::thread::yield_now();
+    }
+
+    /// Encode into a flake id the given timestamp, node id, and sequence value
+    pub fn encode(&self, ts: u64, node: u64, seq: u64) -> u64 {
+        self.flake.encode(ts, node, seq)
+    }
+
+    /// Decode from an encoded id the timestamp, node id, and sequence id
+    pub fn decode(&self, id: u64) -> (u64, u64, u64) {
+        self.flake.decode(id)
+    }
+}
+
+/// The number of ticks the state can count next to `seq_bits` sequence bits,
+/// the tick being stored plus one
+fn max_tick(seq_bits: u64) -> u64 {
+    field_max(64 - seq_bits)
+}
+
+impl From<Flaken> for AtomicFlaken {
+    fn from(flake: Flaken) -> AtomicFlaken {
+        AtomicFlaken::new(flake)
+    }
+}
+
+#[cfg(not(loom))]
+#[test]
+fn test_atomic_threads() {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::thread;
+
+    let flake = Arc::new(AtomicFlaken::new(Flaken::default().bitwidths(40, 16)));
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let flake = flake.clone();
+            thread::spawn(move || {
+                let ids: Vec<u64> = (0..2000).map(|_| flake.next()).collect();
+                assert!(ids.windows(2).all(|w| w[0] < w[1]));
+                ids
+            })
+        })
+        .collect();
+    let mut seen = HashSet::new();
+    for handle in handles {
+        for id in handle.join().unwrap() {
+            assert!(seen.insert(id));
+            assert!(flake.decode(id).2 < 256);
+        }
+    }
+}
+
+#[cfg(not(loom))]
+#[test]
+fn test_atomic_continues() {
+    let mut flake = Flaken::default();
+    let id0 = flake.next();
+    let flake = AtomicFlaken::from(flake);
+    let id1 = flake.next();
+    assert!(id1 > id0);
+}
+
+#[cfg(not(loom))]
+#[test]
+#[should_panic(expected = "ticks its state can count")]
+fn test_atomic_tick_range() {
+    use crate::clock::ManualClock;
+
+    // Forty sequence bits leave 24 bits to count ticks, the timestamp wraps long before
+    let clock = ManualClock::new();
+    let flake = AtomicFlaken::new(Flaken::default().bitwidths(20, 4).clock(clock.clone()));
+    clock.set(Duration::from_millis((1 << 24) - 2));
+    let id0 = flake.next();
+    let id1 = flake.next();
+    assert!(id1 > id0);
+    assert_eq!(flake.decode(id0).0, flake.decode(id1).0);
+    clock.advance(Duration::from_millis(1));
+    flake.next();
+}
+
+/// Clock advancing a millisecond every third read, for model checking
+#[cfg(loom)]
+#[derive(Clone, Debug)]
+struct SteppingClock(std::sync::Arc<AtomicU64>);
+
+#[cfg(loom)]
+impl crate::clock::Clock for SteppingClock {
+    fn now(&self) -> Duration {
+        Duration::from_millis(self.0.fetch_add(1, Ordering::AcqRel) / 3)
+    }
+}
+
+#[cfg(loom)]
+#[test]
+fn loom_next_unique_across_rollover() {
+    use loom::thread;
+    use std::sync::Arc;
+
+    loom::model(|| {
+        let clock = SteppingClock(Arc::new(AtomicU64::new(0)));
+        // One sequence bit, so some interleavings use up a millisecond
+        let flake = Arc::new(AtomicFlaken::new(Flaken::default().bitwidths(40, 23).clock(clock)));
+        let workers: Vec<_> = (0..2)
+            .map(|_| {
+                let flake = flake.clone();
+                thread::spawn(move || {
+                    let id0 = flake.next();
+                    let id1 = flake.next();
+                    assert!(id0 < id1);
+                    vec![id0, id1]
+                })
+            })
+            .collect();
+        let mut ids: Vec<u64> = workers.into_iter().flat_map(|w| w.join().unwrap()).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 4);
+    });
+}
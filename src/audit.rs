@@ -0,0 +1,248 @@
+//! Checking whether two id spaces may collide, for merging the ids of
+//! separate systems
+//!
+//! A [`Space`] describes the ids a system issues: its generator
+//! configuration, the node ids it runs with, and the time it was live.
+//! [`overlap`] tells whether two spaces may produce the same id, and
+//! [`duplicates`] finds the ids two sets actually share.
+//!
+//! Sets too large to hold in memory are streamed through a [`BloomDedup`]
+//! instead, which flags ids it has probably seen before for an exact check.
+//!
+//! ```
+//! use flaken::audit::{self, Space};
+//! use flaken::Flaken;
+//!
+//! let ours = Flaken::default();
+//! let theirs = Flaken::default();
+//! let a = Space::new(&ours, &[0, 1, 2]);
+//! let b = Space::new(&theirs, &[2, 3]);
+//! let overlap = audit::overlap(&a, &b);
+//! assert_eq!(overlap.shared_nodes, vec![2]);
+//! assert!(overlap.possible());
+//! ```
+
+use std::collections::HashSet;
+
+use crate::layout::field_max;
+use crate::rand::mix;
+use crate::Flaken;
+
+/// The ids one system issues
+#[derive(Clone, Debug)]
+pub struct Space<'a> {
+    flake: &'a Flaken,
+    nodes: Vec<u64>,
+    from_ms: u64,
+    to_ms: u64,
+}
+
+impl<'a> Space<'a> {
+    /// Ids of `flake`'s configuration with any of `nodes`, over the whole
+    /// lifetime of the layout
+    ///
+    /// Node ids are cut to the layout's node bits as encoding does, so node
+    /// ids too large for the layout count as the node ids they alias.
+    pub fn new(flake: &'a Flaken, nodes: &[u64]) -> Space<'a> {
+        let max_node = field_max(flake.layout.node_bits);
+        let mut nodes: Vec<u64> = nodes.iter().map(|&node| node & max_node).collect();
+        nodes.sort_unstable();
+        nodes.dedup();
+        Space {
+            flake,
+            nodes,
+            from_ms: flake.layout.epoch,
+            to_ms: flake.layout.max_ts(),
+        }
+    }
+
+    /// Only ids with timestamps from `from_ms` to `to_ms` inclusive, in
+    /// milliseconds since the unix epoch
+    pub fn between(mut self, from_ms: u64, to_ms: u64) -> Space<'a> {
+        self.from_ms = from_ms.max(self.flake.layout.epoch);
+        self.to_ms = to_ms.min(self.flake.layout.max_ts());
+        self
+    }
+
+    /// The smallest and largest id of the space, none when it is empty
+    pub fn id_range(&self) -> Option<(u64, u64)> {
+        if self.from_ms > self.to_ms || self.nodes.is_empty() {
+            return None;
+        }
+        let layout = &self.flake.layout;
+        Some((
+            layout.encode(self.from_ms, self.nodes[0], 0),
+            layout.encode(self.to_ms, self.nodes[self.nodes.len() - 1], layout.max_seq()),
+        ))
+    }
+}
+
+/// What two id spaces have in common
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Overlap {
+    /// Whether both use the same epoch and bit layout
+    pub same_layout: bool,
+    /// Node ids both run with
+    pub shared_nodes: Vec<u64>,
+    /// The times both were live, in milliseconds since the unix epoch
+    pub time: Option<(u64, u64)>,
+    /// The id values both may issue
+    pub ids: Option<(u64, u64)>,
+}
+
+impl Overlap {
+    /// Whether the two spaces may issue the same id
+    ///
+    /// With the same layout that takes a shared node id at a shared time.
+    /// With different layouts any shared id values are counted, which may
+    /// report collisions that can not happen but never misses one.
+    pub fn possible(&self) -> bool {
+        if self.same_layout {
+            !self.shared_nodes.is_empty() && self.time.is_some()
+        } else {
+            self.ids.is_some()
+        }
+    }
+}
+
+/// Compare two id spaces
+pub fn overlap(a: &Space, b: &Space) -> Overlap {
+    let b_nodes: HashSet<u64> = b.nodes.iter().cloned().collect();
+    let from = a.from_ms.max(b.from_ms);
+    let to = a.to_ms.min(b.to_ms);
+    let ids = match (a.id_range(), b.id_range()) {
+        (Some((a_min, a_max)), Some((b_min, b_max))) if a_min.max(b_min) <= a_max.min(b_max) => {
+            Some((a_min.max(b_min), a_max.min(b_max)))
+        }
+        _ => None,
+    };
+    Overlap {
+        same_layout: a.flake.layout == b.flake.layout,
+        shared_nodes: a.nodes.iter().cloned().filter(|node| b_nodes.contains(node)).collect(),
+        time: if from <= to { Some((from, to)) } else { None },
+        ids,
+    }
+}
+
+/// The ids in both `a` and `b`, ascending
+pub fn duplicates<A, B>(a: A, b: B) -> Vec<u64>
+where
+    A: IntoIterator<Item = u64>,
+    B: IntoIterator<Item = u64>,
+{
+    let a: HashSet<u64> = a.into_iter().collect();
+    let mut dups: Vec<u64> = b.into_iter().filter(|id| a.contains(id)).collect();
+    dups.sort_unstable();
+    dups.dedup();
+    dups
+}
+
+/// A Bloom filter flagging ids probably seen before, for collision audits
+/// of more ids than fit in a `HashSet`
+///
+/// Every repeated id is flagged, and other ids are flagged at about the
+/// false positive rate the filter was sized for, so flagged ids are
+/// checked exactly afterwards, such as with [`duplicates`] against the
+/// database. The filter takes about 1.2 bytes per expected id at a 1% rate.
+///
+/// ```
+/// use flaken::audit::BloomDedup;
+///
+/// let mut dedup = BloomDedup::new(1000, 0.01);
+/// assert!(!dedup.insert(42));
+/// assert!(dedup.insert(42));
+/// ```
+#[derive(Clone, Debug)]
+pub struct BloomDedup {
+    bits: Vec<u64>,
+    hashes: u32,
+    inserted: u64,
+}
+
+impl BloomDedup {
+    /// Size a filter for `expected` ids flagged falsely at `false_positive_rate`
+    pub fn new(expected: u64, false_positive_rate: f64) -> BloomDedup {
+        assert!(false_positive_rate > 0.0 && false_positive_rate < 1.0);
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(expected.max(1) as f64) * false_positive_rate.ln() / (ln2 * ln2)).ceil().max(64.0);
+        let hashes = (bits / expected.max(1) as f64 * ln2).round().clamp(1.0, 32.0) as u32;
+        BloomDedup {
+            bits: vec![0; (bits as usize).div_ceil(64)],
+            hashes,
+            inserted: 0,
+        }
+    }
+
+    /// Add an id, returning whether it was probably added before
+    pub fn insert(&mut self, id: u64) -> bool {
+        let len = self.bits.len() as u64 * 64;
+        // Double hashing with two SplitMix64 style mixes of the id
+        let h1 = mix(id);
+        let h2 = mix(id ^ 0x9E3779B97F4A7C15) | 1;
+        let mut seen = true;
+        for i in 0..u64::from(self.hashes) {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % len;
+            let (word, mask) = ((bit / 64) as usize, 1 << (bit % 64));
+            seen &= self.bits[word] & mask != 0;
+            self.bits[word] |= mask;
+        }
+        self.inserted += 1;
+        seen
+    }
+
+    /// Stream ids through the filter, returning those probably seen before
+    /// in the stream or earlier
+    pub fn flag<I: IntoIterator<Item = u64>>(&mut self, ids: I) -> Vec<u64> {
+        ids.into_iter().filter(|&id| self.insert(id)).collect()
+    }
+
+    /// Ids added so far, repeats included
+    pub fn inserted(&self) -> u64 {
+        self.inserted
+    }
+}
+
+#[test]
+fn test_overlap() {
+    let old = Flaken::default().epoch(1_000_000_000_000);
+    let new = Flaken::default().epoch(1_500_000_000_000);
+    // Different epochs shift the same wall time to different id values
+    let a = Space::new(&old, &[1]).between(1_600_000_000_000, 1_600_000_100_000);
+    let b = Space::new(&new, &[1]).between(1_600_000_000_000, 1_600_000_100_000);
+    let overlap = overlap(&a, &b);
+    assert!(!overlap.same_layout);
+    assert_eq!(overlap.shared_nodes, vec![1]);
+    assert!(overlap.time.is_some());
+    assert!(!overlap.possible());
+
+    let same = Flaken::default();
+    let a = Space::new(&same, &[0, 1]).between(0, 2_000_000_000_000);
+    let b = Space::new(&same, &[1]).between(1_000_000_000_000, u64::MAX);
+    assert!(super::audit::overlap(&a, &b).possible());
+    let b = Space::new(&same, &[2]);
+    assert!(!super::audit::overlap(&a, &b).possible());
+
+    // Node 1025 does not fit 10 node bits and encodes as node 1
+    let a = Space::new(&same, &[1]);
+    let b = Space::new(&same, &[1025]);
+    let aliased = super::audit::overlap(&a, &b);
+    assert_eq!(aliased.shared_nodes, vec![1]);
+    assert!(aliased.possible());
+}
+
+#[test]
+fn test_duplicates() {
+    assert_eq!(duplicates(vec![5, 1, 3, 3], vec![3, 4, 5, 3]), vec![3, 5]);
+    assert!(duplicates(0..10, 10..20).is_empty());
+}
+
+#[test]
+fn test_bloom_dedup() {
+    let flake = Flaken::default();
+    let ids: Vec<u64> = (0..100_000).map(|i| flake.encode(flake.start_ts + i / 4096, 0, i % 4096)).collect();
+    let mut dedup = BloomDedup::new(100_000, 0.01);
+    let flagged = dedup.flag(ids.iter().copied().chain([ids[10], ids[99_999]]));
+    assert!(flagged.len() < 2_000);
+    assert!(flagged.ends_with(&[ids[10], ids[99_999]]));
+    assert_eq!(dedup.inserted(), 100_002);
+}
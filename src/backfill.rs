@@ -0,0 +1,213 @@
+//! Generating ids as of historical timestamps, for data migrations
+//!
+//! Records imported from an older system keep their creation time when a
+//! [`BackfillFlaken`] issues their ids, either one id at a time for each
+//! record's timestamp or as a [`Spread`] of ids over a span of time.
+
+use std::collections::BTreeMap;
+
+use crate::rand::Rng;
+use crate::{Error, Flaken};
+
+/// Generates ids as of historical timestamps, for data migrations
+///
+/// Sequence values are counted per timestamp, so backfilled ids are unique
+/// and carry the time the record was really created. They are only unique
+/// against ids from other generators if the node id is not used by any
+/// live generator.
+///
+/// ```
+/// use flaken::{BackfillFlaken, Flaken};
+///
+/// let mut backfill = BackfillFlaken::new(Flaken::default().node(1023));
+/// let created = 1_400_000_000_000;
+/// let id0 = backfill.next_at(created).unwrap();
+/// let id1 = backfill.next_at(created).unwrap();
+/// assert_eq!(backfill.flaken().decode(id1), (created, 1023, 1));
+/// assert!(id1 > id0);
+/// ```
+#[derive(Debug)]
+pub struct BackfillFlaken {
+    flake: Flaken,
+    seqs: BTreeMap<u64, u64>,
+}
+
+impl BackfillFlaken {
+    /// Backfill ids with the layout and node id of `flake`
+    pub fn new(flake: Flaken) -> BackfillFlaken {
+        BackfillFlaken {
+            flake,
+            seqs: BTreeMap::new(),
+        }
+    }
+
+    /// Generate the next id of the millisecond `ts`, in milliseconds since
+    /// the unix epoch
+    ///
+    /// Fails with [`Error::SequenceExhausted`] once every sequence value of
+    /// the millisecond, or of the tick with longer ticks, is used, and with [`Error::OutOfRange`] when the
+    /// layout can not encode the timestamp.
+    pub fn next_at(&mut self, ts: u64) -> Result<u64, Error> {
+        if ts < self.flake.layout.epoch || ts > self.flake.layout.max_ts() {
+            return Err(Error::OutOfRange(ts));
+        }
+        let max_seq = self.flake.max_seq();
+        let ts = self.flake.layout.tick_floor(ts);
+        let seq = self.seqs.entry(ts).or_insert(0);
+        if *seq > max_seq {
+            return Err(Error::SequenceExhausted(ts));
+        }
+        let id = self.flake.encode(ts, self.flake.node, *seq);
+        *seq += 1;
+        Ok(id)
+    }
+
+    /// Forget the sequence counters of milliseconds before `ts`
+    ///
+    /// Backfilling in time order, counters of finished milliseconds only
+    /// take memory. Generating ids before `ts` again reuses sequence values.
+    pub fn forget_before(&mut self, ts: u64) {
+        self.seqs = self.seqs.split_off(&ts);
+    }
+
+    /// The generator whose layout and node id are used
+    pub fn flaken(&self) -> &Flaken {
+        &self.flake
+    }
+
+    /// Spread `count` ids evenly over the milliseconds from `start` up to but
+    /// excluding `end`, for synthesizing historical datasets
+    ///
+    /// Fails with [`Error::OutOfRange`] when the layout can not encode the
+    /// range, and with [`Error::SequenceExhausted`] when the range does not
+    /// have `count` sequence values left.
+    ///
+    /// ```
+    /// use flaken::{BackfillFlaken, Flaken};
+    ///
+    /// let backfill = BackfillFlaken::new(Flaken::default());
+    /// let day = 86_400_000;
+    /// let start = 1_500_000_000_000;
+    /// let ids: Vec<u64> = backfill.spread(start, start + day, 24).unwrap().collect();
+    /// assert_eq!(ids.len(), 24);
+    /// assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    /// ```
+    pub fn spread(self, start: u64, end: u64, count: u64) -> Result<Spread, Error> {
+        let layout = &self.flake.layout;
+        if start < layout.epoch || start >= end {
+            return Err(Error::OutOfRange(start));
+        }
+        if end - 1 > layout.max_ts() {
+            return Err(Error::OutOfRange(end));
+        }
+        let used: u64 = self.seqs.range(layout.tick_floor(start)..end).map(|(_, &seq)| seq).sum();
+        let ticks = (end - 1 - layout.epoch) / layout.tick_ms - (start - layout.epoch) / layout.tick_ms + 1;
+        let capacity = ticks.saturating_mul(self.flake.max_seq() + 1);
+        if count > capacity.saturating_sub(used) {
+            return Err(Error::SequenceExhausted(start));
+        }
+        Ok(Spread {
+            backfill: self,
+            start,
+            end,
+            count,
+            emitted: 0,
+            rng: None,
+        })
+    }
+
+    /// Spread ids over the milliseconds from `start` up to but excluding
+    /// `end` at an average of `ids_per_sec`, see [`spread`](#method.spread)
+    pub fn spread_at_rate(self, start: u64, end: u64, ids_per_sec: f64) -> Result<Spread, Error> {
+        let count = (end.saturating_sub(start) as f64 / 1000.0 * ids_per_sec).round() as u64;
+        self.spread(start, end, count)
+    }
+}
+
+/// Ids spread over a time range, see [`BackfillFlaken::spread`]
+#[derive(Debug)]
+pub struct Spread {
+    backfill: BackfillFlaken,
+    start: u64,
+    end: u64,
+    count: u64,
+    emitted: u64,
+    rng: Option<Rng>,
+}
+
+impl Spread {
+    /// Pick each id's millisecond at random instead of evenly
+    ///
+    /// Ids are then no longer generated in order.
+    pub fn random(mut self) -> Spread {
+        self.rng = Some(Rng::new());
+        self
+    }
+
+    /// The backfill generator, with the sequence values used so far
+    pub fn into_inner(self) -> BackfillFlaken {
+        self.backfill
+    }
+}
+
+impl Iterator for Spread {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.emitted == self.count {
+            return None;
+        }
+        let span = self.end - self.start;
+        let mut ts = match self.rng {
+            Some(ref mut rng) => self.start + rng.below(span),
+            None => self.start + (u128::from(self.emitted) * u128::from(span) / u128::from(self.count)) as u64,
+        };
+        self.emitted += 1;
+        // The capacity checked up front guarantees a free millisecond
+        loop {
+            match self.backfill.next_at(ts) {
+                Ok(id) => return Some(id),
+                Err(_) => ts = if ts + 1 == self.end { self.start } else { ts + 1 },
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = (self.count - self.emitted) as usize;
+        (left, Some(left))
+    }
+}
+
+#[test]
+fn test_backfill_exhausts() {
+    let mut backfill = BackfillFlaken::new(Flaken::default().bitwidths(40, 22));
+    let ts = backfill.flaken().layout.epoch + 1000;
+    let ids: Vec<u64> = (0..4).map(|_| backfill.next_at(ts).unwrap()).collect();
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    assert!(matches!(backfill.next_at(ts), Err(Error::SequenceExhausted(t)) if t == ts));
+    assert!(backfill.next_at(ts + 1).is_ok());
+    backfill.forget_before(ts + 1);
+    assert!(backfill.next_at(ts).is_ok());
+    assert!(matches!(backfill.next_at(0), Err(Error::OutOfRange(0))));
+    assert!(backfill.next_at(ts + (1 << 40)).is_err());
+}
+
+#[test]
+fn test_spread() {
+    let backfill = BackfillFlaken::new(Flaken::default().bitwidths(40, 22));
+    let start = backfill.flaken().layout.epoch + 1000;
+    let ids: Vec<u64> = backfill.spread(start, start + 10, 40).unwrap().collect();
+    assert_eq!(ids.len(), 40);
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+
+    let backfill = BackfillFlaken::new(Flaken::default().bitwidths(40, 22));
+    assert!(matches!(backfill.spread(start, start + 10, 41), Err(Error::SequenceExhausted(_))));
+
+    let backfill = BackfillFlaken::new(Flaken::default().bitwidths(40, 22));
+    let spread = backfill.spread_at_rate(start, start + 1000, 1000.0).unwrap().random();
+    let mut ids: Vec<u64> = spread.collect();
+    assert_eq!(ids.len(), 1000);
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 1000);
+}
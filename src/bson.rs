@@ -0,0 +1,94 @@
+//! BSON conversions for ids, behind the `bson` feature
+//!
+//! Ids convert to and from BSON `Int64`, or 8 byte big endian `Binary` which
+//! keeps their order as unsigned numbers.
+
+use bson::spec::BinarySubtype;
+use bson::{doc, Binary, Bson, Document};
+
+use crate::format::ParseError;
+use crate::{Error, Flaken, Id};
+
+impl<T> TryFrom<Id<T>> for Bson {
+    type Error = Error;
+
+    /// Fails when the top bit is set rather than storing a negative number
+    fn try_from(id: Id<T>) -> Result<Bson, Error> {
+        id.to_signed().map(Bson::Int64)
+    }
+}
+
+impl<T> From<Id<T>> for Binary {
+    fn from(id: Id<T>) -> Binary {
+        Binary {
+            subtype: BinarySubtype::Generic,
+            bytes: id.get().to_be_bytes().to_vec(),
+        }
+    }
+}
+
+impl<T> TryFrom<Bson> for Id<T> {
+    type Error = Error;
+
+    /// Accepts non-negative `Int64` and 8 byte `Binary` values
+    fn try_from(value: Bson) -> Result<Id<T>, Error> {
+        match value {
+            Bson::Int64(id) => Id::from_signed(id),
+            Bson::Binary(Binary { ref bytes, .. }) if bytes.len() == 8 => {
+                let mut be = [0; 8];
+                be.copy_from_slice(bytes);
+                Ok(Id::new(u64::from_be_bytes(be)))
+            }
+            _ => Err(Error::Parse(ParseError::Malformed)),
+        }
+    }
+}
+
+impl Flaken {
+    /// A query filter matching `Int64` ids in `field` created from
+    /// `start_ms` up to but excluding `end_ms`, in milliseconds since the
+    /// unix epoch
+    ///
+    /// Like querying by an ObjectId made from a time, this turns a creation
+    /// time range into an id range, so is served by the index on the ids.
+    /// Fails with [`Error::OutOfRange`] for a bound past the latest
+    /// timestamp the layout can encode, and with [`Error::SignBit`] for one
+    /// whose ids do not fit an `Int64`, rather than wrapping either.
+    pub fn bson_created_between(&self, field: &str, start_ms: u64, end_ms: u64) -> Result<Document, Error> {
+        let bound = |ts: u64| {
+            let ts = ts.max(self.layout.epoch);
+            self.layout_at(ts).try_encode(ts, 0, 0).and_then(|id| Id::<()>::new(id).to_signed())
+        };
+        let (start, end) = (bound(start_ms)?, bound(end_ms)?);
+        Ok(doc! { field: { "$gte": start, "$lt": end } })
+    }
+}
+
+#[test]
+fn test_bson_round_trip() {
+    let id: Id = Id::new(0x0102_0304_0506_0708);
+    assert_eq!(Bson::try_from(id).unwrap(), Bson::Int64(0x0102_0304_0506_0708));
+    assert_eq!(Id::<()>::try_from(Bson::try_from(id).unwrap()).unwrap(), id);
+    assert_eq!(Id::<()>::try_from(Bson::Binary(Binary::from(id))).unwrap(), id);
+    assert!(Bson::try_from(Id::<()>::new(1 << 63)).is_err());
+    assert!(Id::<()>::try_from(Bson::Int64(-1)).is_err());
+    assert!(Id::<()>::try_from(Bson::Int32(1)).is_err());
+}
+
+#[test]
+fn test_bson_created_between() {
+    let mut flake = Flaken::default();
+    let id = flake.next();
+    let (ts, _, _) = flake.decode(id);
+    let filter = flake.bson_created_between("_id", ts, ts + 1).unwrap();
+    let range = filter.get_document("_id").unwrap();
+    assert!(range.get_i64("$gte").unwrap() <= id as i64);
+    assert!(range.get_i64("$lt").unwrap() > id as i64);
+
+    // Bounds past the layout fail instead of wrapping around
+    let flake = Flaken::default().bitwidths(20, 10);
+    let past = flake.layout.max_ts() + 1;
+    assert!(matches!(flake.bson_created_between("_id", ts, past), Err(Error::OutOfRange(_))));
+    let flake = Flaken::default();
+    assert!(matches!(flake.bson_created_between("_id", ts, flake.layout.max_ts()), Err(Error::SignBit(_))));
+}
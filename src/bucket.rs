@@ -0,0 +1,52 @@
+//! Spreading ids evenly over buckets, such as queues or partitions
+//!
+//! The low bits of snowflakes are the sequence, which is mostly small
+//! numbers, so the raw id modulo a bucket count favors the first buckets.
+//! [`bucket_of`] hashes ids first with the SplitMix64 finalizer, then picks a
+//! bucket with Lamping and Veach's jump consistent hash. Both are fixed here
+//! and will never change, so buckets can be recomputed in any language, and
+//! growing from `n` to `n + 1` buckets only moves a `1 / (n + 1)` share of
+//! ids, all to the new bucket.
+
+use crate::rand::mix;
+
+/// The bucket of `buckets` an id belongs to
+///
+/// ```
+/// let buckets = 16;
+/// let bucket = flaken::bucket_of(1234567890, buckets);
+/// assert!(bucket < buckets);
+/// assert_eq!(bucket, flaken::bucket_of(1234567890, buckets));
+/// ```
+pub fn bucket_of(id: u64, buckets: u32) -> u32 {
+    assert!(buckets > 0, "ids need at least one bucket");
+    let mut key = mix(id);
+    // Jump consistent hash, from "A Fast, Minimal Memory, Consistent Hash Algorithm"
+    let (mut bucket, mut next) = (-1i64, 0i64);
+    while next < i64::from(buckets) {
+        bucket = next;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        next = ((bucket + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    bucket as u32
+}
+
+#[test]
+fn test_bucket_of() {
+    use crate::Flaken;
+
+    let flake = Flaken::default();
+    let ids: Vec<u64> = (0..64_000).map(|i| flake.encode(flake.start_ts + i / 64, 0, i % 64)).collect();
+    let mut counts = [0; 10];
+    for &id in &ids {
+        counts[bucket_of(id, 10) as usize] += 1;
+    }
+    assert!(counts.iter().all(|&count| (5_800..7_000).contains(&count)));
+    // Growing to 11 buckets only moves ids to the new bucket
+    for &id in &ids {
+        let grown = bucket_of(id, 11);
+        assert!(grown == bucket_of(id, 10) || grown == 10);
+    }
+    // Fixed forever
+    assert_eq!([bucket_of(0, 1000), bucket_of(1, 1000), bucket_of(u64::MAX, 1000)], [0, 120, 222]);
+}
@@ -0,0 +1,367 @@
+//! Monotonic time sources for generators
+//!
+//! A generator reads the wall clock once when it is built and afterwards
+//! only measures the time elapsed since then with a [`Clock`], so changes to
+//! the wall clock do not affect id generation.
+//!
+//! Reading the clock on every generated id costs a vDSO call or worse. When
+//! that matters a [`CoarseClock`] shared by any number of generators keeps
+//! the current time in an atomic, updated by a background thread.
+//!
+//! With the `quanta` feature a [`QuantaClock`] reads the CPU's time stamp
+//! counter directly where it is reliable, which costs less than the call
+//! into the vDSO and needs no background thread.
+//!
+//! With the `coarsetime` feature a [`CoarsetimeClock`] reads the kernel's
+//! coarse monotonic clock, as cheap as the atomic of a [`CoarseClock`] but
+//! without a thread, at a resolution of the scheduler tick, usually 1 to 4
+//! milliseconds.
+//!
+//! A [`WallClock`] instead reads the wall clock on every call, like classic
+//! snowflake implementations, so ids stay aligned with wall time across NTP
+//! steps. It never goes back, a wall clock stepping backward stalls it
+//! until the wall clock has caught up again.
+//!
+//! ```
+//! use std::time::Duration;
+//! use flaken::clock::CoarseClock;
+//! use flaken::Flaken;
+//!
+//! let clock = CoarseClock::start(Duration::from_micros(250));
+//! let mut flake0 = Flaken::default().node(0).clock(clock.clone());
+//! let mut flake1 = Flaken::default().node(1).clock(clock);
+//! assert_ne!(flake0.next(), flake1.next());
+//! ```
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How often the wall clock is compared to a generator's time
+pub(crate) const CHECK_INTERVAL_MS: u64 = 1000;
+
+/// A monotonic source of time
+pub trait Clock: Debug + Send + Sync {
+    /// Time elapsed since an arbitrary but fixed origin, never decreasing
+    fn now(&self) -> Duration;
+}
+
+/// Clock reading `Instant::now` on every call
+#[derive(Clone, Copy, Debug)]
+pub struct MonotonicClock {
+    origin: Instant,
+}
+
+impl MonotonicClock {
+    /// Build a clock with the current instant as its origin
+    pub fn new() -> MonotonicClock {
+        MonotonicClock { origin: Instant::now() }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> MonotonicClock {
+        MonotonicClock::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// Clock reading `tokio::time::Instant`, behind the `tokio` feature
+///
+/// Under `tokio::time::pause` in tests the clock stands still until time is
+/// advanced, so generators using it, and the waits of
+/// [`Flaken::next_async`](../struct.Flaken.html#method.next_async), follow
+/// the test's time instead of the operating system's.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Copy, Debug)]
+pub struct TokioClock {
+    origin: ::tokio::time::Instant,
+}
+
+#[cfg(feature = "tokio")]
+impl TokioClock {
+    /// Build a clock with the current instant as its origin
+    pub fn new() -> TokioClock {
+        TokioClock { origin: ::tokio::time::Instant::now() }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Default for TokioClock {
+    fn default() -> TokioClock {
+        TokioClock::new()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Clock for TokioClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// Clock reading the CPU's time stamp counter through `quanta`, behind the
+/// `quanta` feature
+///
+/// On x86 with an invariant TSC a read is a single instruction plus a
+/// scaling calibrated when the clock is built, elsewhere `quanta` falls back
+/// to the operating system's monotonic clock. Clones share the origin.
+#[cfg(feature = "quanta")]
+#[derive(Clone, Debug)]
+pub struct QuantaClock {
+    clock: ::quanta::Clock,
+    origin: u64,
+}
+
+#[cfg(feature = "quanta")]
+impl QuantaClock {
+    /// Build a clock with the current instant as its origin
+    pub fn new() -> QuantaClock {
+        let clock = ::quanta::Clock::new();
+        let origin = clock.raw();
+        QuantaClock { clock, origin }
+    }
+}
+
+#[cfg(feature = "quanta")]
+impl Default for QuantaClock {
+    fn default() -> QuantaClock {
+        QuantaClock::new()
+    }
+}
+
+#[cfg(feature = "quanta")]
+impl Clock for QuantaClock {
+    fn now(&self) -> Duration {
+        self.clock.delta(self.origin, self.clock.raw())
+    }
+}
+
+/// Clock reading `CLOCK_MONOTONIC_COARSE` through `coarsetime`, behind the
+/// `coarsetime` feature
+///
+/// Time advances in steps of the kernel's scheduler tick, so a generator
+/// using it fills a millisecond's sequence values for as long as a step
+/// lasts and timestamps lag by up to one step.
+#[cfg(feature = "coarsetime")]
+#[derive(Clone, Copy, Debug)]
+pub struct CoarsetimeClock {
+    origin: ::coarsetime::Instant,
+}
+
+#[cfg(feature = "coarsetime")]
+impl CoarsetimeClock {
+    /// Build a clock with the current instant as its origin
+    pub fn new() -> CoarsetimeClock {
+        CoarsetimeClock { origin: ::coarsetime::Instant::now() }
+    }
+}
+
+#[cfg(feature = "coarsetime")]
+impl Default for CoarsetimeClock {
+    fn default() -> CoarsetimeClock {
+        CoarsetimeClock::new()
+    }
+}
+
+#[cfg(feature = "coarsetime")]
+impl Clock for CoarsetimeClock {
+    fn now(&self) -> Duration {
+        ::coarsetime::Instant::now().duration_since(self.origin).into()
+    }
+}
+
+#[derive(Debug)]
+struct Coarse {
+    origin: Instant,
+    nanos: AtomicU64,
+}
+
+/// Clock reading an atomic kept up to date by a background thread
+///
+/// Reads are a single relaxed atomic load, at the cost of the time lagging
+/// by up to the resolution the updater thread runs at. The updater thread
+/// stops once every clone of the clock has been dropped.
+#[derive(Clone, Debug)]
+pub struct CoarseClock {
+    inner: Arc<Coarse>,
+}
+
+impl CoarseClock {
+    /// Start a clock updated every `resolution`
+    pub fn start(resolution: Duration) -> CoarseClock {
+        let inner = Arc::new(Coarse {
+            origin: Instant::now(),
+            nanos: AtomicU64::new(0),
+        });
+        let weak = Arc::downgrade(&inner);
+        thread::Builder::new()
+            .name("flaken-coarse-clock".to_string())
+            .spawn(move || update(weak, resolution))
+            .expect("failed to spawn flaken coarse clock thread");
+        CoarseClock { inner }
+    }
+}
+
+impl Clock for CoarseClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.inner.nanos.load(Ordering::Relaxed))
+    }
+}
+
+/// Clock reading `SystemTime::now` on every call, never going below the
+/// latest time it returned
+///
+/// Its origin is the unix epoch, so a generator using it follows the wall
+/// clock rather than the time elapsed since it was built.
+#[derive(Debug, Default)]
+pub struct WallClock {
+    /// Nanoseconds since the unix epoch of the latest time returned
+    latest: AtomicU64,
+}
+
+impl WallClock {
+    /// Build a clock reading the wall clock
+    pub fn new() -> WallClock {
+        WallClock::default()
+    }
+}
+
+impl Clock for WallClock {
+    fn now(&self) -> Duration {
+        let wall = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+        let latest = self.latest.fetch_max(wall, Ordering::AcqRel);
+        Duration::from_nanos(latest.max(wall))
+    }
+}
+
+/// Clock for tests, set by hand and optionally advancing a millisecond every
+/// so many reads
+///
+/// Clones share the time, so a test keeps one to move the clock of a
+/// generator it handed the other to.
+#[cfg(test)]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ManualClock {
+    nanos: Arc<AtomicU64>,
+    reads: Arc<AtomicU64>,
+    reads_per_ms: Arc<AtomicU64>,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    /// A clock standing still at its origin until set
+    pub(crate) fn new() -> ManualClock {
+        ManualClock::default()
+    }
+
+    /// A clock advancing one millisecond every `reads_per_ms` reads
+    pub(crate) fn stepping(reads_per_ms: u64) -> ManualClock {
+        let clock = ManualClock::default();
+        clock.set_reads_per_ms(reads_per_ms);
+        clock
+    }
+
+    /// Set the time, not counting the milliseconds stepped by reads
+    pub(crate) fn set(&self, now: Duration) {
+        self.nanos.store(now.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Move the time forward
+    pub(crate) fn advance(&self, by: Duration) {
+        self.nanos.fetch_add(by.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Set how many reads advance the clock by a millisecond, 0 for none
+    pub(crate) fn set_reads_per_ms(&self, reads_per_ms: u64) {
+        self.reads_per_ms.store(reads_per_ms, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        let reads = self.reads.fetch_add(1, Ordering::Relaxed);
+        let stepped = match self.reads_per_ms.load(Ordering::Relaxed) {
+            0 => 0,
+            reads_per_ms => reads / reads_per_ms,
+        };
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed)) + Duration::from_millis(stepped)
+    }
+}
+
+fn update(clock: Weak<Coarse>, resolution: Duration) {
+    while let Some(clock) = clock.upgrade() {
+        let nanos = clock.origin.elapsed().as_nanos() as u64;
+        clock.nanos.fetch_max(nanos, Ordering::Relaxed);
+        drop(clock);
+        thread::sleep(resolution);
+    }
+}
+
+#[test]
+fn test_coarse_clock() {
+    let clock = CoarseClock::start(Duration::from_micros(100));
+    let t0 = clock.now();
+    thread::sleep(Duration::from_millis(5));
+    let t1 = clock.now();
+    assert!(t1 > t0);
+    assert!(t1 - t0 >= Duration::from_millis(4));
+    let weak = Arc::downgrade(&clock.inner);
+    drop(clock);
+    thread::sleep(Duration::from_millis(5));
+    assert!(weak.upgrade().is_none());
+}
+
+#[test]
+fn test_wall_clock() {
+    let clock = WallClock::new();
+    let wall = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    assert!(clock.now() >= wall);
+    // The wall clock stepping back an hour is not followed
+    let ahead = clock.now() + Duration::from_secs(3600);
+    clock.latest.store(ahead.as_nanos() as u64, Ordering::Relaxed);
+    assert_eq!(clock.now(), ahead);
+
+    let mut flake = crate::Flaken::default().wall_clock();
+    let id = flake.next();
+    let (ts, _, _) = flake.decode(id);
+    let wall = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    assert!(ts.abs_diff(wall) <= 2);
+}
+
+#[cfg(all(test, feature = "quanta"))]
+#[test]
+fn test_quanta_clock() {
+    let clock = QuantaClock::new();
+    let t0 = clock.now();
+    thread::sleep(Duration::from_millis(5));
+    let t1 = clock.now();
+    assert!(t1 - t0 >= Duration::from_millis(4));
+
+    let mut flake = crate::Flaken::default().bitwidths(40, 16).clock(clock);
+    let ids: Vec<u64> = (0..10000).map(|_| flake.next()).collect();
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+}
+
+#[cfg(all(test, feature = "coarsetime"))]
+#[test]
+fn test_coarsetime_clock() {
+    let clock = CoarsetimeClock::new();
+    let t0 = clock.now();
+    thread::sleep(Duration::from_millis(20));
+    let t1 = clock.now();
+    // The coarse clock may lag by a scheduler tick
+    assert!(t1 - t0 >= Duration::from_millis(10));
+
+    let mut flake = crate::Flaken::default().bitwidths(40, 10).clock(clock);
+    let ids: Vec<u64> = (0..10000).map(|_| flake.next()).collect();
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+}
@@ -0,0 +1,191 @@
+//! Checking two generators lay out ids the same way
+//!
+//! Services exchanging ids decode each other's, which only works when both
+//! use the same epoch, tick, and field widths. Comparing a generator with
+//! one built from another service's published configuration at startup
+//! catches a mismatch before any id is misread.
+//!
+//! ```
+//! use flaken::{Flaken, LayoutField};
+//!
+//! let ours = Flaken::default();
+//! let theirs = Flaken::default().bitwidths(41, 10);
+//! let report = ours.compatible_with(&theirs);
+//! assert!(!report.is_compatible());
+//! assert_eq!(report.mismatches[0].field, LayoutField::TimestampBits);
+//! ```
+
+use std::fmt;
+
+use crate::layout::field_max;
+use crate::Flaken;
+
+/// A property of a layout
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LayoutField {
+    /// The epoch, in milliseconds since the unix epoch
+    Epoch,
+    /// Milliseconds per unit of the timestamp field
+    Tick,
+    /// Bits reserved at the top of ids
+    ReservedBits,
+    /// Bits of the version field
+    VersionBits,
+    /// Bits of the timestamp field
+    TimestampBits,
+    /// Bits of the node field
+    NodeBits,
+    /// Bits of the sequence field
+    SequenceBits,
+    /// The latest timestamp the layout can encode
+    MaxTimestamp,
+    /// The largest node id
+    MaxNode,
+    /// The largest sequence value
+    MaxSequence,
+    /// Epochs in the epoch table, 0 without one
+    EpochCount,
+    /// The epoch ids of a version count from, in an epoch table
+    VersionEpoch(u64),
+}
+
+impl fmt::Display for LayoutField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let LayoutField::VersionEpoch(version) = *self {
+            return write!(f, "epoch of version {}", version);
+        }
+        let name = match *self {
+            LayoutField::Epoch => "epoch",
+            LayoutField::Tick => "tick",
+            LayoutField::ReservedBits => "reserved bits",
+            LayoutField::VersionBits => "version bits",
+            LayoutField::TimestampBits => "timestamp bits",
+            LayoutField::NodeBits => "node bits",
+            LayoutField::SequenceBits => "sequence bits",
+            LayoutField::MaxTimestamp => "latest timestamp",
+            LayoutField::MaxNode => "largest node id",
+            LayoutField::MaxSequence => "largest sequence value",
+            LayoutField::EpochCount => "epoch count",
+            LayoutField::VersionEpoch(_) => unreachable!(),
+        };
+        f.write_str(name)
+    }
+}
+
+/// A property two layouts differ in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Mismatch {
+    /// The property
+    pub field: LayoutField,
+    /// Its value in the layout compared
+    pub ours: u64,
+    /// Its value in the layout compared with
+    pub theirs: u64,
+}
+
+/// How two generators' layouts differ, see [`Flaken::compatible_with`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompatReport {
+    /// Every property the layouts differ in, widths before the value
+    /// ranges following from them
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl CompatReport {
+    /// Whether ids of either generator decode the same with the other
+    pub fn is_compatible(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for CompatReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.mismatches.is_empty() {
+            return write!(f, "layouts are compatible");
+        }
+        for (i, mismatch) in self.mismatches.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {} != {}", mismatch.field, mismatch.ours, mismatch.theirs)?;
+        }
+        Ok(())
+    }
+}
+
+impl Flaken {
+    /// Compare this generator's layout with `other`'s
+    ///
+    /// Versions and node ids are not compared, generators of several
+    /// versions or nodes can share a layout. With epoch tables the epochs of
+    /// every version are, the epoch and latest timestamp being those of the
+    /// first version whichever epoch generation has moved on to.
+    pub fn compatible_with(&self, other: &Flaken) -> CompatReport {
+        let (ours, theirs) = (&self.layout_at(0), &other.layout_at(0));
+        let mut fields = vec![
+            (LayoutField::Epoch, ours.epoch, theirs.epoch),
+            (LayoutField::Tick, ours.tick_ms, theirs.tick_ms),
+            (LayoutField::ReservedBits, ours.reserved_bits, theirs.reserved_bits),
+            (LayoutField::VersionBits, ours.version_bits, theirs.version_bits),
+            (LayoutField::TimestampBits, ours.ts_bits, theirs.ts_bits),
+            (LayoutField::NodeBits, ours.node_bits, theirs.node_bits),
+            (LayoutField::SequenceBits, ours.seq_bits, theirs.seq_bits),
+            (LayoutField::MaxTimestamp, ours.max_ts(), theirs.max_ts()),
+            (LayoutField::MaxNode, field_max(ours.node_bits), field_max(theirs.node_bits)),
+            (LayoutField::MaxSequence, ours.max_seq(), theirs.max_seq()),
+        ];
+        let (our_epochs, their_epochs) = (self.epochs.as_deref().unwrap_or(&[]), other.epochs.as_deref().unwrap_or(&[]));
+        fields.push((LayoutField::EpochCount, our_epochs.len() as u64, their_epochs.len() as u64));
+        for (version, (&ours, &theirs)) in our_epochs.iter().zip(their_epochs).enumerate().skip(1) {
+            fields.push((LayoutField::VersionEpoch(version as u64), ours, theirs));
+        }
+        CompatReport {
+            mismatches: fields
+                .iter()
+                .filter(|&&(_, ours, theirs)| ours != theirs)
+                .map(|&(field, ours, theirs)| Mismatch { field, ours, theirs })
+                .collect(),
+        }
+    }
+}
+
+#[test]
+fn test_compatible_with() {
+    let ours = Flaken::default().node(1);
+    assert!(ours.compatible_with(&Flaken::default().node(2)).is_compatible());
+    let report = ours.compatible_with(&Flaken::default().epoch(0).bitwidths(42, 11));
+    let fields: Vec<LayoutField> = report.mismatches.iter().map(|m| m.field).collect();
+    assert_eq!(
+        fields,
+        [
+            LayoutField::Epoch,
+            LayoutField::NodeBits,
+            LayoutField::SequenceBits,
+            LayoutField::MaxTimestamp,
+            LayoutField::MaxNode,
+            LayoutField::MaxSequence,
+        ]
+    );
+    assert!(report.to_string().starts_with("epoch 1356998400000 != 0, node bits 10 != 11"));
+}
+
+#[test]
+fn test_compatible_epoch_tables() {
+    let table = [1_400_000_000_000, 1_500_000_000_000u64];
+    let ours = Flaken::default().version_bits(2).epoch_table(&table);
+    let mut theirs = Flaken::default().version_bits(2).epoch_table(&table);
+    assert!(ours.compatible_with(&theirs).is_compatible());
+    // Moving on to the next epoch keeps the layout the same
+    theirs.bump_epoch(u64::MAX);
+    assert!(ours.compatible_with(&theirs).is_compatible());
+
+    let theirs = Flaken::default().version_bits(2).epoch_table(&[1_400_000_000_000, 1_600_000_000_000u64]);
+    let report = ours.compatible_with(&theirs);
+    assert_eq!(report.mismatches.len(), 1);
+    assert_eq!(report.mismatches[0].field, LayoutField::VersionEpoch(1));
+    assert_eq!(report.to_string(), "epoch of version 1 1500000000000 != 1600000000000");
+
+    let report = ours.compatible_with(&Flaken::default().version_bits(2));
+    let fields: Vec<LayoutField> = report.mismatches.iter().map(|m| m.field).collect();
+    assert_eq!(fields, [LayoutField::Epoch, LayoutField::MaxTimestamp, LayoutField::EpochCount]);
+}
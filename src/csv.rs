@@ -0,0 +1,132 @@
+//! Reading and writing lists of ids as CSV, behind the `csv` feature
+//!
+//! ```
+//! use flaken::Flaken;
+//!
+//! let mut flake = Flaken::default().node(7);
+//! let ids: Vec<u64> = (0..3).map(|_| flake.next()).collect();
+//!
+//! let mut out = Vec::new();
+//! flaken::csv::write_decoded(&mut out, &flake, ids.iter().cloned()).unwrap();
+//! let read: Vec<u64> = flaken::csv::read_ids(&out[..], "id").unwrap()
+//!     .collect::<Result<_, _>>()
+//!     .unwrap();
+//! assert_eq!(read, ids);
+//! ```
+
+use std::fmt;
+use std::io::{Read, Write};
+
+use crate::decoded::{DecodedId, Rfc3339};
+use crate::format::{encode_str_into, Format, Radix};
+use crate::{Error, Flaken};
+
+/// Ids streamed from a column of a CSV file with a header row
+pub struct IdReader<R> {
+    records: ::csv::StringRecordsIntoIter<R>,
+    column: usize,
+}
+
+impl<R> fmt::Debug for IdReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IdReader").field("column", &self.column).finish()
+    }
+}
+
+/// Read the decimal ids in the column named `column`
+///
+/// Fails with [`Error::MissingColumn`] when the header has no such column.
+pub fn read_ids<R: Read>(reader: R, column: &str) -> Result<IdReader<R>, Error> {
+    let mut reader = ::csv::Reader::from_reader(reader);
+    let column = reader
+        .headers()?
+        .iter()
+        .position(|name| name == column)
+        .ok_or_else(|| Error::MissingColumn(column.to_string()))?;
+    Ok(IdReader {
+        records: reader.into_records(),
+        column,
+    })
+}
+
+impl<R: Read> IdReader<R> {
+    /// Decode each id read as it is read
+    pub fn decoded<'a>(self, flake: &'a Flaken) -> impl Iterator<Item = Result<DecodedId, Error>> + 'a
+    where
+        R: 'a,
+    {
+        self.map(move |id| id.map(|id| flake.decode_id(id)))
+    }
+}
+
+impl<R: Read> Iterator for IdReader<R> {
+    type Item = Result<u64, Error>;
+
+    fn next(&mut self) -> Option<Result<u64, Error>> {
+        let record = match self.records.next()? {
+            Ok(record) => record,
+            Err(err) => return Some(Err(err.into())),
+        };
+        let field = record.get(self.column).unwrap_or("");
+        Some(Format::new(Radix::Decimal).parse(field.trim()).map_err(Error::from))
+    }
+}
+
+/// Write ids in decimal below an `id` header
+pub fn write_ids<W: Write, I: IntoIterator<Item = u64>>(writer: W, ids: I) -> Result<(), Error> {
+    let mut writer = ::csv::Writer::from_writer(writer);
+    writer.write_record(["id"])?;
+    let mut buf = [0; 20];
+    for id in ids {
+        writer.write_record([encode_str_into(id, &mut buf, Radix::Decimal)])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write ids along with their decoded fields, below an
+/// `id,timestamp,node,sequence` header with RFC 3339 timestamps
+pub fn write_decoded<W: Write, I: IntoIterator<Item = u64>>(writer: W, flake: &Flaken, ids: I) -> Result<(), Error> {
+    let mut writer = ::csv::Writer::from_writer(writer);
+    writer.write_record(["id", "timestamp", "node", "sequence"])?;
+    for id in ids {
+        let decoded = flake.decode_id(id);
+        writer.write_record([
+            id.to_string(),
+            Rfc3339(decoded.ts).to_string(),
+            decoded.node.to_string(),
+            decoded.seq.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[test]
+fn test_read_column() {
+    let input = "name,user_id\nada,17\nbob, 4096 \n";
+    let ids: Vec<u64> = read_ids(input.as_bytes(), "user_id").unwrap().map(Result::unwrap).collect();
+    assert_eq!(ids, vec![17, 4096]);
+    assert!(matches!(read_ids(input.as_bytes(), "id"), Err(Error::MissingColumn(_))));
+    let mut bad = read_ids("id\nx1\n".as_bytes(), "id").unwrap();
+    assert!(matches!(bad.next(), Some(Err(Error::Parse(_)))));
+}
+
+#[test]
+fn test_write_decoded() {
+    let flake = Flaken::default();
+    let id = flake.encode(1717243200123, 7, 42);
+    let mut out = Vec::new();
+    write_decoded(&mut out, &flake, vec![id]).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        format!("id,timestamp,node,sequence\n{},2024-06-01T12:00:00.123Z,7,42\n", id)
+    );
+    let flake = Flaken::default();
+    let decoded: Vec<DecodedId> = read_ids(format!("id\n{}\n", id).as_bytes(), "id")
+        .unwrap()
+        .decoded(&flake)
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(decoded, vec![flake.decode_id(id)]);
+}
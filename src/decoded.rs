@@ -0,0 +1,277 @@
+//! Decoded ids for logging and debugging
+
+use std::fmt;
+
+use crate::format::{encode_str_into, Radix};
+use crate::{AsMillis, Epoch, Flaken};
+
+/// An id along with the fields decoded from it
+///
+/// `{}` formats the id in decimal and `{:#}` the fields, for log lines and
+/// error messages. With the `serde` feature it serializes as a map of `id`,
+/// a decimal string as JSON numbers lose precision above 2^53, `timestamp`
+/// in milliseconds since the unix epoch, `node`, and `sequence`, see
+/// [`rfc3339`](#method.rfc3339) for an RFC 3339 timestamp instead.
+///
+/// ```
+/// use flaken::Flaken;
+///
+/// let flake = Flaken::default();
+/// let decoded = flake.decode_id(flake.encode(1717243200123, 7, 42));
+/// assert_eq!(decoded.to_string(), decoded.id.to_string());
+/// assert_eq!(format!("{:#}", decoded), "ts=2024-06-01T12:00:00.123Z node=7 seq=42");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DecodedId {
+    /// The id
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::string::serialize"))]
+    pub id: u64,
+    /// Milliseconds since the unix epoch
+    #[cfg_attr(feature = "serde", serde(rename = "timestamp"))]
+    pub ts: u64,
+    /// The node id
+    pub node: u64,
+    /// The sequence value
+    #[cfg_attr(feature = "serde", serde(rename = "sequence"))]
+    pub seq: u64,
+}
+
+impl DecodedId {
+    /// Serialize with the timestamp as an RFC 3339 UTC string, such as
+    /// `2024-06-01T12:00:00.123Z`
+    #[cfg(feature = "serde")]
+    pub fn rfc3339(self) -> Rfc3339DecodedId {
+        Rfc3339DecodedId(self)
+    }
+}
+
+/// A decoded id serializing its timestamp as an RFC 3339 string, see
+/// [`DecodedId::rfc3339`]
+#[cfg(feature = "serde")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Rfc3339DecodedId(pub DecodedId);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Rfc3339DecodedId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut map = serializer.serialize_struct("DecodedId", 4)?;
+        map.serialize_field("id", &self.0.id.to_string())?;
+        map.serialize_field("timestamp", &Rfc3339(self.0.ts).to_string())?;
+        map.serialize_field("node", &self.0.node)?;
+        map.serialize_field("sequence", &self.0.seq)?;
+        map.end()
+    }
+}
+
+impl fmt::Display for DecodedId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "ts={} node={} seq={}", Rfc3339(self.ts), self.node, self.seq)
+        } else {
+            let mut buf = [0; 20];
+            f.pad_integral(true, "", encode_str_into(self.id, &mut buf, Radix::Decimal))
+        }
+    }
+}
+
+impl Flaken {
+    /// Decode an id into its fields, see [`decode`](#method.decode)
+    pub fn decode_id(&self, id: u64) -> DecodedId {
+        let (ts, node, seq) = self.decode(id);
+        DecodedId { id, ts, node, seq }
+    }
+
+    /// Describe an id for people, such as `created 3m 12s ago on node 7,
+    /// 14th id that millisecond`
+    ///
+    /// Ages are relative to the generator's clock, in the two largest units
+    /// of days, hours, minutes, and seconds, or in milliseconds when under a
+    /// second. The format is fixed rather than localized, for support
+    /// tooling and command line inspection.
+    pub fn describe(&self, id: u64) -> String {
+        let (ts, node, seq) = self.decode(id);
+        let now = self.start_ts + self.elapsed().as_millis();
+        let age = if ts <= now {
+            format!("{} ago", Age(now - ts))
+        } else {
+            format!("{} from now", Age(ts - now))
+        };
+        let tick = match self.layout.tick_ms {
+            1 => "millisecond",
+            1000 => "second",
+            _ => "tick",
+        };
+        format!("created {} on node {}, {} id that {}", age, node, Ordinal(seq + 1), tick)
+    }
+
+    /// Decode an id against each of `epochs`, returning the epochs it could
+    /// have been generated with along with its fields decoded from them
+    ///
+    /// For tables holding ids from before and after a change of epoch. A
+    /// decoding is plausible unless its timestamp is more than the maximum
+    /// future skew past the generator's clock, so the later epochs of a
+    /// table tend to be ruled out for ids of the earlier ones. Otherwise the
+    /// layout is the generator's own.
+    pub fn decode_any<E: Into<Epoch> + Copy>(&self, id: u64, epochs: &[E]) -> Vec<(Epoch, DecodedId)> {
+        let now = self.start_ts + self.elapsed().as_millis();
+        let latest = now.saturating_add(self.max_skew.as_millis());
+        epochs
+            .iter()
+            .filter_map(|&epoch| {
+                let epoch = epoch.into();
+                let mut layout = self.layout;
+                layout.epoch = epoch.as_millis();
+                let (ts, node, seq) = layout.decode(id);
+                if ts <= latest {
+                    Some((epoch, DecodedId { id, ts, node, seq }))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Decode ids into `out` without allocating, see
+    /// [`decode_batch`](#method.decode_batch) for separate columns
+    ///
+    /// Both slices must have the same length.
+    pub fn decode_slice(&self, ids: &[u64], out: &mut [DecodedId]) {
+        assert_eq!(ids.len(), out.len());
+        let masks = self.layout.masks();
+        for (&id, out) in ids.iter().zip(out.iter_mut()) {
+            let (ts, node, seq) = match self.epochs {
+                Some(_) => self.decode(id),
+                None => masks.decode(id),
+            };
+            *out = DecodedId { id, ts, node, seq };
+        }
+    }
+}
+
+/// Milliseconds formatted in the two largest units of `1d 2h 3m 4s`
+struct Age(u64);
+
+impl fmt::Display for Age {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 < 1000 {
+            return write!(f, "{}ms", self.0);
+        }
+        let secs = self.0 / 1000;
+        let units = [(secs / 86_400, "d"), (secs / 3600 % 24, "h"), (secs / 60 % 60, "m"), (secs % 60, "s")];
+        let first = units.iter().position(|&(n, _)| n > 0).unwrap_or(3);
+        write!(f, "{}{}", units[first].0, units[first].1)?;
+        match units.get(first + 1) {
+            Some(&(n, unit)) if n > 0 => write!(f, " {}{}", n, unit),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A number with its English ordinal suffix, 1st, 2nd, 3rd, 4th
+struct Ordinal(u64);
+
+impl fmt::Display for Ordinal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let suffix = match (self.0 % 10, self.0 % 100) {
+            (_, 11..=13) => "th",
+            (1, _) => "st",
+            (2, _) => "nd",
+            (3, _) => "rd",
+            _ => "th",
+        };
+        write!(f, "{}{}", self.0, suffix)
+    }
+}
+
+/// Milliseconds since the unix epoch formatted as an RFC 3339 UTC timestamp
+pub(crate) struct Rfc3339(pub(crate) u64);
+
+impl fmt::Display for Rfc3339 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (days, ms) = (self.0 / 86_400_000, self.0 % 86_400_000);
+        // Civil date from days since 1970-01-01, by Howard Hinnant's algorithm
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let doe = z % 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + u64::from(month <= 2);
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year,
+            month,
+            day,
+            ms / 3_600_000,
+            ms / 60_000 % 60,
+            ms / 1000 % 60,
+            ms % 1000
+        )
+    }
+}
+
+#[test]
+fn test_rfc3339() {
+    assert_eq!(Rfc3339(0).to_string(), "1970-01-01T00:00:00.000Z");
+    assert_eq!(Rfc3339(951_782_400_000).to_string(), "2000-02-29T00:00:00.000Z");
+    assert_eq!(Rfc3339(1_356_998_399_999).to_string(), "2012-12-31T23:59:59.999Z");
+    assert_eq!(Rfc3339(4_107_542_400_000).to_string(), "2100-03-01T00:00:00.000Z");
+}
+
+#[cfg(all(test, feature = "serde"))]
+#[test]
+fn test_serialize() {
+    let decoded = DecodedId { id: 5, ts: 1717243200123, node: 7, seq: 42 };
+    assert_eq!(
+        serde_json::to_string(&decoded).unwrap(),
+        r#"{"id":"5","timestamp":1717243200123,"node":7,"sequence":42}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&decoded.rfc3339()).unwrap(),
+        r#"{"id":"5","timestamp":"2024-06-01T12:00:00.123Z","node":7,"sequence":42}"#
+    );
+}
+
+#[test]
+fn test_describe() {
+    let flake = Flaken::default();
+    let now = flake.start_ts + flake.elapsed().as_millis();
+    let id = flake.encode(now - 192_000, 7, 13);
+    assert!(flake.describe(id).starts_with("created 3m 1"));
+    assert!(flake.describe(id).ends_with("s ago on node 7, 14th id that millisecond"));
+    assert_eq!(Age(90_061_000).to_string(), "1d 1h");
+    assert_eq!(Age(3_600_000).to_string(), "1h");
+    assert_eq!(Age(999).to_string(), "999ms");
+    let ordinals: Vec<String> = [1, 2, 3, 4, 11, 12, 13, 21, 102, 111].iter().map(|&n| Ordinal(n).to_string()).collect();
+    assert_eq!(ordinals, ["1st", "2nd", "3rd", "4th", "11th", "12th", "13th", "21st", "102nd", "111th"]);
+    assert!(flake.describe(flake.encode(now + 5000, 0, 0)).contains("s from now on node 0, 1st id"));
+}
+
+#[test]
+fn test_decode_any() {
+    let flake = Flaken::default();
+    let old = Flaken::default().epoch(Epoch::Twitter2010);
+    let id = old.encode(1_600_000_000_000, 5, 6);
+    let decoded = flake.decode_any(id, &[Epoch::Twitter2010, Epoch::Flaken2013, Epoch::Custom(1_700_000_000_000)]);
+    // Counted from 2023 the id would be from the 2030s
+    assert_eq!(decoded.len(), 2);
+    assert_eq!(decoded[0], (Epoch::Twitter2010, DecodedId { id, ts: 1_600_000_000_000, node: 5, seq: 6 }));
+    assert_eq!(decoded[1].0, Epoch::Flaken2013);
+}
+
+#[test]
+fn test_decode_slice() {
+    let flake = Flaken::default().bitwidths(40, 11);
+    let ids: Vec<u64> = (0..100).map(|i| flake.encode(flake.start_ts + i, i % 7, i)).collect();
+    let mut out = vec![DecodedId { id: 0, ts: 0, node: 0, seq: 0 }; ids.len()];
+    flake.decode_slice(&ids, &mut out);
+    for (&id, decoded) in ids.iter().zip(&out) {
+        assert_eq!(*decoded, flake.decode_id(id));
+    }
+}
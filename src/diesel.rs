@@ -0,0 +1,59 @@
+//! Diesel column types for ids, behind the `diesel` feature
+//!
+//! Ids map to `BigInt` columns, as signed integers the way
+//! [`Id`](../struct.Id.html#databases) describes.
+
+use diesel::backend::Backend;
+use diesel::deserialize::{self, FromSql};
+use diesel::query_builder::bind_collector::RawBytesBindCollector;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::BigInt;
+
+use crate::Id;
+
+impl<DB, T> ToSql<BigInt, DB> for Id<T>
+where
+    for<'c> DB: Backend<BindCollector<'c> = RawBytesBindCollector<DB>>,
+    i64: ToSql<BigInt, DB>,
+    T: 'static,
+{
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DB>) -> serialize::Result {
+        let id = self.to_signed()?;
+        <i64 as ToSql<BigInt, DB>>::to_sql(&id, &mut out.reborrow())
+    }
+}
+
+impl<DB, T> FromSql<BigInt, DB> for Id<T>
+where
+    DB: Backend,
+    i64: FromSql<BigInt, DB>,
+{
+    fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Id<T>> {
+        Ok(Id::from_signed(<i64 as FromSql<BigInt, DB>>::from_sql(bytes)?)?)
+    }
+}
+
+#[test]
+fn test_diesel_bind() {
+    use diesel::dsl::sql;
+    use diesel::mysql::Mysql;
+    use diesel::pg::Pg;
+
+    let id: Id = Id::new(42);
+    let query = diesel::select(sql::<BigInt>("").bind::<BigInt, _>(id));
+    assert!(diesel::debug_query::<Pg, _>(&query).to_string().ends_with("binds: [Id(42)]"));
+    assert!(diesel::debug_query::<Mysql, _>(&query).to_string().ends_with("binds: [Id(42)]"));
+}
+
+#[test]
+fn test_diesel_sign_boundary() {
+    use diesel::mysql::Mysql;
+    use diesel::query_builder::BindCollector;
+
+    let id: Id = Id::new(i64::MAX as u64);
+    let mut collector = RawBytesBindCollector::<Mysql>::new();
+    collector.push_bound_value::<BigInt, _>(&id, &mut ()).unwrap();
+    assert_eq!(Id::<()>::from_signed(i64::MAX).unwrap(), id);
+    let top: Id = Id::new(1 << 63);
+    assert!(collector.push_bound_value::<BigInt, _>(&top, &mut ()).is_err());
+}
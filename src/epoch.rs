@@ -0,0 +1,180 @@
+//! Well known epochs, and tables of epochs selected by the version field
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::layout::{field_max, Layout};
+use crate::Flaken;
+
+/// The time ids count from
+///
+/// Builders accept either a variant or milliseconds since the unix epoch.
+///
+/// ```
+/// use flaken::{Epoch, Flaken};
+///
+/// let flake = Flaken::default().epoch(Epoch::Twitter2010);
+/// let id = flake.encode(Epoch::Twitter2010.as_millis() + 1, 0, 0);
+/// assert_eq!(id, 1 << 22);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Epoch {
+    /// 1970-01-01T00:00:00Z
+    Unix,
+    /// 2010-11-04T01:42:54.657Z, Twitter's snowflake epoch
+    Twitter2010,
+    /// 2015-01-01T00:00:00Z, Discord's snowflake epoch
+    Discord2015,
+    /// 2013-01-01T00:00:00Z, the default of flaken generators
+    Flaken2013,
+    /// Milliseconds since the unix epoch
+    Custom(u64),
+}
+
+impl Epoch {
+    /// Milliseconds since the unix epoch
+    pub fn as_millis(self) -> u64 {
+        match self {
+            Epoch::Unix => 0,
+            Epoch::Twitter2010 => 1288834974657,
+            Epoch::Discord2015 => 1420070400000,
+            Epoch::Flaken2013 => 1356998400000,
+            Epoch::Custom(ms) => ms,
+        }
+    }
+
+    /// The epoch as a point in time
+    pub fn as_datetime(self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.as_millis())
+    }
+}
+
+impl From<u64> for Epoch {
+    fn from(ms: u64) -> Epoch {
+        Epoch::Custom(ms)
+    }
+}
+
+impl From<Epoch> for u64 {
+    fn from(epoch: Epoch) -> u64 {
+        epoch.as_millis()
+    }
+}
+
+impl Flaken {
+    /// Select the epoch ids count from with the version field, the version
+    /// of an id being the index of its epoch in `epochs`
+    ///
+    /// Generation starts at the first epoch and moves on to the next once
+    /// the clock runs past the latest timestamp the current one can encode,
+    /// so a deployment outliving its timestamp bits bumps the version rather
+    /// than changing layout. Ids of a later epoch sort after those of
+    /// earlier ones. Encoding picks the version and epoch for the timestamp
+    /// given, whichever epoch generation is on. Decoding counts an id's timestamp from the epoch of its
+    /// version. The version bits must be set first and fit every index, and
+    /// the epochs must increase.
+    ///
+    /// ```
+    /// use flaken::{Epoch, Flaken};
+    ///
+    /// let flake = Flaken::default()
+    ///     .version_bits(2)
+    ///     .epoch_table(&[Epoch::Flaken2013, Epoch::Custom(1_900_000_000_000)]);
+    /// let id = flake.encode(1_400_000_000_000, 0, 0);
+    /// assert_eq!(flake.version_of(id), 0);
+    /// assert_eq!(flake.decode(id).0, 1_400_000_000_000);
+    /// ```
+    pub fn epoch_table<E: Into<Epoch> + Copy>(mut self, epochs: &[E]) -> Flaken {
+        let epochs: Vec<u64> = epochs.iter().map(|&epoch| epoch.into().as_millis()).collect();
+        assert!(!epochs.is_empty() && epochs.windows(2).all(|pair| pair[0] < pair[1]), "epochs must increase");
+        assert!(epochs.len() as u64 - 1 <= field_max(self.layout.version_bits), "version bits can not select every epoch");
+        self.layout.epoch = epochs[0];
+        self.layout.set_version(0);
+        self.epochs = Some(epochs.into());
+        self
+    }
+
+    /// Move on through the epoch table while `ts` is past the current
+    /// epoch's range, returning whether the epoch changed
+    pub(crate) fn bump_epoch(&mut self, ts: u64) -> bool {
+        let epochs = match self.epochs {
+            Some(ref epochs) => epochs.clone(),
+            None => return false,
+        };
+        let mut bumped = false;
+        while ts > self.layout.max_ts() {
+            let version = self.layout.version + 1;
+            match epochs.get(version as usize) {
+                Some(&epoch) if epoch <= ts => {
+                    trace_event!(info, node = self.node, version, epoch, "timestamp range used up, moving on to the next epoch");
+                    self.layout.epoch = epoch;
+                    self.layout.set_version(version);
+                    bumped = true;
+                }
+                _ => break,
+            }
+        }
+        bumped
+    }
+
+    /// The layout ids of timestamp `ts` are encoded with, with the version
+    /// and epoch the epoch table selects for it
+    ///
+    /// This is the epoch a generator reaches by moving on from the first one,
+    /// whichever epoch it is on itself.
+    pub(crate) fn layout_at(&self, ts: u64) -> Layout {
+        let mut layout = self.layout;
+        if let Some(ref epochs) = self.epochs {
+            layout.epoch = epochs[0];
+            layout.set_version(0);
+            for (version, &epoch) in epochs.iter().enumerate().skip(1) {
+                if ts <= layout.max_ts() || epoch > ts {
+                    break;
+                }
+                layout.epoch = epoch;
+                layout.set_version(version as u64);
+            }
+        }
+        layout
+    }
+
+    /// The layout `id` was generated with, with the epoch of its version
+    pub(crate) fn layout_of(&self, id: u64) -> Layout {
+        let mut layout = self.layout;
+        if let Some(ref epochs) = self.epochs {
+            if let Some(&epoch) = epochs.get(layout.version_of(id) as usize) {
+                layout.epoch = epoch;
+            }
+        }
+        layout
+    }
+}
+
+#[test]
+fn test_epochs() {
+    assert_eq!(Epoch::Discord2015.as_datetime(), UNIX_EPOCH + Duration::from_secs(1420070400));
+    assert_eq!(Epoch::from(12).as_millis(), 12);
+    assert_eq!(u64::from(Epoch::Flaken2013), crate::layout::Layout::new().epoch);
+}
+
+#[test]
+fn test_epoch_table() {
+    use crate::skew::wall_ms;
+
+    // 20 timestamp bits last about 17 minutes, the first epoch is used up
+    let first = wall_ms() - (1 << 20) - 10_000;
+    let second = wall_ms() - 5_000;
+    let mut flake = Flaken::default().version_bits(1).bitwidths(20, 10).epoch_table(&[first, second]);
+    let old = flake.encode(first + 5, 3, 4);
+    let id = flake.next();
+    assert_eq!(flake.version_of(id), 1);
+    assert!(flake.decode(id).0.abs_diff(wall_ms()) < 100);
+    assert!(id > old);
+    assert_eq!(flake.decode(old), (first + 5, 3, 4));
+
+    // Encoding after moving on still counts old timestamps from their epoch
+    assert_eq!(flake.encode(first + 5, 3, 4), old);
+    assert_eq!(flake.try_encode(first + 5, 3, 4).unwrap(), old);
+    let now = flake.encode(second + 5, 3, 4);
+    assert_eq!(flake.version_of(now), 1);
+    assert_eq!(flake.decode(now), (second + 5, 3, 4));
+}
@@ -0,0 +1,179 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use crate::format::ParseError;
+use crate::layout::LayoutError;
+
+/// Errors returned by fallible flaken operations
+///
+/// Features add variants for their backends, so matches need a wildcard arm.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The named environment variable was not set
+    MissingEnv(String),
+    /// The value did not contain a node ordinal
+    InvalidOrdinal(String),
+    /// Every node id of an allocator is already claimed
+    NodesExhausted,
+    /// An I/O operation failed
+    Io(io::Error),
+    /// A string could not be parsed into an id
+    Parse(ParseError),
+    /// Generating an id now would exceed the maximum rate, try again after the duration
+    RateLimited(Duration),
+    /// The id has reserved bits set, so was not made by this layout or is corrupt
+    ReservedBits(u64),
+    /// The id's timestamp is before the epoch or too far in the future
+    Implausible(u64),
+    /// The process-wide generator was already initialized
+    AlreadyInitialized,
+    /// The id has its top bit set and does not fit in a signed 64 bit integer
+    SignBit(u64),
+    /// A signed integer holding an id was negative
+    Negative(i64),
+    /// The id is too large to be represented exactly as a 64 bit float
+    Imprecise(u64),
+    /// The layout can not encode the timestamp, in milliseconds since the unix epoch
+    OutOfRange(u64),
+    /// The timestamp, in milliseconds since the unix epoch, is further ahead
+    /// of the generator's clock than the maximum future skew
+    TooFarAhead(u64),
+    /// Every sequence value of the millisecond is used
+    SequenceExhausted(u64),
+    /// The node id does not fit in the layout's node bits
+    NodeOverflow(u64),
+    /// The sequence value does not fit in the layout's sequence bits
+    SeqOverflow(u64),
+    /// The timestamp, in milliseconds since the unix epoch, falls within a
+    /// tick of the layout and would be rounded down
+    Truncated(u64),
+    /// The id is at or below the highest id issued by its node
+    Issued(u64),
+    /// The id service thread stopped, as its generator panicked
+    Stopped,
+    /// A real-time generator's time is ahead of the wall clock by the
+    /// duration
+    AheadOfWall(Duration),
+    /// A request asked for this many ids at once, more than
+    /// `AllocateRequest::MAX_COUNT`
+    TooMany(usize),
+    /// A byte slice holding an id was not 8 bytes long
+    Length(usize),
+    /// The fields of a layout do not fit together
+    Layout(LayoutError),
+    /// A CSV file could not be read or written
+    #[cfg(feature = "csv")]
+    Csv(::csv::Error),
+    /// A CSV file has no column of the name
+    #[cfg(feature = "csv")]
+    MissingColumn(String),
+    /// The redis node allocator failed to talk to redis
+    #[cfg(feature = "redis")]
+    Redis(::redis::RedisError),
+    /// The etcd node allocator failed to talk to etcd
+    #[cfg(feature = "etcd")]
+    Etcd(String),
+    /// The ZooKeeper node allocator failed to talk to ZooKeeper
+    #[cfg(feature = "zookeeper")]
+    ZooKeeper(::zookeeper::ZkError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::MissingEnv(ref var) => write!(f, "environment variable {} is not set", var),
+            Error::InvalidOrdinal(ref value) => write!(f, "no node ordinal found in {:?}", value),
+            Error::NodesExhausted => write!(f, "no free node id is available"),
+            Error::Io(ref err) => write!(f, "i/o error: {}", err),
+            Error::Parse(ref err) => write!(f, "{}", err),
+            Error::RateLimited(wait) => write!(f, "rate limited, retry in {:?}", wait),
+            Error::ReservedBits(id) => write!(f, "id {} has reserved bits set", id),
+            Error::Implausible(id) => write!(f, "id {} has an implausible timestamp", id),
+            Error::AlreadyInitialized => write!(f, "the global generator is already initialized"),
+            Error::SignBit(id) => write!(f, "id {} does not fit in a signed 64 bit integer", id),
+            Error::Negative(id) => write!(f, "negative id {}", id),
+            Error::Imprecise(id) => write!(f, "id {} can not be represented exactly as a float", id),
+            Error::OutOfRange(ts) => write!(f, "timestamp {} is outside the range of the layout", ts),
+            Error::TooFarAhead(ts) => write!(f, "timestamp {} is too far in the future", ts),
+            Error::SequenceExhausted(ts) => write!(f, "every sequence value of timestamp {} is used", ts),
+            Error::NodeOverflow(node) => write!(f, "node id {} does not fit in the layout", node),
+            Error::SeqOverflow(seq) => write!(f, "sequence value {} does not fit in the layout", seq),
+            Error::Truncated(ts) => write!(f, "timestamp {} is not a whole tick of the layout", ts),
+            Error::Issued(id) => write!(f, "id {} may already have been issued", id),
+            Error::Stopped => write!(f, "the id service thread stopped"),
+            Error::AheadOfWall(ahead) => write!(f, "generator time is {:?} ahead of the wall clock", ahead),
+            Error::TooMany(count) => write!(f, "asked for {} ids at once", count),
+            Error::Length(len) => write!(f, "expected 8 id bytes, got {}", len),
+            Error::Layout(ref err) => write!(f, "{}", err),
+            #[cfg(feature = "csv")]
+            Error::Csv(ref err) => write!(f, "csv error: {}", err),
+            #[cfg(feature = "csv")]
+            Error::MissingColumn(ref column) => write!(f, "no column {:?} in csv header", column),
+            #[cfg(feature = "redis")]
+            Error::Redis(ref err) => write!(f, "redis error: {}", err),
+            #[cfg(feature = "etcd")]
+            Error::Etcd(ref err) => write!(f, "etcd error: {}", err),
+            #[cfg(feature = "zookeeper")]
+            Error::ZooKeeper(ref err) => write!(f, "zookeeper error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref err) => Some(err),
+            Error::Parse(ref err) => Some(err),
+            Error::Layout(ref err) => Some(err),
+            #[cfg(feature = "csv")]
+            Error::Csv(ref err) => Some(err),
+            #[cfg(feature = "redis")]
+            Error::Redis(ref err) => Some(err),
+            #[cfg(feature = "zookeeper")]
+            Error::ZooKeeper(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<LayoutError> for Error {
+    fn from(err: LayoutError) -> Error {
+        Error::Layout(err)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Error {
+        Error::Parse(err)
+    }
+}
+
+#[cfg(feature = "csv")]
+impl From<::csv::Error> for Error {
+    fn from(err: ::csv::Error) -> Error {
+        Error::Csv(err)
+    }
+}
+
+#[cfg(feature = "redis")]
+impl From<::redis::RedisError> for Error {
+    fn from(err: ::redis::RedisError) -> Error {
+        Error::Redis(err)
+    }
+}
+
+#[cfg(feature = "zookeeper")]
+impl From<::zookeeper::ZkError> for Error {
+    fn from(err: ::zookeeper::ZkError) -> Error {
+        Error::ZooKeeper(err)
+    }
+}
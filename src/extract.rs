@@ -0,0 +1,224 @@
+//! Extracting ids from request paths, behind the `axum` and `actix-web`
+//! features
+//!
+//! Each extractor reads one radix, [`IdPath`] decimal ids and [`Base62Path`]
+//! base62 ids, since a path segment of only digits may be either.
+
+use std::fmt;
+
+use crate::format::{Format, ParseError, Radix};
+use crate::Id;
+
+/// The decimal id of a route with a single path parameter, such as
+/// `/users/{id}`
+///
+/// Malformed ids are rejected with a `400 Bad Request` saying why, and a
+/// route without exactly one path parameter with a `500 Internal Server
+/// Error`.
+#[derive(Debug)]
+pub struct IdPath<T = ()>(pub Id<T>);
+
+/// The base62 id of a route with a single path parameter, as [`IdPath`]
+#[derive(Debug)]
+pub struct Base62Path<T = ()>(pub Id<T>);
+
+/// Why an id could not be extracted from a request path
+#[derive(Debug)]
+pub struct IdRejection {
+    value: String,
+    radix: &'static str,
+    err: Option<ParseError>,
+}
+
+impl IdRejection {
+    /// The route does not have exactly one path parameter, a bug in the
+    /// routing rather than the request
+    fn missing(radix: &'static str) -> IdRejection {
+        IdRejection { value: String::new(), radix, err: None }
+    }
+
+    /// Whether the request, rather than the route, was at fault
+    fn is_client_error(&self) -> bool {
+        self.err.is_some()
+    }
+}
+
+impl fmt::Display for IdRejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.err {
+            Some(ref err) => write!(f, "invalid id {:?}: {}, expected a {} id", self.value, err, self.radix),
+            None => write!(f, "expected a single id path parameter"),
+        }
+    }
+}
+
+impl std::error::Error for IdRejection {}
+
+/// Extractors of ids in one radix
+trait PathRadix {
+    const RADIX: Radix;
+    const NAME: &'static str;
+}
+
+impl<T> PathRadix for IdPath<T> {
+    const RADIX: Radix = Radix::Decimal;
+    const NAME: &'static str = "decimal";
+}
+
+impl<T> PathRadix for Base62Path<T> {
+    const RADIX: Radix = Radix::Base62;
+    const NAME: &'static str = "base62";
+}
+
+/// Parse a path segment as an id in the radix of the extractor `P`
+fn parse_path_id<P: PathRadix, T>(value: &str) -> Result<Id<T>, IdRejection> {
+    Format::new(P::RADIX).parse(value).map(Id::new).map_err(|err| IdRejection {
+        value: value.to_string(),
+        radix: P::NAME,
+        err: Some(err),
+    })
+}
+
+#[cfg(feature = "axum")]
+mod axum_impl {
+    use axum::extract::{FromRequestParts, Path};
+    use axum::http::request::Parts;
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Response};
+
+    use super::{parse_path_id, Base62Path, IdPath, IdRejection, PathRadix};
+
+    macro_rules! from_request_parts {
+        ($extractor:ident) => {
+            impl<S: Send + Sync, T> FromRequestParts<S> for $extractor<T> {
+                type Rejection = IdRejection;
+
+                async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<$extractor<T>, IdRejection> {
+                    let Path(value) = Path::<String>::from_request_parts(parts, state)
+                        .await
+                        .map_err(|_| IdRejection::missing(<$extractor<T> as PathRadix>::NAME))?;
+                    parse_path_id::<$extractor<T>, T>(&value).map($extractor)
+                }
+            }
+        };
+    }
+
+    from_request_parts!(IdPath);
+    from_request_parts!(Base62Path);
+
+    impl IntoResponse for IdRejection {
+        fn into_response(self) -> Response {
+            let status = if self.is_client_error() {
+                StatusCode::BAD_REQUEST
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            };
+            (status, self.to_string()).into_response()
+        }
+    }
+}
+
+#[cfg(feature = "actix-web")]
+mod actix_impl {
+    use std::future::{ready, Ready};
+
+    use actix_web::dev::Payload;
+    use actix_web::http::StatusCode;
+    use actix_web::{FromRequest, HttpRequest, ResponseError};
+
+    use super::{parse_path_id, Base62Path, IdPath, IdRejection, PathRadix};
+
+    macro_rules! from_request {
+        ($extractor:ident) => {
+            impl<T> FromRequest for $extractor<T> {
+                type Error = IdRejection;
+                type Future = Ready<Result<$extractor<T>, IdRejection>>;
+
+                fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+                    let mut params = req.match_info().iter();
+                    let result = match (params.next(), params.next()) {
+                        (Some((_, value)), None) => parse_path_id::<$extractor<T>, T>(value).map($extractor),
+                        _ => Err(IdRejection::missing(<$extractor<T> as PathRadix>::NAME)),
+                    };
+                    ready(result)
+                }
+            }
+        };
+    }
+
+    from_request!(IdPath);
+    from_request!(Base62Path);
+
+    impl ResponseError for IdRejection {
+        fn status_code(&self) -> StatusCode {
+            if self.is_client_error() {
+                StatusCode::BAD_REQUEST
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_path_id() {
+    assert_eq!(parse_path_id::<IdPath, ()>("1234").unwrap(), Id::new(1234));
+    assert_eq!(parse_path_id::<Base62Path, ()>("LygHa16AHYF").unwrap(), Id::new(u64::MAX));
+    // Digits only are still base62 to a base62 extractor
+    assert_eq!(parse_path_id::<Base62Path, ()>("10").unwrap(), Id::new(62));
+    assert!(parse_path_id::<IdPath, ()>("LygHa16AHYF").is_err());
+    assert!(parse_path_id::<IdPath, ()>("99999999999999999999").is_err());
+    let rejection = parse_path_id::<IdPath, ()>("12-34").unwrap_err();
+    assert_eq!(rejection.to_string(), "invalid id \"12-34\": invalid character '-' in id, expected a decimal id");
+    assert!(!IdRejection::missing("decimal").is_client_error());
+}
+
+#[cfg(all(test, feature = "axum"))]
+#[tokio::test]
+async fn test_axum_extract() {
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    let app = Router::new()
+        .route("/users/{id}", get(|IdPath::<()>(id)| async move { id.to_string() }))
+        .route("/orders/{id}", get(|Base62Path::<()>(id)| async move { id.to_string() }))
+        .route("/health", get(|IdPath::<()>(id)| async move { id.to_string() }))
+        .route("/orgs/{org}/users/{id}", get(|IdPath::<()>(id)| async move { id.to_string() }));
+    let get_status = |app: Router, uri: &'static str| async move {
+        app.oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap().status()
+    };
+    assert_eq!(get_status(app.clone(), "/users/1234").await, StatusCode::OK);
+    assert_eq!(get_status(app.clone(), "/orders/LygHa16AHYF").await, StatusCode::OK);
+    assert_eq!(get_status(app.clone(), "/users/LygHa16AHYF").await, StatusCode::BAD_REQUEST);
+    assert_eq!(get_status(app.clone(), "/users/12-34").await, StatusCode::BAD_REQUEST);
+    assert_eq!(get_status(app.clone(), "/health").await, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(get_status(app, "/orgs/7/users/1234").await, StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[cfg(all(test, feature = "actix-web"))]
+#[actix_web::test]
+async fn test_actix_extract() {
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+    use actix_web::{FromRequest, ResponseError};
+
+    let req = TestRequest::default().param("id", "1234").to_http_request();
+    let IdPath::<()>(id) = IdPath::extract(&req).await.unwrap();
+    assert_eq!(id, Id::new(1234));
+    let req = TestRequest::default().param("id", "10").to_http_request();
+    let Base62Path::<()>(id) = Base62Path::extract(&req).await.unwrap();
+    assert_eq!(id, Id::new(62));
+    let req = TestRequest::default().param("id", "12-34").to_http_request();
+    let err = IdPath::<()>::extract(&req).await.unwrap_err();
+    assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+    let req = TestRequest::default().to_http_request();
+    let err = IdPath::<()>::extract(&req).await.unwrap_err();
+    assert_eq!(err.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    // As on /orgs/{org}/users/{id}, the org is not taken for the id
+    let req = TestRequest::default().param("org", "7").param("id", "1234").to_http_request();
+    let err = IdPath::<()>::extract(&req).await.unwrap_err();
+    assert_eq!(err.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+}
@@ -0,0 +1,75 @@
+//! Process-wide fences ordering the ids of strictly ordered generators
+//!
+//! The largest id returned by any strictly ordered generator of a layout is
+//! kept in one atomic per layout. A generator only returns an id after
+//! raising its fence to it, and an id below the fence is discarded for one
+//! of a later tick. Ids handed out across threads, nodes, and generators of
+//! a layout then increase in the order the calls returning them completed,
+//! so they may serve as optimistic concurrency tokens. Ids of different
+//! layouts, epochs, or versions do not compare meaningfully, and a fence
+//! shared between them would hold back the smaller ones for good.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::layout::Layout;
+use crate::Flaken;
+
+/// The fence of the strictly ordered generators of `layout`
+pub(crate) fn of(layout: &Layout) -> Arc<AtomicU64> {
+    static FENCES: OnceLock<Mutex<HashMap<Layout, Arc<AtomicU64>>>> = OnceLock::new();
+    let mut fences = FENCES.get_or_init(Default::default).lock().unwrap_or_else(|err| err.into_inner());
+    fences.entry(*layout).or_default().clone()
+}
+
+/// Raise `fence` to `id`, false when an id at least as large was returned
+pub(crate) fn publish(fence: &AtomicU64, id: u64) -> bool {
+    fence.fetch_max(id, Ordering::AcqRel) < id
+}
+
+impl Flaken {
+    /// The fence of the generator's layout, looked up again once the layout
+    /// changed
+    ///
+    /// Keyed by the layout of the first epoch, so moving on through an epoch
+    /// table keeps the fence.
+    pub(crate) fn fence(&mut self) -> &AtomicU64 {
+        let layout = self.layout_at(0);
+        if !matches!(self.fence, Some((ref key, _)) if *key == layout) {
+            self.fence = Some((layout, of(&layout)));
+        }
+        &self.fence.as_ref().unwrap().1
+    }
+}
+
+#[cfg(not(loom))]
+#[test]
+fn test_strict_order() {
+    use crate::{AtomicFlaken, Flaken};
+
+    // Node 1 orders above node 0 within a tick, so node 0 has to wait it out
+    let mut high = Flaken::default().bitwidths(41, 10).node(1).strict_order(true);
+    let mut low = Flaken::default().bitwidths(41, 10).node(0).strict_order(true);
+    let shared = AtomicFlaken::new(Flaken::default().bitwidths(41, 10).node(2).strict_order(true));
+    let mut last = 0;
+    for _ in 0..5 {
+        for id in [high.next(), low.next(), shared.next()] {
+            assert!(id > last);
+            last = id;
+        }
+    }
+}
+
+#[cfg(not(loom))]
+#[test]
+fn test_strict_order_layouts() {
+    use crate::Flaken;
+
+    // Ids of the later epoch are all smaller, yet neither holds up the other
+    let mut large = Flaken::default().strict_order(true);
+    let mut small = Flaken::default().epoch(large.start_ts - 1000).strict_order(true);
+    for _ in 0..5 {
+        assert!(large.next() > small.next());
+    }
+}
@@ -0,0 +1,472 @@
+//! Rendering ids as strings and parsing them back
+//!
+//! Ids may be rendered in decimal, Crockford base32, base62, or a base26
+//! alphabet for user visible ids which rarely spell words. Strings that
+//! people read out or type in can carry a trailing check character, which
+//! catches any single mistyped character and most swapped neighbours.
+//!
+//! ```
+//! use flaken::format::{Format, Radix};
+//!
+//! let format = Format::new(Radix::Base32).check_char(true);
+//! let s = format.encode(1234567890);
+//! assert_eq!(s, "14SC0PJ0");
+//! assert_eq!(format.parse(&s).unwrap(), 1234567890);
+//! assert!(format.parse("14SC0PK0").is_err());
+//! ```
+//!
+//! [`Hashids`](struct.Hashids.html) renders salted short codes instead,
+//! which do not reveal the order or spacing of ids at a glance.
+
+use std::error;
+use std::fmt;
+
+mod hashids;
+
+pub use self::hashids::Hashids;
+
+const DECIMAL: &[u8] = b"0123456789";
+const CROCKFORD: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const SAFE: &[u8] = b"256789BCDFGHJKLMNPQRSTVWXZ";
+
+/// The digits an id is rendered with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Radix {
+    /// Decimal digits
+    Decimal,
+    /// Crockford's base32, digits and upper case letters without I, L, O, and U
+    ///
+    /// Parsing ignores case and reads O as 0 and I and L as 1, so ids read
+    /// out or typed by people still parse.
+    Base32,
+    /// Digits, upper case, and lower case letters
+    Base62,
+    /// Upper case consonants other than Y and the digits not read as vowels,
+    /// `256789BCDFGHJKLMNPQRSTVWXZ`
+    ///
+    /// Without vowels, nor 0, 1, 3, and 4 standing in for them, rendered ids
+    /// rarely spell words, which matters for ids users see. Consonants alone
+    /// still spell a few, [`Format::next_filtered`] skips ids rendering to
+    /// anything on a block list.
+    Safe,
+}
+
+impl Radix {
+    fn alphabet(self) -> &'static [u8] {
+        match self {
+            Radix::Decimal => DECIMAL,
+            Radix::Base32 => CROCKFORD,
+            Radix::Base62 => BASE62,
+            Radix::Safe => SAFE,
+        }
+    }
+
+    /// Number of distinct digits
+    pub fn base(self) -> u64 {
+        self.alphabet().len() as u64
+    }
+
+    /// The longest rendering of an id, without a check character
+    pub fn max_len(self) -> usize {
+        match self {
+            Radix::Decimal => 20,
+            Radix::Base32 => 13,
+            Radix::Base62 => 11,
+            Radix::Safe => 14,
+        }
+    }
+
+    fn digit(self, c: u8) -> Option<u64> {
+        // Crockford's base32 reads case insensitively, O as 0, and I and L as 1
+        let c = match (self, c.to_ascii_uppercase()) {
+            (Radix::Base32, b'O') => b'0',
+            (Radix::Base32, b'I') | (Radix::Base32, b'L') => b'1',
+            (Radix::Base32, upper) => upper,
+            _ => c,
+        };
+        self.alphabet().iter().position(|&d| d == c).map(|d| d as u64)
+    }
+}
+
+/// Why a string could not be parsed into an id
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The string was empty
+    Empty,
+    /// The string contained a character which is not a digit of the radix
+    InvalidChar(char),
+    /// The value does not fit in 64 bits
+    Overflow,
+    /// The check character did not match, the string was mistyped
+    Checksum,
+    /// The string is not the encoding of a single id
+    Malformed,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Empty => write!(f, "empty id"),
+            ParseError::InvalidChar(c) => write!(f, "invalid character {:?} in id", c),
+            ParseError::Overflow => write!(f, "id does not fit in 64 bits"),
+            ParseError::Checksum => write!(f, "id check character does not match"),
+            ParseError::Malformed => write!(f, "malformed id"),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Renders ids as strings in a radix and parses them back
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Format {
+    radix: Radix,
+    check: bool,
+    fixed: bool,
+}
+
+impl Format {
+    /// Build a format rendering ids in `radix` without a check character
+    pub fn new(radix: Radix) -> Format {
+        Format { radix, check: false, fixed: false }
+    }
+
+    /// Build a format rendering ids as 13 Crockford base32 digits, whose
+    /// string order is the order of the ids as with TSIDs and ULIDs
+    ///
+    /// Ids of one generator within a tick render to strictly increasing
+    /// strings, like ULID's monotonic extension, as generation increments
+    /// the sequence, by a random step with
+    /// [`random_step`](../struct.Flaken.html#method.random_step), rather
+    /// than drawing new random bits.
+    ///
+    /// ```
+    /// use flaken::format::Format;
+    ///
+    /// let format = Format::sortable();
+    /// assert_eq!(format.encode(1), "0000000000001");
+    /// assert!(format.encode(31) < format.encode(32));
+    /// assert!(format.encode(u64::MAX - 1) < format.encode(u64::MAX));
+    /// ```
+    pub fn sortable() -> Format {
+        Format::new(Radix::Base32).fixed_width(true)
+    }
+
+    /// Set whether a check character is appended to rendered ids and
+    /// verified when parsing
+    ///
+    /// The check character is computed with the Luhn mod N algorithm over
+    /// the digits of the radix.
+    pub fn check_char(mut self, check: bool) -> Format {
+        self.check = check;
+        self
+    }
+
+    /// Set whether ids are padded with leading zeros to the longest
+    /// rendering of the radix, see [`Radix::max_len`]
+    ///
+    /// The digits of every radix are in ascending ASCII order, so padded
+    /// ids of one format sort as strings in the same order as numbers, with
+    /// or without a check character.
+    pub fn fixed_width(mut self, fixed: bool) -> Format {
+        self.fixed = fixed;
+        self
+    }
+
+    /// The radix ids are rendered in
+    pub fn radix(&self) -> Radix {
+        self.radix
+    }
+
+    /// Render an id
+    pub fn encode(&self, id: u64) -> String {
+        if self.radix == Radix::Decimal && !self.check && !self.fixed {
+            let mut buf = [0; 20];
+            return decimal_into(id, &mut buf).to_string();
+        }
+        let alphabet = self.radix.alphabet();
+        let base = self.radix.base();
+        let mut digits = Vec::with_capacity(14);
+        let mut rest = id;
+        loop {
+            digits.push((rest % base) as u8);
+            rest /= base;
+            if rest == 0 {
+                break;
+            }
+        }
+        if self.fixed {
+            digits.resize(self.radix.max_len(), 0);
+        }
+        digits.reverse();
+        if self.check {
+            let check = luhn_check(&digits, base);
+            digits.push(check);
+        }
+        digits.iter().map(|&d| alphabet[d as usize] as char).collect()
+    }
+
+    /// Generate ids with `flake` until one renders to a string `accept`
+    /// allows, returning the id and its rendering
+    ///
+    /// Rejected ids are skipped rather than reused, as with blocking
+    /// renderings which spell words.
+    ///
+    /// ```
+    /// use flaken::Flaken;
+    /// use flaken::format::{Format, Radix};
+    ///
+    /// let format = Format::new(Radix::Safe);
+    /// let mut flake = Flaken::default();
+    /// let (id, s) = format.next_filtered(&mut flake, |s| !s.contains("XXX"));
+    /// assert_eq!(format.encode(id), s);
+    /// ```
+    pub fn next_filtered<F>(&self, flake: &mut crate::Flaken, mut accept: F) -> (u64, String)
+    where
+        F: FnMut(&str) -> bool,
+    {
+        loop {
+            let id = flake.next();
+            let s = self.encode(id);
+            if accept(&s) {
+                return (id, s);
+            }
+        }
+    }
+
+    /// Parse a rendered id
+    pub fn parse(&self, s: &str) -> Result<u64, ParseError> {
+        let digits = s
+            .bytes()
+            .map(|c| self.radix.digit(c).ok_or_else(|| ParseError::InvalidChar(invalid_char(s, c))))
+            .collect::<Result<Vec<u64>, ParseError>>()?;
+        let base = self.radix.base();
+        let digits = if self.check {
+            match digits.split_last() {
+                Some((&check, rest)) if !rest.is_empty() => {
+                    let rest: Vec<u8> = rest.iter().map(|&d| d as u8).collect();
+                    if u64::from(luhn_check(&rest, base)) != check {
+                        return Err(ParseError::Checksum);
+                    }
+                    &digits[..digits.len() - 1]
+                }
+                _ => return Err(ParseError::Empty),
+            }
+        } else {
+            &digits[..]
+        };
+        if digits.is_empty() {
+            return Err(ParseError::Empty);
+        }
+        digits.iter().try_fold(0u64, |id, &d| {
+            id.checked_mul(base).and_then(|id| id.checked_add(d)).ok_or(ParseError::Overflow)
+        })
+    }
+}
+
+/// Render an id into `buf` without allocating, returning the rendered part
+///
+/// A buffer of [`Radix::max_len`] bytes fits any id, panics if `buf` is too
+/// short for `id`.
+///
+/// ```
+/// use flaken::format::{encode_str_into, Radix};
+///
+/// let mut buf = [0; 13];
+/// assert_eq!(encode_str_into(1234567890, &mut buf, Radix::Base32), "14SC0PJ");
+/// ```
+pub fn encode_str_into(id: u64, buf: &mut [u8], radix: Radix) -> &str {
+    if radix == Radix::Decimal {
+        return decimal_into(id, buf);
+    }
+    let alphabet = radix.alphabet();
+    let base = radix.base();
+    let mut len = 1;
+    let mut rest = id / base;
+    while rest != 0 {
+        len += 1;
+        rest /= base;
+    }
+    assert!(buf.len() >= len, "buffer of {} bytes can not fit {} digits", buf.len(), len);
+    let mut rest = id;
+    for digit in buf[..len].iter_mut().rev() {
+        *digit = alphabet[(rest % base) as usize];
+        rest /= base;
+    }
+    core::str::from_utf8(&buf[..len]).expect("digits are ascii")
+}
+
+/// Render an id in decimal with itoa, see the `render` benchmarks
+fn decimal_into(id: u64, buf: &mut [u8]) -> &str {
+    let mut digits = itoa::Buffer::new();
+    let digits = digits.format(id).as_bytes();
+    let len = digits.len();
+    assert!(buf.len() >= len, "buffer of {} bytes can not fit {} digits", buf.len(), len);
+    buf[..len].copy_from_slice(digits);
+    core::str::from_utf8(&buf[..len]).expect("digits are ascii")
+}
+
+/// Render an id into a fixed capacity string, kept inline rather than on the
+/// heap, see [`encode_str_into`]
+///
+/// The crate itself needs `std`, this only saves the allocation.
+#[cfg(feature = "heapless")]
+pub fn encode_heapless(id: u64, radix: Radix) -> heapless::String<20> {
+    let mut buf = [0; 20];
+    let mut s = heapless::String::new();
+    s.push_str(encode_str_into(id, &mut buf, radix)).expect("ids fit in 20 characters");
+    s
+}
+
+/// The first character of `s` whose encoding starts with byte `c`
+fn invalid_char(s: &str, c: u8) -> char {
+    s.chars().find(|ch| {
+        let mut buf = [0; 4];
+        ch.encode_utf8(&mut buf).as_bytes()[0] == c
+    }).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+/// Luhn mod N check digit of `digits`, most significant first
+fn luhn_check(digits: &[u8], base: u64) -> u8 {
+    let sum: u64 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| {
+            let addend = u64::from(d) * if i % 2 == 0 { 2 } else { 1 };
+            addend / base + addend % base
+        })
+        .sum();
+    ((base - sum % base) % base) as u8
+}
+
+#[test]
+fn test_round_trip() {
+    for radix in [Radix::Decimal, Radix::Base32, Radix::Base62, Radix::Safe].iter() {
+        for check in [false, true].iter() {
+            let format = Format::new(*radix).check_char(*check);
+            for &id in [0, 1, 61, 62, 1 << 40, u64::MAX].iter() {
+                assert_eq!(format.parse(&format.encode(id)), Ok(id));
+            }
+        }
+    }
+    assert_eq!(Format::new(Radix::Decimal).encode(1234), "1234");
+    assert_eq!(Format::new(Radix::Base62).encode(u64::MAX), "LygHa16AHYF");
+    assert_eq!(Format::new(Radix::Base32).encode(u64::MAX), "FZZZZZZZZZZZZ");
+    assert_eq!(Format::new(Radix::Safe).encode(25), "Z");
+    assert_eq!(Format::new(Radix::Safe).encode(26), "52");
+}
+
+#[test]
+fn test_encode_str_into() {
+    for radix in [Radix::Decimal, Radix::Base32, Radix::Base62, Radix::Safe].iter() {
+        let mut buf = [0; 20];
+        for &id in [0, 1, 61, 62, 1 << 40, u64::MAX].iter() {
+            assert_eq!(encode_str_into(id, &mut buf, *radix), Format::new(*radix).encode(id));
+        }
+        assert_eq!(encode_str_into(u64::MAX, &mut buf, *radix).len(), radix.max_len());
+    }
+}
+
+#[cfg(all(test, feature = "heapless"))]
+#[test]
+fn test_encode_heapless() {
+    assert_eq!(encode_heapless(u64::MAX, Radix::Decimal).as_str(), "18446744073709551615");
+    assert_eq!(encode_heapless(u64::MAX, Radix::Base62).as_str(), "LygHa16AHYF");
+}
+
+#[test]
+fn test_decimal_into() {
+    let mut buf = [0; 20];
+    for &id in [0, 9, 10, 99, 100, 101, 12345, 999_999, 1 << 40, u64::MAX].iter() {
+        assert_eq!(decimal_into(id, &mut buf), id.to_string());
+    }
+}
+
+#[test]
+fn test_fixed_width_sorts() {
+    for radix in [Radix::Decimal, Radix::Base32, Radix::Base62, Radix::Safe].iter() {
+        let format = Format::new(*radix).fixed_width(true).check_char(true);
+        let ids = [0, 1, 9, 10, 31, 32, 61, 62, 1 << 40, u64::MAX - 1, u64::MAX];
+        let rendered: Vec<String> = ids.iter().map(|&id| format.encode(id)).collect();
+        assert!(rendered.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(rendered.iter().all(|s| s.len() == radix.max_len() + 1));
+        for (&id, s) in ids.iter().zip(&rendered) {
+            assert_eq!(format.parse(s), Ok(id));
+        }
+    }
+}
+
+#[test]
+fn test_base32_confusables() {
+    let format = Format::new(Radix::Base32).check_char(true);
+    assert_eq!(format.parse("14sc0pj0"), Ok(1234567890));
+    assert_eq!(format.parse("I4SCOPJO"), Ok(1234567890));
+    assert_eq!(format.parse("l4scoPjo"), Ok(1234567890));
+    assert_eq!(format.parse("14SCUPJ0"), Err(ParseError::InvalidChar('U')));
+}
+
+#[test]
+fn test_sortable_monotonic() {
+    let format = Format::sortable();
+    let mut flake = crate::Flaken::default().random_step(16);
+    let rendered: Vec<String> = (0..2000).map(|_| format.encode(flake.next())).collect();
+    assert!(rendered.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn test_safe_check_char() {
+    // An even base keeps every single character typo from checking out
+    let checked = Format::new(Radix::Safe).check_char(true);
+    assert_eq!(checked.parse("KX"), Err(ParseError::Checksum));
+    let s = checked.encode(987654321);
+    for i in 0..s.len() {
+        for &c in SAFE.iter() {
+            let mut typo = s.clone().into_bytes();
+            if typo[i] == c {
+                continue;
+            }
+            typo[i] = c;
+            assert!(checked.parse(std::str::from_utf8(&typo).unwrap()).is_err());
+        }
+    }
+}
+
+#[test]
+fn test_next_filtered() {
+    let format = Format::new(Radix::Decimal);
+    let mut flake = crate::Flaken::default();
+    let (id, s) = format.next_filtered(&mut flake, |s| s.ends_with('7'));
+    assert!(s.ends_with('7'));
+    assert_eq!(format.parse(&s), Ok(id));
+}
+
+#[test]
+fn test_luhn_decimal() {
+    // The classic Luhn example
+    assert_eq!(Format::new(Radix::Decimal).check_char(true).encode(7992739871), "79927398713");
+}
+
+#[test]
+fn test_parse_errors() {
+    let base62 = Format::new(Radix::Base62);
+    assert_eq!(base62.parse(""), Err(ParseError::Empty));
+    assert_eq!(base62.parse("ab-c"), Err(ParseError::InvalidChar('-')));
+    assert_eq!(base62.parse("abé"), Err(ParseError::InvalidChar('é')));
+    assert_eq!(base62.parse("LygHa16AHYG"), Err(ParseError::Overflow));
+    let checked = base62.check_char(true);
+    assert_eq!(checked.parse("7"), Err(ParseError::Empty));
+    let s = checked.encode(987654321);
+    // Every single character typo is caught
+    for i in 0..s.len() {
+        for &c in BASE62.iter() {
+            let mut typo = s.clone().into_bytes();
+            if typo[i] == c {
+                continue;
+            }
+            typo[i] = c;
+            assert!(checked.parse(std::str::from_utf8(&typo).unwrap()).is_err());
+        }
+    }
+}
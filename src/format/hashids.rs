@@ -0,0 +1,213 @@
+//! Hashids, salted short codes compatible with the hashids libraries
+
+use super::ParseError;
+
+const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890";
+const SEPARATORS: &[u8] = b"cfhistuCFHISTU";
+const SEPARATOR_DIV: f64 = 3.5;
+const GUARD_DIV: f64 = 12.0;
+
+/// Renders ids as salted Hashids and parses them back
+///
+/// The codes are short, alphanumeric, and look unrelated to each other, so
+/// they may be shown to users in place of ids without a lookup table.
+/// Anyone knowing the salt can recover the id, so this hides ids from
+/// casual view only, see [`IdCipher`](../struct.IdCipher.html) when that
+/// matters.
+///
+/// Codes are the same as those of other Hashids implementations encoding
+/// a single number with the default alphabet.
+///
+/// ```
+/// use flaken::format::Hashids;
+///
+/// let hashids = Hashids::new("this is my salt");
+/// assert_eq!(hashids.encode(12345), "NkK9");
+/// assert_eq!(hashids.parse("NkK9").unwrap(), 12345);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Hashids {
+    salt: Vec<u8>,
+    min_length: usize,
+    alphabet: Vec<u8>,
+    separators: Vec<u8>,
+    guards: Vec<u8>,
+}
+
+impl Hashids {
+    /// Hashids salted with `salt`
+    pub fn new(salt: &str) -> Hashids {
+        let salt = salt.as_bytes().to_vec();
+        let mut alphabet: Vec<u8> = ALPHABET.iter().cloned().filter(|c| !SEPARATORS.contains(c)).collect();
+        let mut separators = SEPARATORS.to_vec();
+        shuffle(&mut separators, &salt);
+
+        if alphabet.len() as f64 / separators.len() as f64 > SEPARATOR_DIV {
+            let len = ((alphabet.len() as f64 / SEPARATOR_DIV).ceil() as usize).max(2);
+            if len > separators.len() {
+                let moved = len - separators.len();
+                separators.extend(alphabet.drain(..moved));
+            } else {
+                separators.truncate(len);
+            }
+        }
+
+        shuffle(&mut alphabet, &salt);
+        let guard_len = (alphabet.len() as f64 / GUARD_DIV).ceil() as usize;
+        let guards = alphabet.drain(..guard_len).collect();
+
+        Hashids {
+            salt,
+            min_length: 0,
+            alphabet,
+            separators,
+            guards,
+        }
+    }
+
+    /// Pad codes to at least `min_length` characters
+    pub fn min_length(mut self, min_length: usize) -> Hashids {
+        self.min_length = min_length;
+        self
+    }
+
+    /// Render an id as a code
+    pub fn encode(&self, id: u64) -> String {
+        let mut alphabet = self.alphabet.clone();
+        let len = alphabet.len() as u64;
+        let hash = id % 100;
+        let lottery = alphabet[(hash % len) as usize];
+
+        let mut code = vec![lottery];
+        salt_alphabet(&mut alphabet, lottery, &self.salt);
+        code.extend(to_digits(id, &alphabet));
+
+        if code.len() < self.min_length {
+            let guard = (hash + code[0] as u64) % self.guards.len() as u64;
+            code.insert(0, self.guards[guard as usize]);
+            if code.len() < self.min_length {
+                let guard = (hash + code[2] as u64) % self.guards.len() as u64;
+                code.push(self.guards[guard as usize]);
+            }
+        }
+
+        let half = alphabet.len() / 2;
+        while code.len() < self.min_length {
+            let key = alphabet.clone();
+            shuffle(&mut alphabet, &key);
+            let mut padded = alphabet[half..].to_vec();
+            padded.extend_from_slice(&code);
+            padded.extend_from_slice(&alphabet[..half]);
+            let excess = padded.len().saturating_sub(self.min_length);
+            code = padded[excess / 2..excess / 2 + padded.len().min(self.min_length)].to_vec();
+        }
+
+        String::from_utf8(code).unwrap()
+    }
+
+    /// Parse a code back into an id
+    ///
+    /// Codes of several numbers, or which are not exactly what
+    /// [`encode`](#method.encode) renders, are rejected as malformed.
+    pub fn parse(&self, s: &str) -> Result<u64, ParseError> {
+        if s.is_empty() {
+            return Err(ParseError::Empty);
+        }
+        if let Some(c) = s.chars().find(|&c| !c.is_ascii() || !self.is_digit(c as u8)) {
+            return Err(ParseError::InvalidChar(c));
+        }
+
+        let parts: Vec<&[u8]> = s.as_bytes().split(|c| self.guards.contains(c)).collect();
+        let body = match parts.len() {
+            2 | 3 => parts[1],
+            _ => parts[0],
+        };
+        let (&lottery, digits) = body.split_first().ok_or(ParseError::Malformed)?;
+        if digits.is_empty() || digits.iter().any(|c| self.separators.contains(c)) {
+            return Err(ParseError::Malformed);
+        }
+
+        let mut alphabet = self.alphabet.clone();
+        salt_alphabet(&mut alphabet, lottery, &self.salt);
+        let id = from_digits(digits, &alphabet)?;
+        if self.encode(id) != s {
+            return Err(ParseError::Malformed);
+        }
+        Ok(id)
+    }
+
+    fn is_digit(&self, c: u8) -> bool {
+        self.alphabet.contains(&c) || self.separators.contains(&c) || self.guards.contains(&c)
+    }
+}
+
+/// Reshuffle the alphabet for a number, keyed by the lottery character and salt
+fn salt_alphabet(alphabet: &mut [u8], lottery: u8, salt: &[u8]) {
+    let mut key = vec![lottery];
+    key.extend_from_slice(salt);
+    key.extend_from_slice(alphabet);
+    key.truncate(alphabet.len());
+    shuffle(alphabet, &key);
+}
+
+/// The consistent shuffle of Hashids, a keyed Fisher-Yates
+fn shuffle(values: &mut [u8], key: &[u8]) {
+    if key.is_empty() {
+        return;
+    }
+    let mut v = 0;
+    let mut p = 0;
+    for i in (1..values.len()).rev() {
+        v %= key.len();
+        let k = key[v] as usize;
+        p += k;
+        let j = (k + v + p) % i;
+        values.swap(i, j);
+        v += 1;
+    }
+}
+
+fn to_digits(mut n: u64, alphabet: &[u8]) -> Vec<u8> {
+    let len = alphabet.len() as u64;
+    let mut digits = Vec::new();
+    loop {
+        digits.push(alphabet[(n % len) as usize]);
+        n /= len;
+        if n == 0 {
+            break;
+        }
+    }
+    digits.reverse();
+    digits
+}
+
+fn from_digits(digits: &[u8], alphabet: &[u8]) -> Result<u64, ParseError> {
+    let len = alphabet.len() as u64;
+    digits.iter().try_fold(0u64, |n, c| {
+        let d = alphabet.iter().position(|a| a == c).ok_or(ParseError::Malformed)? as u64;
+        n.checked_mul(len).and_then(|n| n.checked_add(d)).ok_or(ParseError::Overflow)
+    })
+}
+
+#[test]
+fn test_hashids_vectors() {
+    let hashids = Hashids::new("this is my salt");
+    assert_eq!(hashids.encode(12345), "NkK9");
+    assert_eq!(hashids.parse("NkK9"), Ok(12345));
+    let padded = Hashids::new("this is my salt").min_length(8);
+    assert_eq!(padded.encode(1), "gB0NV05e");
+    assert_eq!(padded.parse("gB0NV05e"), Ok(1));
+}
+
+#[test]
+fn test_hashids_parse() {
+    let hashids = Hashids::new("flaken").min_length(10);
+    for &id in &[0, 1, 99, 100, 1 << 40, u64::MAX] {
+        let code = hashids.encode(id);
+        assert!(code.len() >= 10);
+        assert_eq!(hashids.parse(&code), Ok(id));
+    }
+    assert_eq!(hashids.parse(""), Err(ParseError::Empty));
+    assert_eq!(hashids.parse("ab-c"), Err(ParseError::InvalidChar('-')));
+    assert!(Hashids::new("other").min_length(10).parse(&hashids.encode(42)) != Ok(42));
+}
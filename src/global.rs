@@ -0,0 +1,84 @@
+//! A process-wide generator for services that only ever need one
+
+use std::cell::RefCell;
+use std::sync::OnceLock;
+
+use crate::node::{Lease, NodeLease};
+use crate::{AtomicFlaken, Error, Flaken};
+
+static GLOBAL: OnceLock<AtomicFlaken> = OnceLock::new();
+
+thread_local! {
+    /// A generator of the current thread alone, which [`next`] prefers
+    static LOCAL: RefCell<Option<(Flaken, NodeLease)>> = const { RefCell::new(None) };
+}
+
+/// Install `flake` as the current thread's generator for [`next`], keeping
+/// the lease of its node id until the thread exits or it is replaced
+#[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+pub(crate) fn install_local(flake: Flaken, lease: NodeLease) {
+    LOCAL.with(|local| *local.borrow_mut() = Some((flake, lease)));
+}
+
+/// Remove the current thread's generator, releasing its node id once the
+/// tick of its latest id has passed
+#[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+pub(crate) fn remove_local() {
+    let _ = LOCAL.try_with(|local| {
+        if let Some((flake, mut lease)) = local.borrow_mut().take() {
+            lease.hold_until(flake.release_ts());
+        }
+    });
+}
+
+/// Install the process-wide generator used by [`next`](fn.next.html)
+///
+/// Only the first call installs a generator, later calls return
+/// [`Error::AlreadyInitialized`](enum.Error.html#variant.AlreadyInitialized).
+///
+/// ```
+/// let node = 3;
+/// flaken::init(flaken::Flaken::default().node(node)).unwrap();
+/// let id = flaken::next();
+/// assert_eq!(flaken::global().unwrap().decode(id).1, node);
+/// ```
+pub fn init(flake: Flaken) -> Result<(), Error> {
+    let mut flake = Some(flake);
+    GLOBAL.get_or_init(|| AtomicFlaken::new(flake.take().unwrap()));
+    match flake {
+        None => Ok(()),
+        Some(_) => Err(Error::AlreadyInitialized),
+    }
+}
+
+/// Generate an id from the process-wide generator
+///
+/// Threads given their own generator, such as the tokio workers of
+/// [`per_worker`](fn.per_worker.html), use theirs instead.
+///
+/// # Panics
+///
+/// Panics when [`init`](fn.init.html) has not been called and the thread
+/// has no generator of its own.
+pub fn next() -> u64 {
+    if let Some(id) = LOCAL.with(|local| local.borrow_mut().as_mut().map(|(flake, _)| flake.next())) {
+        return id;
+    }
+    global().expect("flaken::init must be called before flaken::next").next()
+}
+
+/// The process-wide generator, if one was installed
+pub fn global() -> Option<&'static AtomicFlaken> {
+    GLOBAL.get()
+}
+
+#[cfg(not(loom))]
+#[test]
+fn test_global() {
+    init(Flaken::default().bitwidths(40, 10).node(7)).unwrap();
+    assert!(init(Flaken::default()).is_err());
+    let id0 = next();
+    let id1 = next();
+    assert!(id1 > id0);
+    assert_eq!(global().unwrap().decode(id1).1, 7);
+}
@@ -0,0 +1,37 @@
+//! GraphQL scalar for ids, behind the `async-graphql` feature
+//!
+//! Ids are exchanged as decimal strings, since GraphQL `Int` is only 32 bits
+//! and JavaScript clients lose precision on numbers past 2^53.
+
+use async_graphql::{InputValueError, InputValueResult, Scalar, ScalarType, Value};
+
+use crate::Id;
+
+#[Scalar(name = "FlakeId")]
+impl<T: Send + Sync + 'static> ScalarType for Id<T> {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        match value {
+            Value::String(ref s) => s.parse().map(Id::new).map_err(|_| InputValueError::custom(format!("invalid id {:?}", s))),
+            Value::Number(ref n) => n.as_u64().map(Id::new).ok_or_else(|| InputValueError::custom(format!("invalid id {}", n))),
+            _ => Err(InputValueError::expected_type(value)),
+        }
+    }
+
+    fn is_valid(value: &Value) -> bool {
+        matches!(value, Value::String(_) | Value::Number(_))
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+#[test]
+fn test_scalar_round_trip() {
+    let id: Id = Id::new(u64::MAX);
+    assert_eq!(id.to_value(), Value::String("18446744073709551615".to_string()));
+    assert_eq!(<Id as ScalarType>::parse(id.to_value()).unwrap(), id);
+    assert_eq!(<Id as ScalarType>::parse(Value::from(42)).unwrap(), Id::new(42));
+    assert!(<Id as ScalarType>::parse(Value::String("-1".to_string())).is_err());
+    assert!(<Id as ScalarType>::parse(Value::Boolean(true)).is_err());
+}
@@ -0,0 +1,293 @@
+//! Ids tagged with the kind of thing they identify
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use crate::format::{encode_str_into, Format, Radix};
+use crate::{Error, Flaken};
+
+/// An id of a `T`, such as `Id<User>`
+///
+/// The tag only exists at compile time, so ids of users and orders from the
+/// same generator layout are still plain 64 bit snowflakes but can not be
+/// swapped by accident.
+///
+/// # Databases
+///
+/// Most databases have no unsigned 64 bit integer, so the database features
+/// store ids as signed 64 bit integers, see [`to_signed`](#method.to_signed).
+/// Ids with the top bit set fail to store rather than being stored as
+/// negative numbers, which would sort before every other id and break
+/// ordering by id, and negative numbers fail to load.
+#[cfg_attr(feature = "diesel", derive(diesel::expression::AsExpression, diesel::deserialize::FromSqlRow))]
+#[cfg_attr(feature = "diesel", diesel(sql_type = diesel::sql_types::BigInt))]
+pub struct Id<T = ()>(u64, PhantomData<fn() -> T>);
+
+impl<T> Id<T> {
+    /// Tag a raw id
+    pub fn new(id: u64) -> Id<T> {
+        Id(id, PhantomData)
+    }
+
+    /// The raw id
+    pub fn get(self) -> u64 {
+        self.0
+    }
+
+    /// The id as a signed integer, for databases without unsigned 64 bit columns
+    ///
+    /// Fails when the top bit is set rather than storing a negative number,
+    /// which would sort before every other id.
+    pub fn to_signed(self) -> Result<i64, Error> {
+        if self.0 > i64::MAX as u64 {
+            Err(Error::SignBit(self.0))
+        } else {
+            Ok(self.0 as i64)
+        }
+    }
+
+    /// An id stored as a signed integer, failing on negative numbers
+    pub fn from_signed(id: i64) -> Result<Id<T>, Error> {
+        if id < 0 {
+            Err(Error::Negative(id))
+        } else {
+            Ok(Id::new(id as u64))
+        }
+    }
+
+    /// Retag the id, for deliberate conversions between kinds
+    pub fn cast<U>(self) -> Id<U> {
+        Id::new(self.0)
+    }
+}
+
+impl<T> Clone for Id<T> {
+    fn clone(&self) -> Id<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Id<T> {}
+
+impl<T> PartialEq for Id<T> {
+    fn eq(&self, other: &Id<T>) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T> Eq for Id<T> {}
+
+impl<T> PartialOrd for Id<T> {
+    fn partial_cmp(&self, other: &Id<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Id<T> {
+    fn cmp(&self, other: &Id<T>) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl<T> Hash for Id<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<T> fmt::Debug for Id<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Id({})", self.0)
+    }
+}
+
+impl<T> fmt::Display for Id<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut buf = [0; 20];
+        f.pad_integral(true, "", encode_str_into(self.0, &mut buf, Radix::Decimal))
+    }
+}
+
+impl<T> From<Id<T>> for u64 {
+    fn from(id: Id<T>) -> u64 {
+        id.0
+    }
+}
+
+impl<T> From<u64> for Id<T> {
+    fn from(id: u64) -> Id<T> {
+        Id::new(id)
+    }
+}
+
+/// Fails with [`Error::SignBit`] when the top bit is set, as [`Id::to_signed`]
+impl<T> TryFrom<Id<T>> for i64 {
+    type Error = Error;
+
+    fn try_from(id: Id<T>) -> Result<i64, Error> {
+        id.to_signed()
+    }
+}
+
+/// Fails with [`Error::Negative`] on negative numbers, as [`Id::from_signed`]
+impl<T> TryFrom<i64> for Id<T> {
+    type Error = Error;
+
+    fn try_from(id: i64) -> Result<Id<T>, Error> {
+        Id::from_signed(id)
+    }
+}
+
+/// The id's big endian bytes, which sort the same as the ids
+impl<T> From<Id<T>> for [u8; 8] {
+    fn from(id: Id<T>) -> [u8; 8] {
+        id.0.to_be_bytes()
+    }
+}
+
+impl<T> From<[u8; 8]> for Id<T> {
+    fn from(bytes: [u8; 8]) -> Id<T> {
+        Id::new(u64::from_be_bytes(bytes))
+    }
+}
+
+/// Fails with [`Error::Length`] unless given exactly 8 big endian bytes
+impl<T> TryFrom<&[u8]> for Id<T> {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Id<T>, Error> {
+        <[u8; 8]>::try_from(bytes).map(Id::from).map_err(|_| Error::Length(bytes.len()))
+    }
+}
+
+/// The id in decimal, as its `Display`
+impl<T> From<Id<T>> for String {
+    fn from(id: Id<T>) -> String {
+        id.to_string()
+    }
+}
+
+/// Parses a decimal id, as written by `Display`
+impl<T> FromStr for Id<T> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Id<T>, Error> {
+        Ok(Id::new(Format::new(Radix::Decimal).parse(s)?))
+    }
+}
+
+impl<T> TryFrom<&str> for Id<T> {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Id<T>, Error> {
+        s.parse()
+    }
+}
+
+impl<T> TryFrom<String> for Id<T> {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Id<T>, Error> {
+        s.parse()
+    }
+}
+
+/// A generator of ids tagged with `T`
+///
+/// ```
+/// use flaken::{Flaken, Id, TypedFlaken};
+///
+/// struct User;
+///
+/// let mut users: TypedFlaken<User> = TypedFlaken::new(Flaken::default());
+/// let id: Id<User> = users.next();
+/// assert_eq!(users.decode(id), users.flaken().decode(id.get()));
+/// ```
+pub struct TypedFlaken<T> {
+    flake: Flaken,
+    kind: PhantomData<fn() -> T>,
+}
+
+impl<T> TypedFlaken<T> {
+    /// Tag the ids of a generator
+    pub fn new(flake: Flaken) -> TypedFlaken<T> {
+        TypedFlaken { flake, kind: PhantomData }
+    }
+
+    /// Generate the next id, as [`Flaken::next`](struct.Flaken.html#method.next)
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Id<T> {
+        Id::new(self.flake.next())
+    }
+
+    /// Decode the timestamp, node id, and sequence id of an id
+    pub fn decode(&self, id: Id<T>) -> (u64, u64, u64) {
+        self.flake.decode(id.get())
+    }
+
+    /// The untyped generator
+    pub fn flaken(&self) -> &Flaken {
+        &self.flake
+    }
+
+    /// Unwrap the untyped generator
+    pub fn into_inner(self) -> Flaken {
+        self.flake
+    }
+}
+
+impl<T> fmt::Debug for TypedFlaken<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TypedFlaken").field("flake", &self.flake).finish()
+    }
+}
+
+impl<T> From<Flaken> for TypedFlaken<T> {
+    fn from(flake: Flaken) -> TypedFlaken<T> {
+        TypedFlaken::new(flake)
+    }
+}
+
+#[test]
+fn test_typed_next() {
+    struct Order;
+    let mut orders: TypedFlaken<Order> = Flaken::default().node(5).into();
+    let id0 = orders.next();
+    let id1 = orders.next();
+    assert!(id1 > id0);
+    assert_eq!(orders.decode(id1).1, 5);
+    assert_eq!(id1.cast::<()>().get(), u64::from(id1));
+    assert_eq!(format!("{} {:?}", id0, id0), format!("{} Id({})", id0.get(), id0.get()));
+}
+
+#[test]
+fn test_signed() {
+    let id: Id = Id::new(i64::MAX as u64);
+    assert_eq!(Id::<()>::from_signed(id.to_signed().unwrap()).unwrap(), id);
+    assert!(Id::<()>::new(1 << 63).to_signed().is_err());
+    assert!(Id::<()>::from_signed(-1).is_err());
+}
+
+#[test]
+fn test_conversions() {
+    let id: Id = Id::from(0x0102_0304_0506_0708);
+    let bytes: [u8; 8] = id.into();
+    assert_eq!(bytes, [1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(Id::<()>::try_from(&bytes[..]).unwrap(), id);
+    assert!(matches!(Id::<()>::try_from(&bytes[1..]), Err(Error::Length(7))));
+    assert_eq!(Id::<()>::try_from(String::from(id)).unwrap(), id);
+    assert!(matches!("12x".parse::<Id>(), Err(Error::Parse(_))));
+    assert_eq!(Id::<()>::try_from(i64::try_from(id).unwrap()).unwrap(), id);
+    assert!(matches!(i64::try_from(Id::<()>::new(u64::MAX)), Err(Error::SignBit(_))));
+    assert!(matches!(Id::<()>::try_from(-5i64), Err(Error::Negative(-5))));
+}
+
+#[test]
+fn test_display() {
+    let id: Id = Id::new(u64::MAX);
+    assert_eq!(id.to_string(), u64::MAX.to_string());
+    assert_eq!(format!("{:>6}|{:<6}|{:06}", Id::<()>::new(42), Id::<()>::new(42), Id::<()>::new(42)), "    42|42    |000042");
+}
@@ -0,0 +1,182 @@
+//! A persistent journal of the highest id issued per node
+//!
+//! Recording each node's high water mark on disk lets a restarted or
+//! replacement generator check, before issuing ids, that it is not about to
+//! reissue ids below or within what was already issued, such as after a
+//! clock was set back while it was down. Records are 16 bytes, the node id
+//! and the id in little endian, appended as marks rise and rewritten to one
+//! per node when the journal is opened.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::layout::Layout;
+use crate::sink::Sink;
+use crate::{Error, Flaken};
+
+/// High water marks of issued ids per node, kept in a file
+///
+/// A [`Sink`]: record into it with [`SinkMode::HighWater`](crate::SinkMode)
+/// to keep it current at one write per tick.
+///
+/// ```
+/// use flaken::{Flaken, Journal, SinkMode};
+///
+/// let path = std::env::temp_dir().join(format!("flaken-journal-doc-{}", std::process::id()));
+/// let flake = Flaken::default().node(3);
+/// let journal = Journal::open(&path, &flake).unwrap();
+/// let mut flake = flake.sink(journal, SinkMode::HighWater);
+/// let id = flake.next();
+/// drop(flake);
+///
+/// let journal = Journal::open(&path, &Flaken::default()).unwrap();
+/// assert_eq!(journal.high_water(3), Some(id));
+/// assert!(journal.check(id).is_err());
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    layout: Layout,
+    marks: HashMap<u64, u64>,
+    sync: bool,
+}
+
+impl Journal {
+    /// Open the journal at `path` for ids of `flake`'s layout, creating it
+    /// if needed
+    pub fn open<P: AsRef<Path>>(path: P, flake: &Flaken) -> io::Result<Journal> {
+        let path = path.as_ref().to_path_buf();
+        let mut marks = HashMap::new();
+        match File::open(&path) {
+            Ok(mut file) => {
+                let mut bytes = Vec::new();
+                file.read_to_end(&mut bytes)?;
+                // A torn final record from a crash is ignored
+                for record in bytes.chunks_exact(16) {
+                    let node = u64::from_le_bytes(record[..8].try_into().unwrap());
+                    let id = u64::from_le_bytes(record[8..].try_into().unwrap());
+                    let mark = marks.entry(node).or_insert(id);
+                    *mark = (*mark).max(id);
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        let mut journal = Journal {
+            file: OpenOptions::new().create(true).append(true).open(&path)?,
+            path,
+            layout: flake.layout,
+            marks,
+            sync: false,
+        };
+        journal.compact()?;
+        Ok(journal)
+    }
+
+    /// Sync each record to the disk with `sync_data` before recording
+    /// returns, off by default
+    ///
+    /// Costs a disk sync per rising mark, which with
+    /// [`SinkMode::HighWater`](crate::SinkMode) is one per tick.
+    pub fn sync(mut self, sync: bool) -> Journal {
+        self.sync = sync;
+        self
+    }
+
+    /// The highest id recorded for `node`, none when it has none
+    pub fn high_water(&self, node: u64) -> Option<u64> {
+        self.marks.get(&node).copied()
+    }
+
+    /// Check that `id` is above the high water mark of its node, failing
+    /// with [`Error::Issued`] when ids up to it may have been issued
+    pub fn check(&self, id: u64) -> Result<(), Error> {
+        let (_, node, _) = self.layout.decode(id);
+        match self.high_water(node) {
+            Some(mark) if id <= mark => Err(Error::Issued(id)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Rewrite the file to a single record per node
+    pub fn compact(&mut self) -> io::Result<()> {
+        let tmp = compact_path(&self.path);
+        let mut out = File::create(&tmp)?;
+        let mut nodes: Vec<(&u64, &u64)> = self.marks.iter().collect();
+        nodes.sort_unstable();
+        let bytes: Vec<u8> = nodes.iter().flat_map(|(&node, &id)| record(node, id)).collect();
+        out.write_all(&bytes)?;
+        out.sync_all()?;
+        std::fs::rename(&tmp, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+}
+
+/// The temporary file a journal at `path` is compacted into, appending to
+/// the name so journals that differ only by extension do not share one
+fn compact_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".compact");
+    PathBuf::from(tmp)
+}
+
+fn record(node: u64, id: u64) -> [u8; 16] {
+    let mut record = [0; 16];
+    record[..8].copy_from_slice(&node.to_le_bytes());
+    record[8..].copy_from_slice(&id.to_le_bytes());
+    record
+}
+
+impl Sink for Journal {
+    fn record(&mut self, id: u64) -> io::Result<()> {
+        let (_, node, _) = self.layout.decode(id);
+        if self.high_water(node).is_some_and(|mark| id <= mark) {
+            return Ok(());
+        }
+        self.marks.insert(node, id);
+        self.file.write_all(&record(node, id))?;
+        if self.sync {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_journal() {
+    let path = std::env::temp_dir().join(format!("flaken-journal-{}", std::process::id()));
+    let flake = Flaken::default();
+    let mut journal = Journal::open(&path, &flake).unwrap();
+    for &(ts, node) in [(5, 1), (9, 1), (7, 1), (3, 2)].iter() {
+        journal.record(flake.encode(flake.start_ts + ts, node, 0)).unwrap();
+    }
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 3 * 16);
+    drop(journal);
+
+    let journal = Journal::open(&path, &flake).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 2 * 16);
+    assert_eq!(journal.high_water(1), Some(flake.encode(flake.start_ts + 9, 1, 0)));
+    assert!(matches!(journal.check(flake.encode(flake.start_ts + 8, 1, 0)), Err(Error::Issued(_))));
+    assert!(journal.check(flake.encode(flake.start_ts + 8, 2, 0)).is_ok());
+    assert!(journal.check(flake.encode(flake.start_ts + 8, 3, 0)).is_ok());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_journal_compact_path() {
+    assert_eq!(compact_path(Path::new("a.x")), Path::new("a.x.compact"));
+    assert_ne!(compact_path(Path::new("a.x")), compact_path(Path::new("a.y")));
+
+    let path = std::env::temp_dir().join(format!("flaken-journal-sync-{}", std::process::id()));
+    let flake = Flaken::default();
+    let mut journal = Journal::open(&path, &flake).unwrap().sync(true);
+    let id = flake.encode(flake.start_ts + 1, 1, 0);
+    journal.record(id).unwrap();
+    assert_eq!(Journal::open(&path, &flake).unwrap().high_water(1), Some(id));
+    std::fs::remove_file(&path).unwrap();
+}
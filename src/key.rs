@@ -0,0 +1,106 @@
+//! Byte keys for ordered key value stores
+//!
+//! Stores such as RocksDB, FoundationDB, and Kafka compare keys as bytes.
+//! Written big endian, ids compare as bytes in the same order as numbers,
+//! so keys of a prefix followed by the id sort chronologically.
+
+use crate::Flaken;
+
+/// Builds byte keys whose order is the chronological order of their ids
+///
+/// ```
+/// use flaken::KeyBuilder;
+///
+/// let keys = KeyBuilder::new(b"user/");
+/// let key = keys.key(258);
+/// assert_eq!(key, b"user/\0\0\0\0\0\0\x01\x02");
+/// assert_eq!(keys.id_of(&key), Some(258));
+/// assert!(keys.key(1) < keys.key(256));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeyBuilder {
+    prefix: Vec<u8>,
+    bucket_ms: Option<u64>,
+}
+
+impl KeyBuilder {
+    /// Build keys starting with `prefix`
+    pub fn new(prefix: &[u8]) -> KeyBuilder {
+        KeyBuilder {
+            prefix: prefix.to_vec(),
+            bucket_ms: None,
+        }
+    }
+
+    /// Put the time bucket of each id, `bucket_ms` milliseconds wide, between
+    /// the prefix and the id
+    ///
+    /// Keys of a bucket are then contiguous, so a time range may be scanned
+    /// or dropped by bucket. Bucketed keys are built with
+    /// [`key_for`](#method.key_for), which needs the generator to decode the
+    /// id's timestamp.
+    pub fn time_bucket(mut self, bucket_ms: u64) -> KeyBuilder {
+        assert!(bucket_ms > 0);
+        self.bucket_ms = Some(bucket_ms);
+        self
+    }
+
+    /// The key of an id, without a time bucket
+    pub fn key(&self, id: u64) -> Vec<u8> {
+        let mut key = Vec::with_capacity(self.prefix.len() + 8);
+        key.extend_from_slice(&self.prefix);
+        key.extend_from_slice(&id.to_be_bytes());
+        key
+    }
+
+    /// The key of an id made by `flake`, with the time bucket if set
+    pub fn key_for(&self, flake: &Flaken, id: u64) -> Vec<u8> {
+        let bucket_ms = match self.bucket_ms {
+            Some(bucket_ms) => bucket_ms,
+            None => return self.key(id),
+        };
+        let (ts, _, _) = flake.decode(id);
+        let mut key = Vec::with_capacity(self.prefix.len() + 16);
+        key.extend_from_slice(&self.prefix);
+        key.extend_from_slice(&(ts / bucket_ms).to_be_bytes());
+        key.extend_from_slice(&id.to_be_bytes());
+        key
+    }
+
+    /// The prefix of all keys in the time bucket holding `ts`, in
+    /// milliseconds since the unix epoch, for scanning a bucket
+    pub fn bucket_prefix(&self, ts: u64) -> Vec<u8> {
+        let mut key = self.prefix.clone();
+        if let Some(bucket_ms) = self.bucket_ms {
+            key.extend_from_slice(&(ts / bucket_ms).to_be_bytes());
+        }
+        key
+    }
+
+    /// The id at the end of a key built by this builder
+    pub fn id_of(&self, key: &[u8]) -> Option<u64> {
+        let extra = if self.bucket_ms.is_some() { 8 } else { 0 };
+        if key.len() != self.prefix.len() + extra + 8 || !key.starts_with(&self.prefix) {
+            return None;
+        }
+        let mut be = [0; 8];
+        be.copy_from_slice(&key[key.len() - 8..]);
+        Some(u64::from_be_bytes(be))
+    }
+}
+
+#[test]
+fn test_keys_sort_chronologically() {
+    let mut flake = Flaken::default();
+    let keys = KeyBuilder::new(b"orders:").time_bucket(3_600_000);
+    let ids: Vec<u64> = (0..1000).map(|_| flake.next()).collect();
+    let built: Vec<Vec<u8>> = ids.iter().map(|&id| keys.key_for(&flake, id)).collect();
+    let mut sorted = built.clone();
+    sorted.sort();
+    assert_eq!(sorted, built);
+    for (key, &id) in built.iter().zip(ids.iter()) {
+        assert_eq!(keys.id_of(key), Some(id));
+        assert!(key.starts_with(&keys.bucket_prefix(flake.decode(id).0)));
+    }
+    assert_eq!(keys.id_of(b"orders:"), None);
+}
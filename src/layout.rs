@@ -0,0 +1,339 @@
+//! How the fields of an id are laid out in its 64 bits
+
+use std::error;
+use std::fmt;
+use std::time::Duration;
+
+use crate::{bitmask, Epoch, Error};
+
+/// Bit layout of ids, from the most significant bit: reserved, version,
+/// timestamp, node, and sequence
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Layout {
+    pub(crate) epoch: u64,
+    pub(crate) reserved_bits: u64,
+    pub(crate) version_bits: u64,
+    pub(crate) version: u64,
+    pub(crate) ts_bits: u64,
+    pub(crate) node_bits: u64,
+    pub(crate) seq_bits: u64,
+    /// Milliseconds per unit of the timestamp field
+    pub(crate) tick_ms: u64,
+}
+
+impl Layout {
+    /// 42 timestamp bits from 2013-01-01T00:00:00Z, 10 node bits, and 12 sequence bits
+    pub(crate) fn new() -> Layout {
+        Layout {
+            epoch: Epoch::Flaken2013.as_millis(),
+            reserved_bits: 0,
+            version_bits: 0,
+            version: 0,
+            ts_bits: 42,
+            node_bits: 10,
+            seq_bits: 12,
+            tick_ms: 1,
+        }
+    }
+
+    /// Set the timestamp and node widths, the sequence takes the remaining bits
+    pub(crate) fn set_bitwidths(&mut self, ts_bits: u64, node_bits: u64) {
+        if let Err(err) = self.try_set_bitwidths(ts_bits, node_bits) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Set the timestamp and node widths, leaving the layout as it was on failure
+    pub(crate) fn try_set_bitwidths(&mut self, ts_bits: u64, node_bits: u64) -> Result<(), LayoutError> {
+        let mut layout = *self;
+        layout.ts_bits = ts_bits;
+        layout.node_bits = node_bits;
+        layout.fit_seq()?;
+        *self = layout;
+        Ok(())
+    }
+
+    /// Set the reserved width, taken from the sequence
+    pub(crate) fn set_reserved_bits(&mut self, reserved_bits: u64) {
+        self.reserved_bits = reserved_bits;
+        if let Err(err) = self.fit_seq() {
+            panic!("{}", err);
+        }
+    }
+
+    /// Set the version width, taken from the sequence
+    pub(crate) fn set_version_bits(&mut self, version_bits: u64) {
+        assert!(self.version <= field_max(version_bits));
+        self.version_bits = version_bits;
+        if let Err(err) = self.fit_seq() {
+            panic!("{}", err);
+        }
+    }
+
+    /// Give the sequence whatever bits the other fields leave, possibly none
+    fn fit_seq(&mut self) -> Result<(), LayoutError> {
+        if self.ts_bits == 0 {
+            return Err(LayoutError::NoTimestampBits);
+        }
+        let used = [self.reserved_bits, self.version_bits, self.ts_bits, self.node_bits]
+            .iter()
+            .fold(0u64, |used, &bits| used.saturating_add(bits));
+        if used > 64 {
+            return Err(LayoutError::TooWide(used));
+        }
+        self.seq_bits = 64 - used;
+        Ok(())
+    }
+
+    pub(crate) fn set_version(&mut self, version: u64) {
+        assert!(version <= field_max(self.version_bits));
+        self.version = version;
+    }
+
+    /// Set the time one unit of the timestamp field stands for
+    pub(crate) fn set_tick(&mut self, tick: Duration) {
+        let tick_ms = tick.as_millis();
+        assert!(tick_ms > 0 && Duration::from_millis(tick_ms as u64) == tick, "ticks must be whole milliseconds");
+        self.tick_ms = tick_ms as u64;
+    }
+
+    /// The time one unit of the timestamp field stands for
+    pub(crate) fn tick(&self) -> Duration {
+        Duration::from_millis(self.tick_ms)
+    }
+
+    /// The start of the tick containing `ts`, in milliseconds since the unix epoch
+    pub(crate) fn tick_floor(&self, ts: u64) -> u64 {
+        ts - (ts - self.epoch) % self.tick_ms
+    }
+
+    /// The latest timestamp the layout can encode, in milliseconds since the unix epoch
+    pub(crate) fn max_ts(&self) -> u64 {
+        let span = (field_max(self.ts_bits) as u128 + 1) * self.tick_ms as u128 - 1;
+        self.epoch.saturating_add(span.min(u64::MAX as u128) as u64)
+    }
+
+    /// The largest sequence value of a tick
+    pub(crate) fn max_seq(&self) -> u64 {
+        field_max(self.seq_bits)
+    }
+
+    pub(crate) fn masks(&self) -> Masks {
+        let seq_shift = self.seq_bits;
+        let ts_shift = self.node_bits + self.seq_bits;
+        let version_shift = ts_shift + self.ts_bits;
+        let version = field_mask(version_shift, self.version_bits);
+        let reserved = field_mask(64 - self.reserved_bits, self.reserved_bits);
+        Masks {
+            epoch: self.epoch,
+            tick: self.tick_ms,
+            ts_shift,
+            seq_shift,
+            version_shift,
+            version_value: if self.version_bits == 0 { 0 } else { (self.version << version_shift) & version },
+            version,
+            reserved,
+            ts: bitmask(ts_shift) & !(version | reserved),
+            node: field_mask(seq_shift, self.node_bits),
+            seq: field_mask(0, self.seq_bits),
+        }
+    }
+
+    /// Encode an id, masking every field to its width
+    pub(crate) fn encode(&self, ts: u64, node: u64, seq: u64) -> u64 {
+        assert!(ts >= self.epoch);
+        self.masks().encode(ts, node, seq)
+    }
+
+    /// Encode an id, failing on any field which does not fit the layout
+    pub(crate) fn try_encode(&self, ts: u64, node: u64, seq: u64) -> Result<u64, Error> {
+        if ts < self.epoch || ts > self.max_ts() {
+            Err(Error::OutOfRange(ts))
+        } else if node > field_max(self.node_bits) {
+            Err(Error::NodeOverflow(node))
+        } else if seq > self.max_seq() {
+            Err(Error::SeqOverflow(seq))
+        } else {
+            Ok(self.masks().encode(ts, node, seq))
+        }
+    }
+
+    pub(crate) fn decode(&self, id: u64) -> (u64, u64, u64) {
+        self.masks().decode(id)
+    }
+
+    pub(crate) fn version_of(&self, id: u64) -> u64 {
+        let masks = self.masks();
+        if self.version_bits == 0 {
+            0
+        } else {
+            (id & masks.version) >> masks.version_shift
+        }
+    }
+
+    /// Check that the reserved bits of an id are clear
+    pub(crate) fn validate(&self, id: u64) -> Result<(), Error> {
+        if id & self.masks().reserved == 0 {
+            Ok(())
+        } else {
+            Err(Error::ReservedBits(id))
+        }
+    }
+}
+
+/// Why a layout is invalid
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayoutError {
+    /// The timestamp field has no bits
+    NoTimestampBits,
+    /// The fields take this many bits, more than the 64 of an id
+    TooWide(u64),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LayoutError::NoTimestampBits => write!(f, "layouts need at least one timestamp bit"),
+            LayoutError::TooWide(bits) => write!(f, "layout fields take {} bits, more than the 64 of an id", bits),
+        }
+    }
+}
+
+impl error::Error for LayoutError {}
+
+/// Shifts and masks of a layout, hoisted out of loops
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Masks {
+    pub(crate) epoch: u64,
+    /// Milliseconds per unit of the timestamp field
+    pub(crate) tick: u64,
+    pub(crate) ts_shift: u64,
+    pub(crate) seq_shift: u64,
+    pub(crate) version_shift: u64,
+    /// The version already shifted into place
+    pub(crate) version_value: u64,
+    pub(crate) version: u64,
+    pub(crate) reserved: u64,
+    pub(crate) ts: u64,
+    pub(crate) node: u64,
+    pub(crate) seq: u64,
+}
+
+impl Masks {
+    /// Encode an id without range checks, see [`Layout::try_encode`]
+    #[inline(always)]
+    pub(crate) fn encode(&self, ts: u64, node: u64, seq: u64) -> u64 {
+        self.version_value
+            | ((self.ticks(ts.wrapping_sub(self.epoch)) << self.ts_shift) & self.ts)
+            | ((node << self.seq_shift) & self.node)
+            | (seq & self.seq)
+    }
+
+    /// Whole ticks in `ms` milliseconds, skipping the division for millisecond ticks
+    #[inline(always)]
+    fn ticks(&self, ms: u64) -> u64 {
+        if self.tick == 1 {
+            ms
+        } else {
+            ms / self.tick
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn decode(&self, id: u64) -> (u64, u64, u64) {
+        (
+            ((id & self.ts) >> self.ts_shift).saturating_mul(self.tick).saturating_add(self.epoch),
+            (id & self.node) >> self.seq_shift,
+            id & self.seq,
+        )
+    }
+}
+
+/// The largest value fitting in `bits` bits
+pub(crate) fn field_max(bits: u64) -> u64 {
+    field_mask(0, bits)
+}
+
+/// Mask of `bits` bits starting at bit `shift`
+pub(crate) fn field_mask(shift: u64, bits: u64) -> u64 {
+    if bits == 0 {
+        0
+    } else {
+        (u64::MAX >> (64 - bits)) << shift
+    }
+}
+
+#[test]
+fn test_field_mask() {
+    assert_eq!(field_mask(0, 0), 0);
+    assert_eq!(field_mask(4, 4), 0xF0);
+    assert_eq!(field_mask(0, 64), u64::MAX);
+    assert_eq!(field_mask(60, 4), 0xF000_0000_0000_0000);
+}
+
+#[test]
+fn test_version_field() {
+    let mut layout = Layout::new();
+    layout.set_bitwidths(40, 10);
+    layout.set_version_bits(2);
+    layout.set_version(2);
+    assert_eq!(layout.seq_bits, 12);
+    let id = layout.encode(layout.epoch + (1 << 40) - 1, 1023, 4095);
+    assert_eq!(id, 0b10 << 62 | (u64::MAX >> 2));
+    assert_eq!(layout.version_of(id), 2);
+    assert_eq!(layout.decode(id), (layout.epoch + (1 << 40) - 1, 1023, 4095));
+}
+
+#[test]
+fn test_reserved_bits() {
+    let mut layout = Layout::new();
+    layout.set_reserved_bits(2);
+    layout.set_version_bits(1);
+    layout.set_version(1);
+    assert_eq!(layout.seq_bits, 9);
+    let id = layout.encode(layout.epoch + (1 << 42) - 1, 1023, 511);
+    assert_eq!(id, u64::MAX >> 2);
+    assert_eq!(layout.version_of(id), 1);
+    assert!(layout.validate(id).is_ok());
+    assert!(layout.validate(id | 1 << 62).is_err());
+    assert!(layout.validate(id | 1 << 63).is_err());
+}
+
+#[test]
+fn test_try_encode() {
+    let mut layout = Layout::new();
+    layout.set_bitwidths(8, 2);
+    assert_eq!(layout.max_ts(), layout.epoch + 255);
+    assert!(layout.try_encode(layout.max_ts(), 3, layout.max_seq()).is_ok());
+    assert!(matches!(layout.try_encode(layout.max_ts() + 1, 0, 0), Err(Error::OutOfRange(_))));
+    assert!(matches!(layout.try_encode(layout.epoch - 1, 0, 0), Err(Error::OutOfRange(_))));
+    assert!(matches!(layout.try_encode(layout.epoch, 4, 0), Err(Error::NodeOverflow(4))));
+    assert!(matches!(layout.try_encode(layout.epoch, 0, 1 << 54), Err(Error::SeqOverflow(_))));
+
+    layout.epoch = u64::MAX - 10;
+    assert_eq!(layout.max_ts(), u64::MAX);
+    assert_eq!(layout.decode(u64::MAX).0, u64::MAX);
+}
+
+#[test]
+#[should_panic(expected = "more than the 64 of an id")]
+fn test_oversized_fields() {
+    Layout::new().set_bitwidths(u64::MAX, 2);
+}
+
+#[test]
+fn test_zero_width_fields() {
+    let mut layout = Layout::new();
+    layout.set_bitwidths(42, 0);
+    assert_eq!(layout.seq_bits, 22);
+    let id = layout.encode(layout.epoch + 3, 0, 5);
+    assert_eq!(id, 3 << 22 | 5);
+    assert_eq!(layout.decode(id), (layout.epoch + 3, 0, 5));
+
+    layout.set_bitwidths(44, 20);
+    assert_eq!(layout.seq_bits, 0);
+    assert_eq!(layout.max_seq(), 0);
+    let id = layout.encode(layout.epoch + 3, 7, 0);
+    assert_eq!(id, 3 << 20 | 7);
+    assert_eq!(layout.decode(id), (layout.epoch + 3, 7, 0));
+}
@@ -0,0 +1,136 @@
+//! Handing the rest of a tick's sequence values to one caller at a time
+//!
+//! A [`TickLease`] takes the sequence values left in the current tick in one
+//! go, so a tight loop generating ids only increments an id instead of
+//! reading the clock for each one.
+
+use crate::{fence, metrics, Flaken, SinkMode};
+
+/// The sequence values left in the current tick of a generator
+///
+/// Ids are taken by iterating, each one the previous plus one, without
+/// reading the clock, waiting for the maximum rate, or checking clock skew.
+/// Dropping the lease hands the values not taken back to the generator.
+///
+/// ```
+/// use flaken::Flaken;
+///
+/// let mut flake = Flaken::default().bitwidths(42, 14);
+/// let ids: Vec<u64> = flake.lease_tick().collect();
+/// assert!(!ids.is_empty() && ids.len() <= 256);
+/// assert!(ids.windows(2).all(|w| w[1] == w[0] + 1));
+/// assert!(flake.next() > *ids.last().unwrap());
+/// ```
+#[derive(Debug)]
+pub struct TickLease<'a> {
+    flake: &'a mut Flaken,
+    first: u64,
+    next: u64,
+    end: u64,
+}
+
+impl Flaken {
+    /// Lease the sequence values left in the current tick, waiting for the
+    /// next tick if none are left
+    ///
+    /// The lease holds at least one id. Sequence steps do not apply within
+    /// it, and an id not taken before the lease is dropped stays unused
+    /// when no id was taken at all. Panics as [`next`](#method.next) does.
+    ///
+    /// With [`strict_order`](#method.strict_order) set the lease raises the
+    /// fence above all of its ids up front, so they stay ordered before any
+    /// id returned elsewhere while it is held. It holds only its first id
+    /// when a larger id was returned elsewhere already.
+    pub fn lease_tick(&mut self) -> TickLease<'_> {
+        let first = self.next();
+        let max_seq = self.max_seq();
+        let mut end = first - (first & max_seq) + max_seq + 1;
+        if self.strict && !fence::publish(self.fence(), end - 1) {
+            end = first + 1;
+        }
+        TickLease { flake: self, first, next: first, end }
+    }
+}
+
+impl TickLease<'_> {
+    /// Ids left in the lease
+    pub fn remaining(&self) -> u64 {
+        self.end - self.next
+    }
+}
+
+impl Iterator for TickLease<'_> {
+    type Item = u64;
+
+    #[inline]
+    fn next(&mut self) -> Option<u64> {
+        if self.next == self.end {
+            return None;
+        }
+        let id = self.next;
+        self.next += 1;
+        Some(id)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.remaining() as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for TickLease<'_> {}
+
+impl Drop for TickLease<'_> {
+    fn drop(&mut self) {
+        // The generator already accounted for the first id
+        if self.next <= self.first + 1 {
+            return;
+        }
+        let flake = &mut *self.flake;
+        let last = self.next - 1;
+        flake.seq = flake.seq.max((self.next - self.first) + (self.first & flake.max_seq()));
+        if let Some((ref mut sink, SinkMode::Every)) = flake.sink {
+            for id in self.first + 1..self.next {
+                if let Err(_err) = sink.record(id) {
+                    trace_event!(warn, node = flake.node, id, error = %_err, "failed to record issued id");
+                }
+            }
+        }
+        flake.last_id = last;
+        flake.issued += last - self.first;
+        metrics::ids_generated(last - self.first);
+    }
+}
+
+#[test]
+fn test_tick_lease() {
+    let mut flake = Flaken::default().bitwidths(41, 12);
+    let mut lease = flake.lease_tick();
+    let first = lease.next().unwrap();
+    let second = lease.next().unwrap();
+    assert_eq!(second, first + 1);
+    let left = lease.remaining();
+    drop(lease);
+    let after = flake.next();
+    assert!(after > second);
+    let (ts, _, seq) = flake.decode(after);
+    if ts == flake.decode(first).0 {
+        // Still the same tick, picking up right after the lease
+        assert_eq!(seq, flake.decode(second).2 + 1);
+        assert!(left > 0);
+    }
+    assert_eq!(flake.status().issued, 3);
+}
+
+#[test]
+fn test_tick_lease_strict() {
+    let mut leasing = Flaken::default().bitwidths(41, 12).node(1).strict_order(true);
+    let mut other = Flaken::default().bitwidths(41, 12).node(0).strict_order(true);
+    let mut lease = leasing.lease_tick();
+    let first = lease.next().unwrap();
+    // Node 0 orders below the lease within the tick, so it waits the tick out
+    let between = other.next();
+    let rest: Vec<u64> = lease.collect();
+    assert!(between > first);
+    assert!(rest.iter().all(|&id| id < between));
+}
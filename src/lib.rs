@@ -34,9 +34,151 @@
 //! assert_eq!(seq0, 0);
 //! assert_eq!(flake.encode(ts0, node0, seq0), id0);
 //! ```
+//!
+//! Small services may instead [`init`](fn.init.html) a process-wide
+//! generator once and call [`next`](fn.next.html) anywhere.
+//!
+//! With the `tracing` feature, notable conditions such as sequence
+//! exhaustion, failed validation, and node lease renewal are emitted as
+//! tracing events with the node id attached. With the `metrics` feature,
+//! generators record these metrics through the `metrics` crate facade:
+//!
+//! - `flaken_ids_generated_total`, a counter of generated ids
+//! - `flaken_sequence_exhaustion_waits_total`, a counter of waits for the
+//!   next tick after the sequence values of a tick ran out
+//! - `flaken_wait_duration_seconds`, a histogram of those waits
+//! - `flaken_clock_skew_detected_total`, a counter of wall clock divergence
+//!   beyond an [`on_skew`](struct.Flaken.html#method.on_skew) threshold,
+//!   only checked by generators with `on_skew` set
 
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+/// Emit a tracing event at `$level` when the `tracing` feature is enabled
+macro_rules! trace_event {
+    ($level:ident, $($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::$level!($($arg)*);
+    };
+}
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+use crate::anchor::Anchor;
+use crate::clock::{Clock, MonotonicClock, WallClock};
+use crate::layout::Layout;
+use crate::limit::TokenBucket;
+use crate::rand::Rng;
+use crate::rotate::NodeRotation;
+use crate::skew::{wall_ms, SkewWatch};
+
+mod anchor;
+#[cfg(feature = "arrow")]
+mod arrow;
+mod atomic;
+pub mod audit;
+mod backfill;
+mod batch;
+mod bucket;
+#[cfg(feature = "bson")]
+mod bson;
+mod cipher;
+pub mod clock;
+mod compat;
+#[cfg(feature = "csv")]
+pub mod csv;
+mod decoded;
+#[cfg(feature = "diesel")]
+mod diesel;
+mod epoch;
+mod error;
+#[cfg(any(feature = "axum", feature = "actix-web"))]
+mod extract;
+mod fence;
+pub mod format;
+mod global;
+#[cfg(feature = "async-graphql")]
+mod graphql;
+mod id;
+mod journal;
+mod key;
+mod layout;
+mod lease;
+mod limit;
+mod metrics;
+mod migrate;
+pub mod node;
+mod overflow;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod prefetch;
+mod rand;
+mod rotate;
+#[cfg(feature = "redis")]
+mod redis;
+#[cfg(feature = "rusqlite")]
+mod rusqlite;
+mod scramble;
+#[cfg(feature = "simulation")]
+pub mod simulation;
+mod service;
+mod sink;
+mod skew;
+mod sleep;
+mod status;
+#[cfg(feature = "serde")]
+mod string;
+#[cfg(feature = "sea-orm")]
+mod sea_orm;
+#[cfg(feature = "sqlx")]
+mod sqlx;
+mod throughput;
+#[cfg(feature = "tokio")]
+mod tokio;
+#[cfg(feature = "tower")]
+mod tower;
+#[cfg(feature = "vectors")]
+pub mod vectors;
+mod wait;
+pub mod wellknown;
+
+pub use crate::atomic::AtomicFlaken;
+pub use crate::backfill::{BackfillFlaken, Spread};
+pub use crate::bucket::bucket_of;
+pub use crate::cipher::IdCipher;
+pub use crate::compat::{CompatReport, LayoutField, Mismatch};
+pub use crate::decoded::DecodedId;
+#[cfg(feature = "serde")]
+pub use crate::decoded::Rfc3339DecodedId;
+pub use crate::epoch::Epoch;
+pub use crate::error::Error;
+#[cfg(any(feature = "axum", feature = "actix-web"))]
+pub use crate::extract::{Base62Path, IdPath, IdRejection};
+pub use crate::global::{global, init, next};
+pub use crate::id::{Id, TypedFlaken};
+pub use crate::journal::Journal;
+pub use crate::key::KeyBuilder;
+pub use crate::layout::LayoutError;
+pub use crate::lease::TickLease;
+pub use crate::migrate::Migration;
+pub use crate::overflow::TsOverflow;
+pub use crate::prefetch::PrefetchedFlaken;
+pub use crate::scramble::Scrambler;
+pub use crate::service::{IdHandle, IdService};
+pub use crate::sink::{Durability, FileSink, Sink, SinkMode};
+pub use crate::skew::Skew;
+pub use crate::sleep::Sleep;
+pub use crate::status::Status;
+pub use crate::throughput::Throughput;
+#[cfg(feature = "tokio")]
+pub use crate::tokio::{per_worker, TokioSleep};
+#[cfg(feature = "tower")]
+pub use crate::tower::AllocateRequest;
+pub use crate::wait::WaitStrategy;
+
 #[cfg(test)]
 use std::thread;
 
@@ -45,15 +187,53 @@ use std::thread;
 #[derive(Debug)]
 pub struct Flaken {
     node: u64,
-    epoch: u64,
-    bitwidths: (u64, u64, u64),
+    layout: Layout,
     seq: u64,
     start_ts: u64,
-    start_instant: Instant,
+    clock: Arc<dyn Clock>,
+    start: Duration,
     duration: u64,
+    wait: WaitStrategy,
+    limit: Option<TokenBucket>,
+    step: Option<(u64, Rng)>,
+    max_skew: Duration,
+    issued: u64,
+    rollovers: u64,
+    last_id: u64,
+    sink: Option<(Box<dyn Sink>, SinkMode)>,
+    skew: Option<SkewWatch>,
+    ticks: u64,
+    saturated: u64,
+    rotation: Option<NodeRotation>,
+    anchor: Anchor,
+    strict: bool,
+    /// Ticks a saturated generator may run ahead of its clock
+    ahead: u64,
+    real_time: bool,
+    /// The tick a real-time generator found ahead of the wall clock
+    held: Option<u64>,
+    overflow: TsOverflow,
+    epochs: Option<Arc<[u64]>>,
+    /// The strict ordering fence and the layout it was looked up for
+    fence: Option<(Layout, Arc<AtomicU64>)>,
+}
+
+impl Drop for Flaken {
+    fn drop(&mut self) {
+        let release_ts = self.release_ts();
+        if let Some(ref mut rotation) = self.rotation {
+            rotation.hold_leases(release_ts);
+        }
+        if let Some((ref mut sink, SinkMode::HighWater)) = self.sink {
+            if self.issued > 0 {
+                let _ = sink.record(self.last_id);
+            }
+        }
+    }
 }
 
 trait AsMillis {
+    #[allow(clippy::wrong_self_convention)]
     fn as_millis(self) -> u64;
 }
 
@@ -68,24 +248,87 @@ impl Flaken {
     /// node: 0
     /// epoch: 2013-01-01T00:00:00Z in milliseconds since the unix epoch
     /// bitwidths (42 timestamp bits, 10 id bits, 12 sequence bits)
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Flaken {
         let since_unix = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
         let ts = since_unix.as_millis();
-        let instant = Instant::now();
+        let clock = MonotonicClock::new();
+        let start = clock.now();
         Flaken {
             node: 0,
             seq: 0,
-            epoch: 1356998400000,
-            bitwidths: (42, 10, 12),
+            layout: Layout::new(),
             start_ts: ts,
-            start_instant: instant,
+            clock: Arc::new(clock),
+            start,
             duration: 0,
+            wait: WaitStrategy::default(),
+            limit: None,
+            step: None,
+            max_skew: Duration::from_secs(60),
+            issued: 0,
+            rollovers: 0,
+            last_id: 0,
+            sink: None,
+            skew: None,
+            ticks: 0,
+            saturated: 0,
+            rotation: None,
+            anchor: Anchor::default(),
+            strict: false,
+            ahead: 0,
+            real_time: false,
+            held: None,
+            overflow: TsOverflow::Wrap,
+            epochs: None,
+            fence: None,
         }
     }
 
-    /// Set the epoch of a Flaken generator
-    pub fn epoch(mut self, epoch: u64) -> Flaken {
-        self.epoch = epoch;
+    /// Build a generator counting whole seconds, laid out like Baidu's UidGenerator
+    ///
+    /// From the most significant bit, a clear sign bit, 30 timestamp bits of
+    /// seconds since 2016-05-20T00:00:00Z, 20 node bits, and 13 sequence bits.
+    /// That lasts until 2050 and allows 8192 ids per node per second, for
+    /// systems favoring a long lifetime and bursts over millisecond ordering.
+    /// UidGenerator's 28 timestamp bits ran out in 2024, so two of its node
+    /// bits moved to the timestamp.
+    pub fn seconds() -> Flaken {
+        Flaken::default()
+            .epoch(1463702400000)
+            .tick(Duration::from_secs(1))
+            .bitwidths(30, 20)
+            .reserved_bits(1)
+    }
+
+    /// Build a generator of 40 bit ids for URL slugs, at most 7 base62 digits
+    ///
+    /// From the most significant bit, 24 clear reserved bits, 30 timestamp
+    /// bits of seconds since 2024-01-01T00:00:00Z, 4 node bits, and 6
+    /// sequence bits. That lasts until 2058 and allows 64 ids per node per
+    /// second, enough for share links while keeping them roughly in the
+    /// order they were made.
+    ///
+    /// ```
+    /// use flaken::format::{Format, Radix};
+    /// use flaken::Flaken;
+    ///
+    /// let mut flake = Flaken::short();
+    /// let slug = Format::new(Radix::Base62).encode(flake.next());
+    /// assert!(slug.len() <= 7);
+    /// ```
+    pub fn short() -> Flaken {
+        Flaken::default()
+            .epoch(1704067200000)
+            .tick(Duration::from_secs(1))
+            .bitwidths(30, 4)
+            .reserved_bits(24)
+    }
+
+    /// Set the epoch of a Flaken generator, an [`Epoch`] or milliseconds
+    /// since the unix epoch
+    pub fn epoch<E: Into<Epoch>>(mut self, epoch: E) -> Flaken {
+        self.layout.epoch = epoch.into().as_millis();
         self
     }
 
@@ -96,57 +339,619 @@ impl Flaken {
     }
 
     /// Set the bitwidths of a Flaken generator
+    ///
+    /// The sequence takes the bits left over after the version, timestamp,
+    /// and node bits. Either the node or the sequence may have no bits, for
+    /// a single writer or for at most one id per millisecond.
+    ///
+    /// Panics when the widths do not fit, see
+    /// [`try_bitwidths`](#method.try_bitwidths).
     pub fn bitwidths(mut self, ts_bits: u64, node_bits: u64) -> Flaken {
-        assert!(ts_bits + node_bits < 64);
-        self.bitwidths = (ts_bits, node_bits, 64-(ts_bits+node_bits));
+        self.layout.set_bitwidths(ts_bits, node_bits);
+        self
+    }
+
+    /// Set the time one unit of the timestamp field stands for, in whole
+    /// milliseconds
+    ///
+    /// Timestamps passed to and returned from encoding and decoding stay in
+    /// milliseconds since the unix epoch, rounded down to the start of their
+    /// tick. A generator issues up to `max_seq + 1` ids per tick, and ticks
+    /// are counted from the epoch.
+    pub fn tick(mut self, tick: Duration) -> Flaken {
+        self.layout.set_tick(tick);
+        self
+    }
+
+    /// Set the bitwidths of a Flaken generator, failing with
+    /// [`Error::Layout`] when there are no timestamp bits or the fields do
+    /// not fit in 64 bits
+    pub fn try_bitwidths(mut self, ts_bits: u64, node_bits: u64) -> Result<Flaken, Error> {
+        self.layout.try_set_bitwidths(ts_bits, node_bits)?;
+        Ok(self)
+    }
+
+    /// Reserve the top `bits` bits of ids as always zero, taken from the sequence
+    ///
+    /// Reserved bits leave headroom for later layout changes, and are cleared
+    /// by encoding and checked by [`validate`](#method.validate), which makes
+    /// them a cheap corruption check.
+    pub fn reserved_bits(mut self, bits: u64) -> Flaken {
+        self.layout.set_reserved_bits(bits);
+        self
+    }
+
+    /// Reserve `bits` bits of ids for a version, below any reserved bits and
+    /// taken from the sequence
+    ///
+    /// A version fixed per generator lets ids minted under different layouts
+    /// be told apart when decoding, see [`version_of`](#method.version_of).
+    pub fn version_bits(mut self, bits: u64) -> Flaken {
+        self.layout.set_version_bits(bits);
+        self
+    }
+
+    /// Set the version embedded in every id, which must fit in the version bits
+    pub fn version(mut self, version: u64) -> Flaken {
+        self.layout.set_version(version);
+        self
+    }
+
+    /// Set how far ahead of the generator's clock the timestamp of an id may
+    /// be before [`decode_checked`](#method.decode_checked) rejects it, and
+    /// timestamps before [`try_encode`](#method.try_encode) rejects them
+    ///
+    /// Defaults to one minute, enough for clock differences between nodes.
+    pub fn max_future_skew(mut self, skew: Duration) -> Flaken {
+        self.max_skew = skew;
+        self
+    }
+
+    /// Record issued ids into `sink`, every id or the last of each tick
+    ///
+    /// The log is only written by [`next`](#method.next) and
+    /// [`try_next`](#method.try_next), an [`AtomicFlaken`] does not use it.
+    pub fn sink<S: Sink + 'static>(mut self, sink: S, mode: SinkMode) -> Flaken {
+        self.sink = Some((Box::new(sink), mode));
+        self
+    }
+
+    /// Set how the generator waits for the next millisecond once the
+    /// sequence values of the current one are used up
+    pub fn wait_strategy(mut self, wait: WaitStrategy) -> Flaken {
+        self.wait = wait;
+        self
+    }
+
+    /// Set the clock measuring the time elapsed since the generator was built
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Flaken {
+        self.start = clock.now().saturating_sub(self.uptime());
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Read the wall clock on every id, see [`WallClock`](clock/struct.WallClock.html)
+    ///
+    /// Ids then follow the wall clock, through NTP steps forward and without
+    /// needing [`smear`](#method.smear) or
+    /// [`reanchor_after`](#method.reanchor_after). While the wall clock is
+    /// behind the latest id's timestamp, ids reuse that timestamp and then
+    /// wait for the wall clock to catch up.
+    pub fn wall_clock(self) -> Flaken {
+        self.clock(WallClock::new())
+    }
+
+    /// Limit the generator to at most `ids_per_sec` ids per second
+    ///
+    /// Bursts of up to a tenth of a second worth of ids are allowed, see
+    /// [`max_burst`](#method.max_burst) to change that.
+    pub fn max_rate(mut self, ids_per_sec: u64) -> Flaken {
+        self.limit = Some(TokenBucket::new(ids_per_sec, std::cmp::max(1, ids_per_sec / 10)));
+        self
+    }
+
+    /// Set how many ids may be generated in a burst when rate limited
+    pub fn max_burst(mut self, ids: u64) -> Flaken {
+        self.limit
+            .as_mut()
+            .expect("max_burst requires max_rate to be set first")
+            .set_burst(ids);
         self
     }
 
+    /// Advance the sequence by a random step of `1..=max_step` between ids
+    ///
+    /// Ids of the same millisecond are then no longer contiguous, so they
+    /// can not be enumerated by counting up from a known id. They stay unique
+    /// and increasing, at the cost of fewer ids fitting in a millisecond.
+    pub fn random_step(mut self, max_step: u64) -> Flaken {
+        assert!(max_step > 0);
+        self.step = if max_step == 1 { None } else { Some((max_step, Rng::new())) };
+        self
+    }
+
+    /// Set whether ids are ordered across every strictly ordered generator
+    /// of the same layout in the process
+    ///
+    /// Each id returned is then larger than any id a strictly ordered
+    /// generator of the layout, on any thread or node, returned before. An
+    /// id which would not be is discarded and the generator waits for the
+    /// next tick, so generators of several nodes take turns at ticks.
+    /// Generators of different layouts, epochs, or versions are not ordered
+    /// against each other.
+    pub fn strict_order(mut self, strict: bool) -> Flaken {
+        self.strict = strict;
+        self
+    }
+
+    /// Let a generator whose tick ran out of sequence values move on to the
+    /// next tick before its clock gets there, up to `max` ahead
+    ///
+    /// Bursts then take ids from the near future instead of waiting, and
+    /// the clock catches up once the burst is over. Only once the generator
+    /// is `max` ahead does it wait again. Ids may carry timestamps up to
+    /// `max` past the time they were generated, see
+    /// [`Status::ahead`](struct.Status.html#structfield.ahead).
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use flaken::Flaken;
+    ///
+    /// let mut flake = Flaken::default().bitwidths(42, 20).generate_ahead(Duration::from_millis(50));
+    /// let ids: Vec<u64> = (0..100).map(|_| flake.next()).collect();
+    /// assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    /// assert!(flake.status().ahead <= Duration::from_millis(50));
+    /// ```
+    pub fn generate_ahead(mut self, max: Duration) -> Flaken {
+        self.ahead = max.as_millis().div_ceil(self.layout.tick_ms.max(1));
+        self
+    }
+
+    /// Set whether ids are checked to never carry a timestamp ahead of the
+    /// wall clock
+    ///
+    /// The generator measures time with its own clock from when it was
+    /// built, which may drift ahead of the wall clock, or the wall clock may
+    /// be stepped back. A real-time generator reads the wall clock for every
+    /// id and waits until it has caught up with the id's timestamp, so the
+    /// timestamp is never later than the time the id was returned. This
+    /// also holds back [`generate_ahead`](#method.generate_ahead).
+    ///
+    /// [`next`](#method.next) waits as long as it takes,
+    /// [`try_next`](#method.try_next) up to a tick before failing with
+    /// [`Error::AheadOfWall`], and the async methods sleep instead.
+    pub fn real_time(mut self, real_time: bool) -> Flaken {
+        self.real_time = real_time;
+        self
+    }
+
+    /// Whether the next tick is within reach of generating ahead
+    fn can_run_ahead(&self) -> bool {
+        self.ahead > 0 && self.duration < self.elapsed_ticks() + self.ahead
+    }
+
     /// generate the next id
     /// internally this updates at least the current sequence value, possibly
     /// the timestamp value if enough time has elapsed to matter
+    ///
+    /// Once every sequence value of the current millisecond has been used
+    /// this blocks until the next millisecond using the wait strategy. When a
+    /// maximum rate is set this also blocks until the rate allows another id.
+    /// Panics when the node id does not fit the layout, and once the clock
+    /// has run past the latest timestamp the layout can encode unless the
+    /// [timestamp overflow](#method.on_ts_overflow) policy wraps around.
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> u64 {
-        let duration = self.start_instant.elapsed().as_millis();
-        if duration != self.duration {
-            self.seq = 0;
+        if let Some(ref mut limit) = self.limit {
+            while let Err(wait) = limit.take(Instant::now()) {
+                self.wait.wait_until(Instant::now() + wait);
+            }
+        }
+        loop {
+            match self.generate() {
+                Ok(id) => return id,
+                Err(Error::AheadOfWall(wait)) => self.wait.wait_until(Instant::now() + wait),
+                Err(err) => panic!("{}", err),
+            }
+        }
+    }
+
+    /// Generate the next id unless that would exceed the maximum rate
+    ///
+    /// Returns [`Error::RateLimited`] with the time until an id is available
+    /// instead of waiting, [`Error::NodeOverflow`] when the node id does not
+    /// fit the layout, and [`Error::OutOfRange`] once the clock has run past
+    /// the latest timestamp the layout can encode without wrapping, where
+    /// `next` panics. A [real-time](#method.real_time) generator waits up to
+    /// a tick for the wall clock, then fails with [`Error::AheadOfWall`].
+    pub fn try_next(&mut self) -> Result<u64, Error> {
+        if let Some(ref mut limit) = self.limit {
+            limit.take(Instant::now()).map_err(Error::RateLimited)?;
+        }
+        let deadline = Instant::now() + self.tick_unit();
+        loop {
+            match self.generate() {
+                Err(Error::AheadOfWall(wait)) if Instant::now() + wait <= deadline => {
+                    self.wait.wait_until(Instant::now() + wait)
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// The most recently generated id, none before the first
+    pub fn last_id(&self) -> Option<u64> {
+        if self.issued > 0 {
+            Some(self.last_id)
+        } else {
+            None
+        }
+    }
+
+    /// The id [`next`](#method.next) would return if called now, without
+    /// generating it
+    ///
+    /// With a random sequence step the first id of a tick is not known in
+    /// advance, and the smallest it could be is returned instead. Node
+    /// rotation and generating ahead are followed, but a node id that would
+    /// be claimed or released as the next tick starts is not.
+    pub fn peek(&self) -> u64 {
+        let mut duration = self.elapsed_ticks();
+        if duration < self.duration && self.ahead > 0 {
+            duration = self.duration;
+        }
+        let first_node = self.rotation.as_ref().and_then(|rotation| rotation.nodes().first().copied()).unwrap_or(self.node);
+        let (tick, node, seq) = if self.issued == 0 || duration != self.duration {
+            (duration, first_node, 0)
+        } else if self.seq <= self.max_seq() {
+            (duration, self.node, self.seq)
+        } else if let Some(node) = self.rotation.as_ref().and_then(|rotation| rotation.next_node()) {
+            (duration, node, 0)
+        } else {
+            // Waiting and running ahead both move on to the next tick
+            (duration + 1, first_node, 0)
+        };
+        self.encode(self.tick_ts(tick), node, seq)
+    }
+
+    /// The timestamp of ids generated now, the start of the current tick in
+    /// milliseconds since the unix epoch
+    pub fn current_tick(&self) -> u64 {
+        self.tick_ts(self.elapsed_ticks())
+    }
+
+    /// How many of the `max_seq + 1` sequence values of the current tick are used
+    ///
+    /// Sequence values skipped by a random step count as used. Once all are
+    /// used, the next id waits for the next tick.
+    pub fn sequence_used_this_tick(&self) -> u64 {
+        if self.issued == 0 || self.elapsed_ticks() != self.duration {
+            0
+        } else {
+            self.seq.min(self.max_seq() + 1)
+        }
+    }
+
+    fn generate(&mut self) -> Result<u64, Error> {
+        self.generate_fenced(true)
+    }
+
+    /// Generate an id, retrying in the next tick when the fence is past the
+    /// claimed id if `retry` is set, and failing with
+    /// [`Error::SequenceExhausted`] otherwise
+    fn generate_fenced(&mut self, retry: bool) -> Result<u64, Error> {
+        let (id, new_tick) = loop {
+            let (id, new_tick) = match self.claim() {
+                Ok(claimed) => claimed,
+                Err(err) => {
+                    if let (Error::OutOfRange(ts), TsOverflow::Handler(handler)) = (&err, &self.overflow) {
+                        handler(*ts);
+                    }
+                    return Err(err);
+                }
+            };
+            if !self.strict || fence::publish(self.fence(), id) {
+                break (id, new_tick);
+            }
+            // A larger id was returned elsewhere, no later id of this tick can follow it
+            self.seq = self.max_seq().saturating_add(1);
+            if !retry {
+                return Err(Error::SequenceExhausted(self.tick_ts(self.duration)));
+            }
+        };
+        if let Some((ref mut sink, mode)) = self.sink {
+            let record = match mode {
+                SinkMode::Every => Some(id),
+                SinkMode::HighWater if new_tick && self.issued > 0 => Some(self.last_id),
+                SinkMode::HighWater => None,
+            };
+            if let Some(record) = record {
+                if let Err(_err) = sink.record(record) {
+                    trace_event!(warn, node = self.node, id = record, error = %_err, "failed to record issued id");
+                }
+            }
+        }
+        self.last_id = id;
+        self.issued += 1;
+        metrics::ids_generated(1);
+        Ok(id)
+    }
+
+    /// Claim the sequence value of the next id, returning the id and whether
+    /// it starts a new tick
+    fn claim(&mut self) -> Result<(u64, bool), Error> {
+        let mut duration = self.elapsed_ticks();
+        if self.tick_ts(duration) > self.layout.max_ts() && self.bump_epoch(self.tick_ts(duration)) {
+            duration = self.elapsed_ticks();
+        }
+        if let Some(last) = self.saturate_at() {
+            if duration > last {
+                if self.issued > 0 && self.duration == last && self.seq > self.max_seq() {
+                    return Err(Error::OutOfRange(self.tick_ts(duration)));
+                }
+                duration = last;
+            }
         }
-        let ts = self.start_ts + duration;
-        let id = self.encode(ts, self.node, self.seq);
+        if duration < self.duration && self.ahead > 0 {
+            // Still ahead of the clock after a burst
+            duration = self.duration;
+        }
+        if duration == self.duration && self.seq > self.max_seq() {
+            self.saturated += 1;
+        }
+        if duration == self.duration && self.seq > self.max_seq() && !self.switch_node() {
+            if self.can_run_ahead() {
+                trace_event!(debug, node = self.node, tick = duration + 1, "sequence exhausted, running ahead of the clock");
+                duration += 1;
+            } else {
+                trace_event!(debug, node = self.node, tick = duration, "sequence exhausted, waiting for the next tick");
+                self.rollovers += 1;
+                let waiting = Instant::now();
+                // Generating ahead, the clock only needs to get back within reach
+                while duration <= self.duration && self.seq > self.max_seq() {
+                    let next_tick = self.tick_start((self.duration + 1).saturating_sub(self.ahead));
+                    self.wait.pause(next_tick.saturating_sub(self.elapsed()));
+                    duration = if self.can_run_ahead() { self.duration + 1 } else { self.elapsed_ticks() };
+                }
+                metrics::sequence_wait(waiting.elapsed());
+            }
+        }
+        if self.real_time {
+            // Trying again keeps the tick that was ahead, the clock keeps moving on meanwhile
+            if let Some(held) = self.held.take() {
+                if held < duration && (held > self.duration || self.issued == 0) {
+                    duration = held;
+                }
+            }
+            // Checked before the tick is started, so it is started once
+            let ahead = self.tick_ts(duration).saturating_sub(wall_ms());
+            if ahead > 0 {
+                trace_event!(debug, node = self.node, ahead_ms = ahead, "generator time is ahead of the wall clock");
+                self.held = Some(duration);
+                return Err(Error::AheadOfWall(Duration::from_millis(ahead)));
+            }
+        }
+        let new_tick = duration != self.duration;
+        if new_tick {
+            self.ticks += 1;
+            self.watch_skew(duration);
+            self.rotate_new_tick(duration);
+            self.seq = self.first_seq();
+        }
+        if new_tick || self.issued == 0 {
+            self.follow_wall(duration);
+        }
+        let ts = self.tick_ts(duration);
+        let id = self.encode_generated(ts, self.seq & self.max_seq())?;
         self.duration = duration;
-        self.seq += 1;
-        id
+        self.seq = self.seq.saturating_add(match self.step {
+            Some((max_step, ref mut rng)) => 1 + rng.below(max_step),
+            None => 1,
+        });
+        Ok((id, new_tick))
+    }
+
+    /// The sequence value of the first id of a tick
+    fn first_seq(&mut self) -> u64 {
+        match self.step {
+            Some((max_step, ref mut rng)) => rng.below(max_step),
+            None => 0,
+        }
+    }
+
+    /// Time elapsed since the generator was built
+    fn uptime(&self) -> Duration {
+        self.clock.now().saturating_sub(self.start)
+    }
+
+    /// Time elapsed since the generator was built, plus any moves forward
+    fn elapsed(&self) -> Duration {
+        self.uptime() + self.anchor.adjusted
+    }
+
+    /// Ticks started since the generator was built, ticks being aligned to the epoch
+    fn elapsed_ticks(&self) -> u64 {
+        let tick = self.layout.tick_ms;
+        if tick == 1 {
+            return self.elapsed().as_millis();
+        }
+        let since_epoch = self.start_ts.saturating_sub(self.layout.epoch);
+        (since_epoch + self.elapsed().as_millis()) / tick - since_epoch / tick
+    }
+
+    /// The timestamp of the tick `ticks` after the one the generator was built in
+    fn tick_ts(&self, ticks: u64) -> u64 {
+        let tick = self.layout.tick_ms;
+        if tick == 1 {
+            return self.start_ts.saturating_add(ticks);
+        }
+        let first = self.layout.tick_floor(self.start_ts.max(self.layout.epoch));
+        first.saturating_add(ticks.saturating_mul(tick))
+    }
+
+    /// The timestamp from which another generator may reuse this one's node
+    /// ids, the end of the tick of its latest id
+    pub(crate) fn release_ts(&self) -> u64 {
+        if self.issued == 0 {
+            0
+        } else {
+            self.tick_ts(self.duration + 1)
+        }
+    }
+
+    /// Time since the generator was built at which the tick `ticks` starts
+    fn tick_start(&self, ticks: u64) -> Duration {
+        Duration::from_millis(self.tick_ts(ticks).saturating_sub(self.start_ts))
+    }
+
+    /// The largest sequence value of a millisecond
+    fn max_seq(&self) -> u64 {
+        self.layout.max_seq()
     }
 
     /// Encode into a flake id the given id, current time, and sequence value
     ///
     /// The current time (ts) is the number of milliseconds passed since the unix epoch
+    ///
+    /// The generator's version, if any, is embedded as well, with an epoch
+    /// table the version of the epoch the timestamp falls in.
+    ///
+    /// Every field is masked to its width. A timestamp past the end of the
+    /// layout is handled as the [timestamp overflow](#method.on_ts_overflow)
+    /// policy says, wrapping around by default and panicking otherwise,
+    /// after calling the handler of [`TsOverflow::Handler`]. Panics if the
+    /// timestamp is before the epoch. Nothing else is checked,
+    /// [`try_encode`](#method.try_encode) checks the fields and timestamp.
     pub fn encode(&self, ts: u64, node: u64, seq: u64) -> u64 {
-        assert!(ts >= self.epoch);
-        let ts0 = ts - self.epoch;
-        let (_, node_shift, seq_shift) = self.bitwidths;
-        let ts_mask = bitmask(node_shift+seq_shift);
-        let node_mask = bitmask(seq_shift) ^ ts_mask;
-        let seq_mask = (bitmask(0) ^ ts_mask) ^ node_mask;
-        ((ts0 << (node_shift + seq_shift)) & ts_mask) | ((node << seq_shift) & node_mask) | (seq & seq_mask)
+        let layout = self.layout_at(ts);
+        if ts > layout.max_ts() {
+            match self.overflow {
+                TsOverflow::Wrap => {}
+                TsOverflow::Handler(ref handler) => {
+                    handler(ts);
+                    panic!("{}", Error::OutOfRange(ts));
+                }
+                _ => panic!("{}", Error::OutOfRange(ts)),
+            }
+        }
+        layout.encode(ts, node, seq)
+    }
+
+    /// Encode an id, failing instead of masking or panicking when a field
+    /// does not fit the layout
+    ///
+    /// A timestamp further ahead of the generator's clock than the maximum
+    /// future skew, see [`max_future_skew`](#method.max_future_skew), fails
+    /// with [`Error::TooFarAhead`], such as seconds passed as milliseconds
+    /// or a timestamp from a badly set clock. Ids minted for such times
+    /// would collide with the ids generated once the time comes.
+    pub fn try_encode(&self, ts: u64, node: u64, seq: u64) -> Result<u64, Error> {
+        let layout = self.layout_at(ts);
+        let now = self.start_ts + self.elapsed().as_millis();
+        if ts > now.saturating_add(self.max_skew.as_millis()) && ts <= layout.max_ts() {
+            return Err(Error::TooFarAhead(ts));
+        }
+        layout.try_encode(ts, node, seq)
     }
 
     /// Decode from an encoded id the timestamp, node id, and sequence id
     //
     /// The current time (ts) is the number of milliseconds passed since the unix epoch
     ///
-    ///
+    /// Reserved and version bits are not part of any field, check them with
+    /// [`validate`](#method.validate) and [`version_of`](#method.version_of).
     pub fn decode(&self, id: u64) -> (u64, u64, u64) {
-        let (_, node_shift, seq_shift) = self.bitwidths;
-        let ts_mask = bitmask(node_shift+seq_shift);
-        let node_mask = bitmask(seq_shift) ^ ts_mask;
-        let seq_mask = (bitmask(0) ^ ts_mask) ^ node_mask;
-        let ts = (id & ts_mask) >> (node_shift+seq_shift);
-        let node = (id & node_mask) >> seq_shift;
-        let seq = id & seq_mask;
-        (ts + self.epoch, node, seq)
+        match self.epochs {
+            Some(_) => self.layout_of(id).decode(id),
+            None => self.layout.decode(id),
+        }
+    }
+
+    /// Decode the version embedded in an id, 0 when no version bits are reserved
+    pub fn version_of(&self, id: u64) -> u64 {
+        self.layout.version_of(id)
+    }
+
+    /// The time one unit of the timestamp field stands for, a millisecond
+    /// unless set with [`tick`](#method.tick)
+    pub fn tick_unit(&self) -> Duration {
+        self.layout.tick()
+    }
+
+    /// The time a number of ticks stands for, such as the raw timestamp field
+    /// of an id counted from the epoch
+    pub fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        let nanos = self.layout.tick().as_nanos().saturating_mul(ticks as u128);
+        match u64::try_from(nanos / 1_000_000_000) {
+            Ok(secs) => Duration::new(secs, (nanos % 1_000_000_000) as u32),
+            Err(_) => Duration::MAX,
+        }
+    }
+
+    /// The number of whole ticks in a duration, rounding down
+    pub fn duration_to_ticks(&self, duration: Duration) -> u64 {
+        (duration.as_nanos() / self.layout.tick().as_nanos()).min(u64::MAX as u128) as u64
+    }
+
+    /// Check an id against the layout, failing when any reserved bit is set
+    pub fn validate(&self, id: u64) -> Result<(), Error> {
+        let valid = self.layout.validate(id);
+        if valid.is_err() {
+            trace_event!(debug, node = self.node, id, "id has reserved bits set");
+        }
+        valid
+    }
+
+    /// Decode an id, rejecting it when it could not have been generated
+    ///
+    /// Besides checking the reserved bits, the timestamp must not be before
+    /// the epoch or more than the maximum future skew past the generator's
+    /// clock, so forged or garbage ids fail fast at service boundaries.
+    pub fn decode_checked(&self, id: u64) -> Result<(u64, u64, u64), Error> {
+        self.validate(id)?;
+        let masks = self.layout.masks();
+        let ts = (id & masks.ts) >> masks.ts_shift;
+        let now = self.start_ts + self.elapsed().as_millis();
+        match ts.checked_mul(masks.tick).and_then(|ts| ts.checked_add(self.layout_of(id).epoch)) {
+            Some(ts) if ts <= now.saturating_add(self.max_skew.as_millis()) => Ok(self.decode(id)),
+            _ => {
+                trace_event!(debug, node = self.node, id, ts, "id has an implausible timestamp");
+                Err(Error::Implausible(id))
+            }
+        }
+    }
+
+    /// Encode many (timestamp, node, sequence) triples at once
+    ///
+    /// With the `rayon` feature large slices are encoded in parallel.
+    pub fn encode_many(&self, fields: &[(u64, u64, u64)]) -> Vec<u64> {
+        #[cfg(feature = "rayon")]
+        {
+            if fields.len() >= PARALLEL_MIN_LEN {
+                return fields.par_iter().map(|&(ts, node, seq)| self.encode(ts, node, seq)).collect();
+            }
+        }
+        fields.iter().map(|&(ts, node, seq)| self.encode(ts, node, seq)).collect()
+    }
+
+    /// Decode many ids at once into (timestamp, node, sequence) triples
+    ///
+    /// With the `rayon` feature large slices are decoded in parallel.
+    pub fn decode_many(&self, ids: &[u64]) -> Vec<(u64, u64, u64)> {
+        #[cfg(feature = "rayon")]
+        {
+            if ids.len() >= PARALLEL_MIN_LEN {
+                return ids.par_iter().map(|&id| self.decode(id)).collect();
+            }
+        }
+        ids.iter().map(|&id| self.decode(id)).collect()
     }
 }
 
+/// Slices shorter than this are not worth splitting across threads
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_LEN: usize = 1 << 14;
+
 fn bitmask(left_shift: u64) -> u64 {
     0xFFFFFFFFFFFFFFFF << left_shift
 }
@@ -192,3 +997,317 @@ fn test_next() {
     assert_eq!(id3, 100);
     assert_eq!(seq3, 0);
 }
+
+#[test]
+fn test_next_waits_for_tick() {
+    let strategies = [
+        WaitStrategy::Spin,
+        WaitStrategy::Yield,
+        WaitStrategy::Sleep(Duration::from_micros(100)),
+        WaitStrategy::Park,
+    ];
+    for strategy in strategies.iter() {
+        let mut flake = Flaken::default().bitwidths(40, 22).wait_strategy(*strategy);
+        let mut last = None;
+        for _ in 0..20 {
+            let id = flake.next();
+            let (_, _, seq) = flake.decode(id);
+            assert!(seq < 4);
+            assert!(last.is_none_or(|last| id > last));
+            last = Some(id);
+        }
+    }
+}
+
+#[test]
+fn test_max_rate() {
+    let mut flake = Flaken::default().max_rate(100).max_burst(3);
+    for _ in 0..3 {
+        flake.try_next().unwrap();
+    }
+    match flake.try_next() {
+        Err(Error::RateLimited(wait)) => assert!(wait <= Duration::from_millis(10)),
+        res => panic!("expected rate limit, got {:?}", res),
+    }
+    let start = Instant::now();
+    flake.next();
+    assert!(start.elapsed() >= Duration::from_millis(5));
+}
+
+#[test]
+fn test_encode_decode_many() {
+    let flake = Flaken::default().bitwidths(41, 10);
+    let fields: Vec<_> = (0..40_000).map(|i| (flake.start_ts + i, i % 1024, i % 4096)).collect();
+    let ids = flake.encode_many(&fields);
+    assert_eq!(ids.len(), fields.len());
+    assert_eq!(ids[7], flake.encode(fields[7].0, fields[7].1, fields[7].2));
+    assert_eq!(flake.decode_many(&ids), fields);
+    assert_eq!(flake.decode_many(&ids[..3]), &fields[..3]);
+}
+
+#[test]
+fn test_coarse_clock_next() {
+    let clock = clock::CoarseClock::start(Duration::from_micros(200));
+    let mut flake = Flaken::default().bitwidths(40, 22).clock(clock);
+    let mut last = flake.next();
+    for _ in 0..20 {
+        let id = flake.next();
+        assert!(id > last);
+        last = id;
+    }
+}
+
+#[test]
+fn test_random_step() {
+    let mut flake = Flaken::default().bitwidths(40, 14).random_step(100);
+    let ids: Vec<u64> = (0..2000).map(|_| flake.next()).collect();
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    let fields = flake.decode_many(&ids);
+    let steps: Vec<u64> = fields.windows(2).filter(|w| w[0].0 == w[1].0).map(|w| w[1].2 - w[0].2).collect();
+    assert!(steps.iter().all(|step| (1..=100).contains(step)));
+    assert!(steps.iter().any(|&step| step > 1));
+}
+
+#[test]
+fn test_version() {
+    let mut v1 = Flaken::default().bitwidths(41, 10).version_bits(1).version(1);
+    let v0 = Flaken::default().bitwidths(41, 10).version_bits(1);
+    let id = v1.next();
+    assert_eq!(v1.version_of(id), 1);
+    assert_eq!(v0.version_of(id), 1);
+    assert_eq!(v0.version_of(v0.encode(v0.start_ts, 3, 4)), 0);
+    assert_eq!(v0.decode(id), v1.decode(id));
+}
+
+#[test]
+fn test_reserved_bits() {
+    let mut flake = Flaken::default().bitwidths(40, 10).reserved_bits(2);
+    let id = flake.next();
+    assert!(id >> 62 == 0);
+    assert!(flake.validate(id).is_ok());
+    assert!(flake.validate(id | 1 << 63).is_err());
+    assert_eq!(flake.decode(id | 1 << 63), flake.decode(id));
+}
+
+#[test]
+fn test_decode_checked() {
+    let mut flake = Flaken::default();
+    let id = flake.next();
+    assert_eq!(flake.decode_checked(id).unwrap(), flake.decode(id));
+    let ahead = flake.encode(flake.start_ts + 61000, 0, 0);
+    assert!(flake.decode_checked(ahead).is_err());
+    let flake = flake.max_future_skew(Duration::from_secs(120));
+    assert!(flake.decode_checked(ahead).is_ok());
+    let late = Flaken::default().epoch(u64::MAX - 10);
+    assert!(late.decode_checked(u64::MAX).is_err());
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_trace_events() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata};
+
+    struct Counter(Arc<AtomicUsize>);
+
+    impl tracing::Subscriber for Counter {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+        fn new_span(&self, _: &Attributes) -> Id {
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, _: &Record) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    let events = Arc::new(AtomicUsize::new(0));
+    tracing::subscriber::with_default(Counter(events.clone()), || {
+        let mut flake = Flaken::default().bitwidths(50, 12).reserved_bits(1);
+        for _ in 0..5 {
+            flake.next();
+        }
+        assert!(flake.validate(1 << 63).is_err());
+    });
+    assert!(events.load(Ordering::Relaxed) >= 2);
+}
+
+#[test]
+fn test_layout_exhausted() {
+    // 40 timestamp bits from 1970 ran out in 2004
+    let mut flake = Flaken::default().epoch(0).bitwidths(40, 10).on_ts_overflow(TsOverflow::Error);
+    assert!(matches!(flake.try_next(), Err(Error::OutOfRange(_))));
+    assert!(matches!(flake.try_encode(1 << 40, 0, 0), Err(Error::OutOfRange(_))));
+    assert_eq!(Flaken::default().epoch(0).bitwidths(40, 10).encode(1 << 40, 0, 0), 0);
+}
+
+#[test]
+#[should_panic(expected = "outside the range of the layout")]
+fn test_encode_overflow() {
+    Flaken::default().epoch(0).bitwidths(40, 10).on_ts_overflow(TsOverflow::Error).encode(1 << 40, 0, 0);
+}
+
+#[test]
+fn test_encode_too_far_ahead() {
+    let flake = Flaken::default().max_future_skew(Duration::from_secs(1));
+    let now = flake.start_ts;
+    assert!(flake.try_encode(now + 500, 0, 0).is_ok());
+    assert!(matches!(flake.try_encode(now * 1000, 0, 0), Err(Error::OutOfRange(_))));
+    assert!(matches!(flake.try_encode(now + 3_600_000, 0, 0), Err(Error::TooFarAhead(_))));
+}
+
+#[test]
+fn test_generate_ahead() {
+    // Two sequence values per millisecond and a clock standing still
+    let clock = clock::ManualClock::new();
+    let mut flake = Flaken::default()
+        .bitwidths(42, 21)
+        .clock(clock.clone())
+        .generate_ahead(Duration::from_millis(5));
+    let ids: Vec<u64> = (0..12).map(|_| flake.next()).collect();
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    let ts0 = flake.decode(ids[0]).0;
+    assert_eq!(flake.decode(ids[11]).0, ts0 + 5);
+    assert_eq!(flake.status().ahead, Duration::from_millis(5));
+    assert_eq!(flake.status().rollovers, 0);
+
+    // The clock catching up ends the debt
+    clock.set(Duration::from_millis(20));
+    let id = flake.next();
+    assert_eq!(flake.decode(id).0, ts0 + 20);
+    assert_eq!(flake.status().ahead, Duration::from_millis(0));
+}
+
+#[test]
+fn test_real_time() {
+    // As after skipping past ids held by a snapshot, the generator is ahead
+    let mut flake = Flaken::default();
+    flake.skip_to(wall_ms() + 30).unwrap();
+    let id = flake.next();
+    assert!(flake.decode(id).0 > wall_ms());
+
+    let mut flake = Flaken::default().real_time(true);
+    flake.skip_to(wall_ms() + 30).unwrap();
+    let started = Instant::now();
+    for _ in 0..3 {
+        let id = flake.next();
+        assert!(flake.decode(id).0 <= wall_ms());
+    }
+    assert!(started.elapsed() >= Duration::from_millis(20));
+
+    // Ahead by more than a tick, try_next gives up
+    flake.skip_to(wall_ms() + 1000).unwrap();
+    assert!(matches!(flake.try_next(), Err(Error::AheadOfWall(_))));
+    assert!(flake.try_next().is_err());
+}
+
+#[test]
+fn test_tick_conversions() {
+    let flake = Flaken::default();
+    assert_eq!(flake.tick_unit(), Duration::from_millis(1));
+    assert_eq!(flake.ticks_to_duration(1500), Duration::from_millis(1500));
+    assert_eq!(flake.duration_to_ticks(Duration::from_micros(2999)), 2);
+    assert_eq!(flake.duration_to_ticks(flake.ticks_to_duration(u64::MAX)), u64::MAX);
+}
+
+#[test]
+fn test_one_id_per_tick() {
+    let mut flake = Flaken::default().bitwidths(44, 20).node(9);
+    let ids: Vec<u64> = (0..5).map(|_| flake.next()).collect();
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    let ts: Vec<u64> = ids.iter().map(|&id| flake.decode(id).0).collect();
+    assert!(ts.windows(2).all(|w| w[0] < w[1]));
+    assert!(ids.iter().all(|&id| flake.decode(id).1 == 9));
+
+    let atomic = AtomicFlaken::new(Flaken::default().bitwidths(44, 20));
+    let (id0, id1) = (atomic.next(), atomic.next());
+    assert!(atomic.decode(id1).0 > atomic.decode(id0).0);
+}
+
+#[test]
+fn test_try_bitwidths() {
+    assert!(Flaken::default().try_bitwidths(44, 20).is_ok());
+    assert!(matches!(
+        Flaken::default().try_bitwidths(0, 10),
+        Err(Error::Layout(LayoutError::NoTimestampBits))
+    ));
+    let err = Flaken::default().version_bits(4).try_bitwidths(50, 12).unwrap_err();
+    assert!(matches!(err, Error::Layout(LayoutError::TooWide(66))));
+    assert_eq!(err.to_string(), "layout fields take 66 bits, more than the 64 of an id");
+}
+
+#[test]
+fn test_seconds() {
+    let mut flake = Flaken::seconds().node(7);
+    assert_eq!(flake.tick_unit(), Duration::from_secs(1));
+    assert_eq!(flake.layout.seq_bits, 13);
+    let ids: Vec<u64> = (0..100).map(|_| flake.next()).collect();
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    assert!(ids.iter().all(|&id| id >> 63 == 0));
+    let (ts, node, seq) = flake.decode(ids[99]);
+    assert_eq!(ts % 1000, 0);
+    assert!(ts.abs_diff(flake.start_ts) < 2000);
+    assert_eq!(node, 7);
+    assert!(seq >= 99 || flake.decode(ids[0]).0 < ts);
+
+    let id = flake.encode(1463702400000 + 5999, 1, 2);
+    assert_eq!(id, 5 << 33 | 1 << 13 | 2);
+    assert_eq!(flake.decode(id), (1463702405000, 1, 2));
+    assert_eq!(flake.layout.max_ts(), 1463702400000 + (1 << 30) * 1000 - 1);
+}
+
+#[test]
+fn test_short() {
+    let mut flake = Flaken::short().node(15);
+    assert_eq!(flake.layout.seq_bits, 6);
+    let ids: Vec<u64> = (0..64).map(|_| flake.next()).collect();
+    assert!(ids.iter().all(|&id| id < 1 << 40));
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(flake.layout.max_ts(), 1704067200000 + (1 << 30) * 1000 - 1);
+}
+
+#[test]
+fn test_peek() {
+    let mut flake = Flaken::default().bitwidths(40, 22).node(3);
+    assert_eq!(flake.last_id(), None);
+    for _ in 0..10 {
+        let peeked = flake.peek();
+        let id = flake.next();
+        // A new millisecond may start between the two
+        assert!(id >= peeked);
+        assert_eq!(flake.last_id(), Some(id));
+    }
+    let clock = clock::ManualClock::new();
+    let mut flake = Flaken::default().bitwidths(40, 22).clock(clock);
+    let ids: Vec<u64> = (0..4).map(|_| flake.next()).collect();
+    let (ts, _, seq) = flake.decode(flake.peek());
+    assert_eq!((ts, seq), (flake.decode(ids[3]).0 + 1, 0));
+
+    // Running ahead of a frozen clock
+    let clock = clock::ManualClock::new();
+    let mut flake = Flaken::default().bitwidths(40, 22).clock(clock).generate_ahead(Duration::from_millis(10));
+    for _ in 0..10 {
+        assert_eq!(flake.peek(), flake.next());
+    }
+}
+
+#[test]
+fn test_sequence_used_this_tick() {
+    let clock = clock::ManualClock::new();
+    let mut flake = Flaken::default().bitwidths(40, 21).clock(clock);
+    assert_eq!(flake.sequence_used_this_tick(), 0);
+    let id = flake.next();
+    assert_eq!(flake.current_tick(), flake.decode(id).0);
+    flake.next();
+    assert_eq!(flake.sequence_used_this_tick(), 2);
+    for _ in 0..6 {
+        flake.next();
+    }
+    assert_eq!(flake.sequence_used_this_tick(), 8);
+}
@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+
+/// Token bucket limiting the rate ids are generated at
+#[derive(Clone, Debug)]
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket refilled at `rate` tokens per second holding up to `burst` tokens
+    pub(crate) fn new(rate: u64, burst: u64) -> TokenBucket {
+        assert!(rate > 0 && burst > 0);
+        TokenBucket {
+            rate: rate as f64,
+            burst: burst as f64,
+            tokens: burst as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    pub(crate) fn set_burst(&mut self, burst: u64) {
+        assert!(burst > 0);
+        self.burst = burst as f64;
+        self.tokens = self.tokens.min(self.burst);
+    }
+
+    /// Take a token, or return how long until one is available
+    pub(crate) fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        let refill = now.saturating_duration_since(self.updated).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.burst);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+#[test]
+fn test_token_bucket() {
+    let start = Instant::now();
+    let mut bucket = TokenBucket::new(1000, 2);
+    assert!(bucket.take(start).is_ok());
+    assert!(bucket.take(start).is_ok());
+    let wait = bucket.take(start).unwrap_err();
+    assert!(wait > Duration::from_micros(900) && wait <= Duration::from_millis(1));
+    assert!(bucket.take(start + Duration::from_millis(1)).is_ok());
+    assert!(bucket.take(start + Duration::from_millis(1)).is_err());
+    // Idle time only ever refills up to the burst size
+    let later = start + Duration::from_secs(1);
+    assert!(bucket.take(later).is_ok());
+    assert!(bucket.take(later).is_ok());
+    assert!(bucket.take(later).is_err());
+}
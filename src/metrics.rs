@@ -0,0 +1,93 @@
+//! Metrics recorded through the `metrics` crate, behind the `metrics` feature
+//!
+//! Without the feature every hook is an empty inline function.
+
+use std::time::Duration;
+
+#[cfg(feature = "metrics")]
+const IDS_GENERATED: &str = "flaken_ids_generated_total";
+#[cfg(feature = "metrics")]
+const SEQUENCE_WAITS: &str = "flaken_sequence_exhaustion_waits_total";
+#[cfg(feature = "metrics")]
+const WAIT_DURATION: &str = "flaken_wait_duration_seconds";
+#[cfg(feature = "metrics")]
+const CLOCK_SKEW: &str = "flaken_clock_skew_detected_total";
+
+/// `ids` ids were generated
+#[inline(always)]
+pub(crate) fn ids_generated(_ids: u64) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(IDS_GENERATED).increment(_ids);
+}
+
+/// A generator waited for the next tick after running out of sequence values
+#[inline(always)]
+pub(crate) fn sequence_wait(_waited: Duration) {
+    #[cfg(feature = "metrics")]
+    {
+        ::metrics::counter!(SEQUENCE_WAITS).increment(1);
+        ::metrics::histogram!(WAIT_DURATION).record(_waited);
+    }
+}
+
+/// The wall clock and a generator's time diverged beyond its skew threshold
+#[inline(always)]
+pub(crate) fn clock_skew() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(CLOCK_SKEW).increment(1);
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_metrics_recorded() {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use ::metrics::{Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+
+    type Values = Arc<Mutex<HashMap<String, f64>>>;
+
+    struct Handle(String, Values);
+
+    impl CounterFn for Handle {
+        fn increment(&self, value: u64) {
+            *self.1.lock().unwrap().entry(self.0.clone()).or_insert(0.0) += value as f64;
+        }
+        fn absolute(&self, _: u64) {}
+    }
+
+    impl HistogramFn for Handle {
+        fn record(&self, value: f64) {
+            *self.1.lock().unwrap().entry(self.0.clone()).or_insert(0.0) += value;
+        }
+    }
+
+    struct Memory(Values);
+
+    impl Recorder for Memory {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn register_counter(&self, key: &Key, _: &Metadata) -> Counter {
+            Counter::from_arc(Arc::new(Handle(key.name().to_string(), self.0.clone())))
+        }
+        fn register_gauge(&self, _: &Key, _: &Metadata) -> Gauge {
+            Gauge::noop()
+        }
+        fn register_histogram(&self, key: &Key, _: &Metadata) -> Histogram {
+            Histogram::from_arc(Arc::new(Handle(key.name().to_string(), self.0.clone())))
+        }
+    }
+
+    let values = Values::default();
+    ::metrics::with_local_recorder(&Memory(values.clone()), || {
+        let mut flake = crate::Flaken::default().bitwidths(50, 13);
+        for _ in 0..10 {
+            flake.next();
+        }
+    });
+    let values = values.lock().unwrap();
+    assert_eq!(values[IDS_GENERATED], 10.0);
+    assert!(values[SEQUENCE_WAITS] >= 1.0);
+    assert!(values.contains_key(WAIT_DURATION));
+}
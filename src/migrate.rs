@@ -0,0 +1,113 @@
+//! Re-encoding ids from one layout into another
+//!
+//! Bulk re-keying jobs moving between snowflake schemes decode each id with
+//! the old generator's layout and encode its fields with the new one's. The
+//! rules are explicit so every job maps ids the same way: a field which does
+//! not fit fails rather than being masked, node ids may be remapped, and
+//! timestamps are only rounded down to coarser ticks when allowed.
+//!
+//! ```
+//! use flaken::{Epoch, Flaken, Migration};
+//!
+//! let old = Flaken::default().epoch(Epoch::Twitter2010);
+//! let new = Flaken::default().bitwidths(41, 8);
+//! let id = old.encode(1_600_000_000_000, 300, 7);
+//! let migrated = Migration::new(&old, &new).nodes(|node| node - 200).migrate(id).unwrap();
+//! assert_eq!(new.decode(migrated), (1_600_000_000_000, 100, 7));
+//! ```
+
+use std::fmt;
+
+use crate::{Error, Flaken};
+
+/// Rules for re-encoding ids of one generator's layout in another's
+pub struct Migration<'a> {
+    from: &'a Flaken,
+    to: &'a Flaken,
+    nodes: Option<Box<dyn Fn(u64) -> u64 + 'a>>,
+    truncate_ts: bool,
+}
+
+impl fmt::Debug for Migration<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Migration")
+            .field("from", &self.from.layout)
+            .field("to", &self.to.layout)
+            .field("truncate_ts", &self.truncate_ts)
+            .finish()
+    }
+}
+
+impl<'a> Migration<'a> {
+    /// Re-encode ids of `from`'s layout with `to`'s layout and version,
+    /// keeping node ids and failing on any loss of precision
+    pub fn new(from: &'a Flaken, to: &'a Flaken) -> Migration<'a> {
+        Migration {
+            from,
+            to,
+            nodes: None,
+            truncate_ts: false,
+        }
+    }
+
+    /// Map node ids of the old layout to node ids of the new one
+    pub fn nodes<F: Fn(u64) -> u64 + 'a>(mut self, map: F) -> Migration<'a> {
+        self.nodes = Some(Box::new(map));
+        self
+    }
+
+    /// Set whether timestamps are rounded down to the new layout's ticks
+    /// when those are coarser
+    ///
+    /// Ids of the same node and sequence value within one new tick then
+    /// migrate to the same id, only allow this when that can not happen.
+    pub fn truncate_ts(mut self, truncate: bool) -> Migration<'a> {
+        self.truncate_ts = truncate;
+        self
+    }
+
+    /// Re-encode an id
+    ///
+    /// Fails with [`Error::ReservedBits`] when the id does not belong to the
+    /// old layout, [`Error::OutOfRange`], [`Error::NodeOverflow`], or
+    /// [`Error::SeqOverflow`] when a field does not fit the new one, and
+    /// [`Error::Truncated`] when the timestamp falls within a tick of the
+    /// new layout unless truncating.
+    pub fn migrate(&self, id: u64) -> Result<u64, Error> {
+        self.from.validate(id)?;
+        let (ts, node, seq) = self.from.decode(id);
+        let node = match self.nodes {
+            Some(ref map) => map(node),
+            None => node,
+        };
+        let layout = &self.to.layout;
+        if ts >= layout.epoch && layout.tick_floor(ts) != ts && !self.truncate_ts {
+            return Err(Error::Truncated(ts));
+        }
+        self.to.try_encode(ts, node, seq)
+    }
+}
+
+impl Flaken {
+    /// Re-encode an id of this generator's layout with `to`'s, see
+    /// [`Migration`] for remapping nodes or rounding timestamps
+    pub fn migrate(&self, id: u64, to: &Flaken) -> Result<u64, Error> {
+        Migration::new(self, to).migrate(id)
+    }
+}
+
+#[test]
+fn test_migrate() {
+    use std::time::Duration;
+
+    let old = Flaken::default();
+    let id = old.encode(1_700_000_000_123, 1000, 4000);
+    let narrow = Flaken::default().bitwidths(42, 8);
+    assert!(matches!(old.migrate(id, &narrow), Err(Error::NodeOverflow(1000))));
+    let seconds = Flaken::default().tick(Duration::from_secs(1));
+    assert!(matches!(old.migrate(id, &seconds), Err(Error::Truncated(1_700_000_000_123))));
+    let migrated = Migration::new(&old, &seconds).truncate_ts(true).migrate(id).unwrap();
+    assert_eq!(seconds.decode(migrated), (1_700_000_000_000, 1000, 4000));
+    let versioned = Flaken::default().version_bits(1).version(1);
+    assert!(matches!(old.migrate(id, &versioned), Err(Error::SeqOverflow(4000))));
+}
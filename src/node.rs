@@ -0,0 +1,201 @@
+//! Helpers for choosing a node id
+//!
+//! Kubernetes StatefulSets give every pod a stable ordinal which makes a
+//! convenient unique node id. The ordinal is the trailing number of the pod
+//! name, which is also the pod's `HOSTNAME`.
+//!
+//! ```no_run
+//! use flaken::{node, Flaken};
+//!
+//! let mut flake = Flaken::default().node(node::statefulset_ordinal().unwrap());
+//! let id = flake.next();
+//! ```
+//!
+//! Elastic deployments without stable ordinals can instead claim a node id
+//! from a [`NodeAllocator`]. The claimed id is held by the returned [`Lease`]
+//! until it is dropped. Within one process a [`NodeRegistry`] hands node ids
+//! out to each generator.
+//!
+//! Deployments without any coordination may pick a [`NodeId::random`] node id
+//! and use [`collision_probability`] to know the risk they are taking, while
+//! an [`Announcer`] can warn about duplicates on the local network.
+//!
+//! ```
+//! use flaken::node::{self, NodeId};
+//! use flaken::Flaken;
+//!
+//! let node = NodeId::random(10);
+//! let risk = node::collision_probability(8, 10);
+//! assert!(risk > 0.02 && risk < 0.03);
+//! let mut flake = Flaken::default().node(node.get());
+//! ```
+
+use std::env;
+
+use crate::rand::Rng;
+use crate::Error;
+
+mod announce;
+#[cfg(feature = "etcd")]
+mod etcd;
+mod local;
+#[cfg(feature = "redis")]
+mod redis;
+mod registry;
+#[cfg(feature = "zookeeper")]
+mod zookeeper;
+
+pub use self::announce::{Announcement, Announcer, Duplicate, ANNOUNCE_PORT};
+#[cfg(feature = "etcd")]
+pub use self::etcd::{EtcdLease, EtcdNodeAllocator};
+pub use self::local::{LocalLease, LocalNodeAllocator};
+#[cfg(feature = "redis")]
+pub use self::redis::{RedisLease, RedisNodeAllocator};
+pub use self::registry::{NodeLease, NodeRegistry};
+#[cfg(feature = "zookeeper")]
+pub use self::zookeeper::{ZooKeeperLease, ZooKeeperNodeAllocator};
+
+/// A node id
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(pub u64);
+
+impl NodeId {
+    /// Pick a random node id fitting in `node_bits` bits
+    pub fn random(node_bits: u64) -> NodeId {
+        assert!(node_bits < 64);
+        NodeId(Rng::new().below(1 << node_bits))
+    }
+
+    /// The node id value
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl From<NodeId> for u64 {
+    fn from(node: NodeId) -> u64 {
+        node.0
+    }
+}
+
+/// Probability that any two of `nodes` randomly chosen node ids of
+/// `node_bits` bits are the same
+///
+/// This is the birthday problem, computed exactly for up to a million nodes
+/// and with the usual exponential approximation beyond that.
+pub fn collision_probability(nodes: u64, node_bits: u64) -> f64 {
+    assert!(node_bits < 64);
+    let space = (1u64 << node_bits) as f64;
+    if nodes < 2 {
+        return 0.0;
+    }
+    if nodes as f64 > space {
+        return 1.0;
+    }
+    let nodes_f = nodes as f64;
+    let log_unique = if nodes <= 1_000_000 {
+        (1..nodes).map(|i| (-(i as f64) / space).ln_1p()).sum()
+    } else {
+        -nodes_f * (nodes_f - 1.0) / (2.0 * space)
+    };
+    -log_unique.exp_m1()
+}
+
+/// A node id claimed from a [`NodeAllocator`], released when dropped
+pub trait Lease {
+    /// The claimed node id
+    fn node(&self) -> u64;
+
+    /// Keep the node id from being claimed again before `ts`, in
+    /// milliseconds since the unix epoch, once released
+    ///
+    /// Allocators handing released ids straight back out within the process
+    /// honor it, so the next owner does not reissue the ids of the last tick.
+    /// Does nothing by default.
+    fn hold_until(&mut self, _ts: u64) {}
+}
+
+/// Hands out node ids that are unique among everyone sharing the allocator
+pub trait NodeAllocator {
+    /// The guard holding a claimed node id
+    type Lease: Lease;
+
+    /// Claim a free node id
+    fn claim(&self) -> Result<Self::Lease, Error>;
+
+    /// Claim a free node id for generating ids from `ts` on, in milliseconds
+    /// since the unix epoch, skipping ids held past it, see
+    /// [`Lease::hold_until`]
+    fn claim_at(&self, _ts: u64) -> Result<Self::Lease, Error> {
+        self.claim()
+    }
+}
+
+/// Parse the ordinal from a StatefulSet pod name such as `api-7`
+pub fn parse_ordinal(name: &str) -> Option<u64> {
+    let ordinal = match name.rfind('-') {
+        Some(idx) => &name[idx + 1..],
+        None => name,
+    };
+    if ordinal.is_empty() || !ordinal.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    ordinal.parse().ok()
+}
+
+/// Read a node ordinal from the environment variable `var`
+///
+/// The value may be either a bare ordinal, as exposed through the downward
+/// API from the `apps.kubernetes.io/pod-index` label, or a pod name such as
+/// `api-7` taken from `metadata.name`.
+pub fn ordinal_from_env(var: &str) -> Result<u64, Error> {
+    let value = env::var(var).map_err(|_| Error::MissingEnv(var.to_string()))?;
+    parse_ordinal(value.trim()).ok_or(Error::InvalidOrdinal(value))
+}
+
+/// Read the node ordinal of a StatefulSet pod from `HOSTNAME`
+pub fn statefulset_ordinal() -> Result<u64, Error> {
+    ordinal_from_env("HOSTNAME")
+}
+
+#[test]
+fn test_random_node() {
+    for _ in 0..100 {
+        assert!(NodeId::random(4).get() < 16);
+    }
+    assert_eq!(NodeId::random(0), NodeId(0));
+}
+
+#[test]
+fn test_collision_probability() {
+    assert_eq!(collision_probability(1, 10), 0.0);
+    assert_eq!(collision_probability(2, 0), 1.0);
+    assert_eq!(collision_probability(1025, 10), 1.0);
+    assert!((collision_probability(2, 10) - 1.0 / 1024.0).abs() < 1e-12);
+    // 23 people sharing a birthday, with a year rounded up to 512 days
+    assert!((collision_probability(23, 9) - 0.3944).abs() < 1e-4);
+    let approx = collision_probability(2_000_000, 48);
+    assert!((approx - 0.00708).abs() < 1e-5);
+}
+
+#[test]
+fn test_parse_ordinal() {
+    assert_eq!(parse_ordinal("api-7"), Some(7));
+    assert_eq!(parse_ordinal("my-web-app-12"), Some(12));
+    assert_eq!(parse_ordinal("3"), Some(3));
+    assert_eq!(parse_ordinal("api"), None);
+    assert_eq!(parse_ordinal("api-"), None);
+    assert_eq!(parse_ordinal("api-+7"), None);
+    assert_eq!(parse_ordinal("api-7b"), None);
+}
+
+#[test]
+fn test_ordinal_from_env() {
+    env::set_var("FLAKEN_TEST_POD_NAME", "api-4");
+    env::set_var("FLAKEN_TEST_POD_INDEX", "9\n");
+    env::set_var("FLAKEN_TEST_POD_BAD", "api");
+    assert_eq!(ordinal_from_env("FLAKEN_TEST_POD_NAME").unwrap(), 4);
+    assert_eq!(ordinal_from_env("FLAKEN_TEST_POD_INDEX").unwrap(), 9);
+    assert!(matches!(ordinal_from_env("FLAKEN_TEST_POD_BAD"), Err(Error::InvalidOrdinal(_))));
+    assert!(matches!(ordinal_from_env("FLAKEN_TEST_POD_UNSET"), Err(Error::MissingEnv(_))));
+}
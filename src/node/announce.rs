@@ -0,0 +1,219 @@
+//! Duplicate node id detection by announcing node ids over UDP
+
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::rand::Rng;
+use crate::Error;
+
+/// UDP port announcements are sent to and received on by default
+pub const ANNOUNCE_PORT: u16 = 47512;
+
+const MAGIC: &[u8; 4] = b"FLKN";
+const MESSAGE_LEN: usize = 20;
+
+/// Another generator announcing the same node id
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Duplicate {
+    /// The node id claimed by both generators
+    pub node: u64,
+    /// Where the conflicting announcement came from
+    pub from: SocketAddr,
+}
+
+/// Periodically announces a node id and listens for others announcing it
+///
+/// By default announcements are broadcast to the local network on
+/// [`ANNOUNCE_PORT`]. Only one process per host can listen on a port, so
+/// announce once per host and use [`LocalNodeAllocator`] to keep the
+/// processes on each host apart.
+///
+/// ```no_run
+/// use flaken::node::Announcer;
+///
+/// let announcement = Announcer::new(7).start(|dup| {
+///     eprintln!("node id {} is also used by {}", dup.node, dup.from);
+/// }).unwrap();
+/// ```
+///
+/// [`LocalNodeAllocator`]: struct.LocalNodeAllocator.html
+#[derive(Clone, Debug)]
+pub struct Announcer {
+    node: u64,
+    bind: SocketAddr,
+    target: SocketAddr,
+    interval: Duration,
+}
+
+impl Announcer {
+    /// Build an announcer for `node` broadcasting once a second
+    pub fn new(node: u64) -> Announcer {
+        Announcer {
+            node,
+            bind: SocketAddr::from(([0, 0, 0, 0], ANNOUNCE_PORT)),
+            target: SocketAddr::from(([255, 255, 255, 255], ANNOUNCE_PORT)),
+            interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Set the address announcements are received on
+    pub fn bind(mut self, addr: SocketAddr) -> Announcer {
+        self.bind = addr;
+        self
+    }
+
+    /// Set the broadcast, multicast, or unicast address announcements are sent to
+    pub fn target(mut self, addr: SocketAddr) -> Announcer {
+        self.target = addr;
+        self
+    }
+
+    /// Set how often the node id is announced
+    pub fn interval(mut self, interval: Duration) -> Announcer {
+        assert!(interval > Duration::from_millis(0));
+        self.interval = interval;
+        self
+    }
+
+    /// Start announcing, calling `on_duplicate` for each conflicting announcement received
+    pub fn start<F>(self, on_duplicate: F) -> Result<Announcement, Error>
+    where
+        F: Fn(Duplicate) + Send + 'static,
+    {
+        let socket = UdpSocket::bind(self.bind)?;
+        socket.set_broadcast(true)?;
+        if let SocketAddr::V4(target) = self.target {
+            if target.ip().is_multicast() {
+                socket.join_multicast_v4(target.ip(), &[0, 0, 0, 0].into())?;
+            }
+        }
+        socket.set_read_timeout(Some(self.interval))?;
+        let local_addr = socket.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let token = Rng::new().next_u64();
+        let handle = {
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name(format!("flaken-announce-{}", self.node))
+                .spawn(move || self.run(socket, token, stop, on_duplicate))?
+        };
+        Ok(Announcement {
+            local_addr,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    fn run<F: Fn(Duplicate)>(self, socket: UdpSocket, token: u64, stop: Arc<AtomicBool>, on_duplicate: F) {
+        let mut message = [0u8; MESSAGE_LEN];
+        message[..4].copy_from_slice(MAGIC);
+        message[4..12].copy_from_slice(&self.node.to_be_bytes());
+        message[12..].copy_from_slice(&token.to_be_bytes());
+        let mut announced_at: Option<Instant> = None;
+        let mut buf = [0u8; MESSAGE_LEN];
+        while !stop.load(Ordering::Acquire) {
+            if announced_at.is_none_or(|at| at.elapsed() >= self.interval) {
+                let _ = socket.send_to(&message, self.target);
+                announced_at = Some(Instant::now());
+            }
+            match socket.recv_from(&mut buf) {
+                Ok((MESSAGE_LEN, from)) if &buf[..4] == MAGIC => {
+                    let mut node = [0u8; 8];
+                    let mut other = [0u8; 8];
+                    node.copy_from_slice(&buf[4..12]);
+                    other.copy_from_slice(&buf[12..]);
+                    if u64::from_be_bytes(node) == self.node && u64::from_be_bytes(other) != token {
+                        on_duplicate(Duplicate { node: self.node, from });
+                    }
+                }
+                Ok(_) => {}
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut => {}
+                Err(_) => thread::sleep(self.interval),
+            }
+        }
+    }
+}
+
+/// A running announcer, stopped when dropped
+pub struct Announcement {
+    local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Announcement {
+    /// The address announcements are received on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl fmt::Debug for Announcement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Announcement").field("local_addr", &self.local_addr).finish()
+    }
+}
+
+impl Drop for Announcement {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[test]
+fn test_announce_duplicate() {
+    use std::sync::mpsc;
+
+    let localhost = |port| SocketAddr::from(([127, 0, 0, 1], port));
+    let interval = Duration::from_millis(10);
+    let (dups, dups_rx) = mpsc::channel();
+    let a = {
+        let dups = dups.clone();
+        Announcer::new(5)
+            .bind(localhost(0))
+            .target(localhost(9))
+            .interval(interval)
+            .start(move |dup| {
+                let _ = dups.send(("a", dup));
+            })
+            .unwrap()
+    };
+    let b = Announcer::new(5)
+        .bind(localhost(0))
+        .target(a.local_addr())
+        .interval(interval)
+        .start(move |dup| {
+            let _ = dups.send(("b", dup));
+        })
+        .unwrap();
+    let (who, dup) = dups_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(who, "a");
+    assert_eq!(dup, Duplicate { node: 5, from: b.local_addr() });
+}
+
+#[test]
+fn test_announce_distinct() {
+    let localhost = |port| SocketAddr::from(([127, 0, 0, 1], port));
+    let interval = Duration::from_millis(10);
+    let dups = Arc::new(AtomicBool::new(false));
+    let seen = dups.clone();
+    let a = Announcer::new(1)
+        .bind(localhost(0))
+        .target(localhost(9))
+        .interval(interval)
+        .start(move |_| seen.store(true, Ordering::SeqCst))
+        .unwrap();
+    let b = Announcer::new(2).bind(localhost(0)).target(a.local_addr()).interval(interval).start(|_| {}).unwrap();
+    thread::sleep(interval * 5);
+    drop(b);
+    drop(a);
+    assert!(!dups.load(Ordering::SeqCst));
+}
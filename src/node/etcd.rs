@@ -0,0 +1,221 @@
+//! Node ids leased from etcd keys
+//!
+//! This talks to the etcd v3 JSON gateway so no gRPC toolchain or async
+//! runtime is required.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use ureq::Agent;
+
+use crate::node::{Lease, NodeAllocator};
+use crate::Error;
+
+/// Claims node ids as etcd keys attached to a lease
+///
+/// Node id `n` is held by whoever created the key `{prefix}{n}`. Keys are
+/// created in a transaction which only succeeds if the key does not exist,
+/// and are attached to an etcd lease so a crashed holder's id becomes free
+/// again once the lease TTL runs out.
+///
+/// ```no_run
+/// use flaken::node::{EtcdNodeAllocator, Lease, NodeAllocator};
+/// use flaken::Flaken;
+///
+/// let allocator = EtcdNodeAllocator::new("http://127.0.0.1:2379", "flaken/node/", 1024);
+/// let lease = allocator.claim().unwrap();
+/// let mut flake = Flaken::default().node(lease.node());
+/// let id = flake.next();
+/// ```
+#[derive(Clone, Debug)]
+pub struct EtcdNodeAllocator {
+    endpoint: String,
+    prefix: String,
+    nodes: u64,
+    ttl: Duration,
+}
+
+impl EtcdNodeAllocator {
+    /// Build an allocator handing out node ids `0..nodes` with a TTL of 30 seconds
+    pub fn new(endpoint: &str, prefix: &str, nodes: u64) -> EtcdNodeAllocator {
+        EtcdNodeAllocator {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            prefix: prefix.to_string(),
+            nodes,
+            ttl: Duration::from_secs(30),
+        }
+    }
+
+    /// Set the TTL of the etcd lease, it is kept alive every third of it
+    pub fn ttl(mut self, ttl: Duration) -> EtcdNodeAllocator {
+        assert!(ttl >= Duration::from_secs(3));
+        self.ttl = ttl;
+        self
+    }
+}
+
+impl NodeAllocator for EtcdNodeAllocator {
+    type Lease = EtcdLease;
+
+    fn claim(&self) -> Result<EtcdLease, Error> {
+        let gateway = Gateway::new(&self.endpoint, self.ttl / 3);
+        let granted = gateway.call("/v3/lease/grant", json!({ "TTL": self.ttl.as_secs() }))?;
+        let lease = match granted["ID"].as_str() {
+            Some(id) => id.to_string(),
+            None => return Err(Error::Etcd(format!("unexpected lease grant response {}", granted))),
+        };
+        for node in 0..self.nodes {
+            let key = base64(format!("{}{}", self.prefix, node).as_bytes());
+            let txn = json!({
+                "compare": [{ "key": key, "target": "CREATE", "result": "EQUAL", "create_revision": "0" }],
+                "success": [{ "request_put": { "key": key, "value": base64(lease.as_bytes()), "lease": lease } }],
+            });
+            let claimed = gateway.call("/v3/kv/txn", txn);
+            match claimed {
+                Ok(ref res) if res["succeeded"].as_bool() == Some(true) => {
+                    return Ok(EtcdLease::start(gateway, node, lease, self.ttl));
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    let _ = gateway.call("/v3/lease/revoke", json!({ "ID": lease }));
+                    return Err(err);
+                }
+            }
+        }
+        let _ = gateway.call("/v3/lease/revoke", json!({ "ID": lease }));
+        Err(Error::NodesExhausted)
+    }
+}
+
+/// A node id held in etcd, kept alive in the background until dropped
+#[derive(Debug)]
+pub struct EtcdLease {
+    node: u64,
+    held: Arc<AtomicBool>,
+    stop: Option<mpsc::Sender<()>>,
+    keeper: Option<thread::JoinHandle<()>>,
+}
+
+impl EtcdLease {
+    fn start(gateway: Gateway, node: u64, lease: String, ttl: Duration) -> EtcdLease {
+        let held = Arc::new(AtomicBool::new(true));
+        let (stop, stopped) = mpsc::channel();
+        let keeper = {
+            let held = held.clone();
+            thread::Builder::new()
+                .name(format!("flaken-etcd-lease-{}", node))
+                .spawn(move || keep_alive(gateway, node, lease, ttl, held, stopped))
+                .expect("failed to spawn etcd lease keeper")
+        };
+        EtcdLease {
+            node,
+            held,
+            stop: Some(stop),
+            keeper: Some(keeper),
+        }
+    }
+
+    /// Whether the lease is still held
+    ///
+    /// This becomes false once the etcd lease could not be kept alive before
+    /// its TTL ran out, at which point another client may claim the node id.
+    pub fn is_held(&self) -> bool {
+        self.held.load(Ordering::Acquire)
+    }
+}
+
+impl Lease for EtcdLease {
+    fn node(&self) -> u64 {
+        self.node
+    }
+}
+
+impl Drop for EtcdLease {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(handle) = self.keeper.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn keep_alive(gateway: Gateway, _node: u64, lease: String, ttl: Duration, held: Arc<AtomicBool>, stopped: mpsc::Receiver<()>) {
+    let mut renewed_at = Instant::now();
+    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(ttl / 3) {
+        match gateway.call("/v3/lease/keepalive", json!({ "ID": lease })) {
+            Ok(ref res) if res["result"]["TTL"].as_str().is_some_and(|ttl| ttl != "0") => {
+                trace_event!(trace, node = _node, "renewed etcd node lease");
+                renewed_at = Instant::now()
+            }
+            Ok(_) => {
+                trace_event!(warn, node = _node, "etcd node lease expired");
+                break;
+            }
+            Err(_err) if renewed_at.elapsed() >= ttl => {
+                trace_event!(warn, node = _node, error = %_err, "etcd node lease expired");
+                break;
+            }
+            Err(_err) => {
+                trace_event!(debug, node = _node, error = %_err, "failed to renew etcd node lease, retrying");
+            }
+        }
+    }
+    held.store(false, Ordering::Release);
+    // Revoking the lease deletes the node key attached to it
+    let _ = gateway.call("/v3/lease/revoke", json!({ "ID": lease }));
+}
+
+#[derive(Debug)]
+struct Gateway {
+    agent: Agent,
+    endpoint: String,
+}
+
+impl Gateway {
+    fn new(endpoint: &str, timeout: Duration) -> Gateway {
+        let agent = Agent::config_builder().timeout_global(Some(timeout)).build().into();
+        Gateway {
+            agent,
+            endpoint: endpoint.to_string(),
+        }
+    }
+
+    fn call(&self, path: &str, body: Value) -> Result<Value, Error> {
+        let url = format!("{}{}", self.endpoint, path);
+        self.agent
+            .post(&url)
+            .send_json(&body)
+            .and_then(|mut res| res.body_mut().read_json::<Value>())
+            .map_err(|err| Error::Etcd(err.to_string()))
+    }
+}
+
+fn base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn test_base64() {
+    assert_eq!(base64(b""), "");
+    assert_eq!(base64(b"f"), "Zg==");
+    assert_eq!(base64(b"fo"), "Zm8=");
+    assert_eq!(base64(b"foo"), "Zm9v");
+    assert_eq!(base64(b"flaken/node/7"), "Zmxha2VuL25vZGUvNw==");
+}
@@ -0,0 +1,119 @@
+//! Node ids claimed with lock files shared by processes on one host
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+
+use crate::node::{Lease, NodeAllocator};
+use crate::Error;
+
+/// Claims node ids by locking files in a directory
+///
+/// Node id `n` is held by whichever process holds the exclusive lock on
+/// `{dir}/{n}.lock`. The operating system drops the lock when the process
+/// exits, so ids of crashed processes are freed immediately. This keeps
+/// several workers on one machine from sharing a node id by accident.
+///
+/// Combine it with a per host node id by offsetting the claimed range.
+///
+/// ```no_run
+/// use flaken::node::{Lease, LocalNodeAllocator, NodeAllocator};
+/// use flaken::Flaken;
+///
+/// let host = 3;
+/// let allocator = LocalNodeAllocator::new("/var/run/flaken", 16).offset(host * 16);
+/// let lease = allocator.claim().unwrap();
+/// let mut flake = Flaken::default().node(lease.node());
+/// let id = flake.next();
+/// ```
+#[derive(Clone, Debug)]
+pub struct LocalNodeAllocator {
+    dir: PathBuf,
+    nodes: u64,
+    offset: u64,
+}
+
+impl LocalNodeAllocator {
+    /// Build an allocator handing out node ids `0..nodes` locked under `dir`
+    pub fn new<P: AsRef<Path>>(dir: P, nodes: u64) -> LocalNodeAllocator {
+        LocalNodeAllocator {
+            dir: dir.as_ref().to_path_buf(),
+            nodes,
+            offset: 0,
+        }
+    }
+
+    /// Hand out node ids `offset..offset + nodes` instead
+    pub fn offset(mut self, offset: u64) -> LocalNodeAllocator {
+        self.offset = offset;
+        self
+    }
+}
+
+impl NodeAllocator for LocalNodeAllocator {
+    type Lease = LocalLease;
+
+    fn claim(&self) -> Result<LocalLease, Error> {
+        fs::create_dir_all(&self.dir)?;
+        for sub in 0..self.nodes {
+            let path = self.dir.join(format!("{}.lock", sub));
+            let file = OpenOptions::new().create(true).truncate(false).write(true).open(&path)?;
+            match file.try_lock() {
+                Ok(()) => {
+                    return Ok(LocalLease {
+                        file,
+                        path,
+                        node: self.offset + sub,
+                    })
+                }
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Error(err)) => return Err(err.into()),
+            }
+        }
+        Err(Error::NodesExhausted)
+    }
+}
+
+/// A node id held by a file lock until dropped
+#[derive(Debug)]
+pub struct LocalLease {
+    file: File,
+    path: PathBuf,
+    node: u64,
+}
+
+impl LocalLease {
+    /// The path of the locked file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Lease for LocalLease {
+    fn node(&self) -> u64 {
+        self.node
+    }
+}
+
+impl Drop for LocalLease {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+#[test]
+fn test_local_claim() {
+    use std::env;
+    use std::process;
+
+    let dir = env::temp_dir().join(format!("flaken-test-local-{}", process::id()));
+    let allocator = LocalNodeAllocator::new(&dir, 2).offset(10);
+    let lease0 = allocator.claim().unwrap();
+    let lease1 = allocator.claim().unwrap();
+    assert_eq!(lease0.node(), 10);
+    assert_eq!(lease1.node(), 11);
+    assert!(matches!(allocator.claim(), Err(Error::NodesExhausted)));
+    drop(lease0);
+    assert_eq!(allocator.claim().unwrap().node(), 10);
+    drop(lease1);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,270 @@
+//! Node ids leased from redis keys
+
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use redis::{self as redis_rs, Client, Connection, RedisResult, Script};
+
+use crate::node::{Lease, NodeAllocator};
+use crate::Error;
+
+const RENEW_SCRIPT: &str = r"
+if redis.call('get', KEYS[1]) == ARGV[1] then
+    return redis.call('pexpire', KEYS[1], ARGV[2])
+end
+return 0
+";
+
+const RELEASE_SCRIPT: &str = r"
+if redis.call('get', KEYS[1]) == ARGV[1] then
+    return redis.call('del', KEYS[1])
+end
+return 0
+";
+
+/// Claims node ids as redis keys which expire unless renewed
+///
+/// Node id `n` is held by whoever owns the key `{prefix}{n}`. Keys are
+/// claimed with `SET NX PX` so only one client may hold an id at a time, and
+/// a crashed holder's id becomes free again once its TTL runs out.
+///
+/// ```no_run
+/// use flaken::node::{Lease, NodeAllocator, RedisNodeAllocator};
+/// use flaken::Flaken;
+///
+/// let client = redis::Client::open("redis://127.0.0.1/").unwrap();
+/// let allocator = RedisNodeAllocator::new(client, "flaken:node:", 1024);
+/// let lease = allocator.claim().unwrap();
+/// let mut flake = Flaken::default().node(lease.node());
+/// let id = flake.next();
+/// ```
+#[derive(Clone, Debug)]
+pub struct RedisNodeAllocator {
+    client: Client,
+    prefix: String,
+    nodes: u64,
+    ttl: Duration,
+}
+
+impl RedisNodeAllocator {
+    /// Build an allocator handing out node ids `0..nodes` with a TTL of 30 seconds
+    pub fn new(client: Client, prefix: &str, nodes: u64) -> RedisNodeAllocator {
+        RedisNodeAllocator {
+            client,
+            prefix: prefix.to_string(),
+            nodes,
+            ttl: Duration::from_secs(30),
+        }
+    }
+
+    /// Set the TTL of claimed keys, leases are renewed every third of it
+    pub fn ttl(mut self, ttl: Duration) -> RedisNodeAllocator {
+        assert!(ttl >= Duration::from_millis(3));
+        self.ttl = ttl;
+        self
+    }
+}
+
+impl NodeAllocator for RedisNodeAllocator {
+    type Lease = RedisLease;
+
+    fn claim(&self) -> Result<RedisLease, Error> {
+        let mut con = self.client.get_connection()?;
+        let token = token();
+        let ttl_ms = self.ttl.as_millis() as u64;
+        for node in 0..self.nodes {
+            let key = format!("{}{}", self.prefix, node);
+            let sent = Instant::now();
+            let claimed: Option<String> = redis_rs::cmd("SET")
+                .arg(&key)
+                .arg(&token)
+                .arg("NX")
+                .arg("PX")
+                .arg(ttl_ms)
+                .query(&mut con)?;
+            if claimed.is_some() {
+                return Ok(RedisLease::start(self.client.clone(), con, node, key, token, self.ttl, sent));
+            }
+        }
+        Err(Error::NodesExhausted)
+    }
+}
+
+/// A node id held in redis, renewed in the background until dropped
+#[derive(Debug)]
+pub struct RedisLease {
+    node: u64,
+    held: Arc<Held>,
+    stop: Option<mpsc::Sender<()>>,
+    renewer: Option<thread::JoinHandle<()>>,
+}
+
+impl RedisLease {
+    fn start(client: Client, con: Connection, node: u64, key: String, token: String, ttl: Duration, sent: Instant) -> RedisLease {
+        let held = Arc::new(Held::new(ttl, sent));
+        let (stop, stopped) = mpsc::channel();
+        let renewer = Renewer {
+            node,
+            client,
+            con: Some(con),
+            key,
+            token,
+            ttl,
+            held: held.clone(),
+        };
+        let handle = thread::Builder::new()
+            .name(format!("flaken-redis-lease-{}", node))
+            .spawn(move || renewer.run(stopped))
+            .expect("failed to spawn redis lease renewer");
+        RedisLease {
+            node,
+            held,
+            stop: Some(stop),
+            renewer: Some(handle),
+        }
+    }
+
+    /// Whether the lease is still held
+    ///
+    /// This becomes false a renew interval, a third of the TTL, before the
+    /// key expires unless renewed, so before another client may claim the
+    /// same node id, even while the renewer is stuck on a slow connection.
+    pub fn is_held(&self) -> bool {
+        self.held.is_held()
+    }
+}
+
+impl Lease for RedisLease {
+    fn node(&self) -> u64 {
+        self.node
+    }
+}
+
+impl Drop for RedisLease {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(handle) = self.renewer.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+struct Renewer {
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    node: u64,
+    client: Client,
+    con: Option<Connection>,
+    key: String,
+    token: String,
+    ttl: Duration,
+    held: Arc<Held>,
+}
+
+/// Until when a lease counts as held, a renew interval short of its TTL
+#[derive(Debug)]
+struct Held {
+    origin: Instant,
+    ttl: Duration,
+    /// Nanoseconds since the origin, 0 once released or taken over
+    until: AtomicU64,
+}
+
+impl Held {
+    /// Held from a claim sent at `sent`
+    fn new(ttl: Duration, sent: Instant) -> Held {
+        let held = Held {
+            origin: sent,
+            ttl,
+            until: AtomicU64::new(0),
+        };
+        held.renewed(held.origin);
+        held
+    }
+
+    /// The key's TTL was reset by a command sent at `sent`
+    fn renewed(&self, sent: Instant) {
+        let until = sent.duration_since(self.origin) + self.ttl - self.ttl / 3;
+        self.until.store(until.as_nanos() as u64, Ordering::Release);
+    }
+
+    fn is_held(&self) -> bool {
+        (self.origin.elapsed().as_nanos() as u64) < self.until.load(Ordering::Acquire)
+    }
+
+    fn release(&self) {
+        self.until.store(0, Ordering::Release);
+    }
+}
+
+impl Renewer {
+    fn run(mut self, stopped: mpsc::Receiver<()>) {
+        let renew = Script::new(RENEW_SCRIPT);
+        while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(self.ttl / 3) {
+            let ttl_ms = self.ttl.as_millis() as u64;
+            let key = self.key.clone();
+            let token = self.token.clone();
+            let sent = Instant::now();
+            match self.invoke(|con| renew.key(&key).arg(&token).arg(ttl_ms).invoke::<i64>(con)) {
+                Ok(1) => {
+                    trace_event!(trace, node = self.node, "renewed redis node lease");
+                    self.held.renewed(sent)
+                }
+                Ok(_) => {
+                    trace_event!(warn, node = self.node, "redis node lease was taken over");
+                    break;
+                }
+                Err(_err) if !self.held.is_held() => {
+                    trace_event!(warn, node = self.node, error = %_err, "redis node lease expired");
+                    break;
+                }
+                Err(_err) => {
+                    trace_event!(debug, node = self.node, error = %_err, "failed to renew redis node lease, retrying");
+                }
+            }
+        }
+        self.held.release();
+        let release = Script::new(RELEASE_SCRIPT);
+        let key = self.key.clone();
+        let token = self.token.clone();
+        let _ = self.invoke(|con| release.key(&key).arg(&token).invoke::<i64>(con));
+    }
+
+    /// Run a command, reconnecting first if the last one failed
+    fn invoke<T, F>(&mut self, f: F) -> RedisResult<T>
+    where
+        F: FnOnce(&mut Connection) -> RedisResult<T>,
+    {
+        let mut con = match self.con.take() {
+            Some(con) => con,
+            None => self.client.get_connection_with_timeout(self.ttl / 3)?,
+        };
+        let res = f(&mut con);
+        if res.is_ok() {
+            self.con = Some(con);
+        }
+        res
+    }
+}
+
+/// A value unique to this claim so only its holder renews or releases it
+fn token() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("{}-{}-{}", process::id(), now.as_secs(), now.subsec_nanos())
+}
+
+#[test]
+fn test_redis_held() {
+    let held = Held::new(Duration::from_millis(30), Instant::now());
+    assert!(held.is_held());
+    // Without a renewal it lapses a renew interval before the key expires
+    thread::sleep(Duration::from_millis(21));
+    assert!(!held.is_held());
+    held.renewed(Instant::now());
+    assert!(held.is_held());
+    held.release();
+    assert!(!held.is_held());
+}
@@ -0,0 +1,123 @@
+//! Node ids shared between generators within one process
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use crate::node::{Lease, NodeAllocator};
+use crate::skew::wall_ms;
+use crate::Error;
+
+/// A pool of node ids handed out to generators within a process
+///
+/// Each generator, for instance one per worker thread, claims its own
+/// [`NodeLease`] which returns the node id to the pool when dropped. The
+/// registry is cheap to clone, clones share the same pool.
+///
+/// A node id returned with a [`hold_until`](trait.Lease.html#method.hold_until)
+/// is only claimed again at that time, so a generator taking over a node id
+/// starts after the last tick of the one before.
+///
+/// ```
+/// use flaken::node::{Lease, NodeAllocator, NodeRegistry};
+/// use flaken::Flaken;
+///
+/// let registry = NodeRegistry::new(0..4);
+/// let lease = registry.claim().unwrap();
+/// let mut flake = Flaken::default().node(lease.node());
+/// let other = registry.claim().unwrap();
+/// assert_ne!(lease.node(), other.node());
+/// ```
+#[derive(Clone, Debug)]
+pub struct NodeRegistry {
+    /// Free node ids and the timestamp each may be claimed from
+    free: Arc<Mutex<BTreeMap<u64, u64>>>,
+}
+
+impl NodeRegistry {
+    /// Build a registry owning the node ids in `nodes`
+    pub fn new(nodes: Range<u64>) -> NodeRegistry {
+        NodeRegistry {
+            free: Arc::new(Mutex::new(nodes.map(|node| (node, 0)).collect())),
+        }
+    }
+
+    /// Number of node ids not currently leased, including held ones
+    pub fn available(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+}
+
+impl NodeAllocator for NodeRegistry {
+    type Lease = NodeLease;
+
+    fn claim(&self) -> Result<NodeLease, Error> {
+        self.claim_at(wall_ms())
+    }
+
+    fn claim_at(&self, ts: u64) -> Result<NodeLease, Error> {
+        let mut free = self.free.lock().unwrap();
+        let node = match free.iter().find(|&(_, &from)| from <= ts) {
+            Some((&node, _)) => node,
+            None => return Err(Error::NodesExhausted),
+        };
+        free.remove(&node);
+        Ok(NodeLease {
+            node,
+            hold: 0,
+            free: self.free.clone(),
+        })
+    }
+}
+
+/// A node id leased from a [`NodeRegistry`], returned to it when dropped
+#[derive(Debug)]
+pub struct NodeLease {
+    node: u64,
+    hold: u64,
+    free: Arc<Mutex<BTreeMap<u64, u64>>>,
+}
+
+impl Lease for NodeLease {
+    fn node(&self) -> u64 {
+        self.node
+    }
+
+    fn hold_until(&mut self, ts: u64) {
+        self.hold = self.hold.max(ts);
+    }
+}
+
+impl Drop for NodeLease {
+    fn drop(&mut self) {
+        if let Ok(mut free) = self.free.lock() {
+            free.insert(self.node, self.hold);
+        }
+    }
+}
+
+#[test]
+fn test_registry_leases() {
+    let registry = NodeRegistry::new(3..5);
+    let a = registry.claim().unwrap();
+    let b = registry.clone().claim().unwrap();
+    assert_eq!((a.node(), b.node()), (3, 4));
+    assert_eq!(registry.available(), 0);
+    assert!(matches!(registry.claim(), Err(Error::NodesExhausted)));
+    drop(a);
+    assert_eq!(registry.available(), 1);
+    assert_eq!(registry.claim().unwrap().node(), 3);
+}
+
+#[test]
+fn test_registry_hold() {
+    let registry = NodeRegistry::new(0..1);
+    let mut lease = registry.claim().unwrap();
+    let ts = wall_ms() + 60_000;
+    lease.hold_until(ts);
+    drop(lease);
+    assert_eq!(registry.available(), 1);
+    assert!(matches!(registry.claim(), Err(Error::NodesExhausted)));
+    assert!(matches!(registry.claim_at(ts - 1), Err(Error::NodesExhausted)));
+    assert_eq!(registry.claim_at(ts).unwrap().node(), 0);
+}
@@ -0,0 +1,157 @@
+//! Node ids registered as ZooKeeper sequential ephemeral znodes
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use zookeeper::{Acl, CreateMode, ZkState, ZooKeeper, ZooKeeperExt};
+
+use crate::node::{Lease, NodeAllocator};
+use crate::Error;
+
+const MEMBER_PREFIX: &str = "node-";
+
+/// Claims node ids by registering sequential ephemeral znodes
+///
+/// Every claim opens its own ZooKeeper session and creates an ephemeral
+/// sequential znode `{root}/node-{seq}`, its node id being `seq % nodes`. If
+/// an older member, one with a lower sequence number, already maps to the
+/// same node id the znode is deleted and registration is retried. This is
+/// the registration scheme of the original snowflake daemons, so the same
+/// `root` may be shared with them as long as they agree on `nodes`.
+///
+/// The znode disappears when the lease is dropped or its session expires.
+///
+/// ```no_run
+/// use flaken::node::{Lease, NodeAllocator, ZooKeeperNodeAllocator};
+/// use flaken::Flaken;
+///
+/// let allocator = ZooKeeperNodeAllocator::new("127.0.0.1:2181", "/flaken/nodes", 1024);
+/// let lease = allocator.claim().unwrap();
+/// let mut flake = Flaken::default().node(lease.node());
+/// let id = flake.next();
+/// ```
+#[derive(Clone, Debug)]
+pub struct ZooKeeperNodeAllocator {
+    connect: String,
+    root: String,
+    nodes: u64,
+    timeout: Duration,
+}
+
+impl ZooKeeperNodeAllocator {
+    /// Build an allocator handing out node ids `0..nodes` with a session timeout of 10 seconds
+    pub fn new(connect: &str, root: &str, nodes: u64) -> ZooKeeperNodeAllocator {
+        assert!(nodes > 0);
+        ZooKeeperNodeAllocator {
+            connect: connect.to_string(),
+            root: root.trim_end_matches('/').to_string(),
+            nodes,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Set the ZooKeeper session timeout
+    pub fn session_timeout(mut self, timeout: Duration) -> ZooKeeperNodeAllocator {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl NodeAllocator for ZooKeeperNodeAllocator {
+    type Lease = ZooKeeperLease;
+
+    fn claim(&self) -> Result<ZooKeeperLease, Error> {
+        let zk = ZooKeeper::connect(&self.connect, self.timeout, |_| {})?;
+        let held = Arc::new(AtomicBool::new(true));
+        {
+            let held = held.clone();
+            zk.add_listener(move |state| {
+                if let ZkState::Closed | ZkState::AuthFailed = state {
+                    held.store(false, Ordering::Release);
+                }
+            });
+        }
+        zk.ensure_path(&self.root)?;
+        let prefix = format!("{}/{}", self.root, MEMBER_PREFIX);
+        for _ in 0..self.nodes {
+            let path = zk.create(&prefix, Vec::new(), Acl::open_unsafe().clone(), CreateMode::EphemeralSequential)?;
+            let seq = path.rsplit('/').next().and_then(member_seq).expect("znode without a sequence number");
+            let node = seq % self.nodes;
+            let members = zk.get_children(&self.root, false)?;
+            let taken = members
+                .iter()
+                .filter_map(|member| member_seq(member))
+                .any(|other| other < seq && other % self.nodes == node);
+            if !taken {
+                return Ok(ZooKeeperLease { zk, path, node, held });
+            }
+            zk.delete(&path, None)?;
+        }
+        let _ = zk.close();
+        Err(Error::NodesExhausted)
+    }
+}
+
+/// A node id held by a ZooKeeper session until dropped
+pub struct ZooKeeperLease {
+    zk: ZooKeeper,
+    path: String,
+    node: u64,
+    held: Arc<AtomicBool>,
+}
+
+impl ZooKeeperLease {
+    /// The path of the registered znode
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Whether the lease is still held
+    ///
+    /// This becomes false once the ZooKeeper session is closed, which deletes
+    /// the ephemeral znode and lets another client claim the same node id.
+    pub fn is_held(&self) -> bool {
+        self.held.load(Ordering::Acquire)
+    }
+}
+
+impl fmt::Debug for ZooKeeperLease {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ZooKeeperLease")
+            .field("path", &self.path)
+            .field("node", &self.node)
+            .field("held", &self.is_held())
+            .finish()
+    }
+}
+
+impl Lease for ZooKeeperLease {
+    fn node(&self) -> u64 {
+        self.node
+    }
+}
+
+impl Drop for ZooKeeperLease {
+    fn drop(&mut self) {
+        let _ = self.zk.delete(&self.path, None);
+        let _ = self.zk.close();
+    }
+}
+
+/// The sequence number of a member znode name such as `node-0000000042`
+fn member_seq(name: &str) -> Option<u64> {
+    if !name.starts_with(MEMBER_PREFIX) {
+        return None;
+    }
+    name[MEMBER_PREFIX.len()..].parse().ok()
+}
+
+#[test]
+fn test_member_seq() {
+    assert_eq!(member_seq("node-0000000042"), Some(42));
+    assert_eq!(member_seq("node-2147483647"), Some(2147483647));
+    assert_eq!(member_seq("other-0000000042"), None);
+    assert_eq!(member_seq("node-"), None);
+}
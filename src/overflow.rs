@@ -0,0 +1,139 @@
+//! What generation does once the clock runs past the end of the layout
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::layout::field_max;
+use crate::{Error, Flaken};
+
+/// How a generator handles the clock running past the latest timestamp its
+/// layout can encode
+///
+/// By default the timestamp wraps around to the start of its field, as it
+/// always has, which reissues ids of the epoch; any other policy stops
+/// instead. [`Flaken::encode`] does mask its fields, [`Flaken::try_encode`]
+/// checks them instead.
+#[derive(Clone, Default)]
+pub enum TsOverflow {
+    /// Mask the timestamp to its field, so ids start over from the epoch
+    ///
+    /// Ids no longer increase or stay unique once this happens. A generator
+    /// with [`strict_order`](struct.Flaken.html#method.strict_order) fails as
+    /// with `Error` instead.
+    #[default]
+    Wrap,
+    /// Fail with [`Error::OutOfRange`](enum.Error.html#variant.OutOfRange),
+    /// `next` panics with it
+    Error,
+    /// Keep using the latest timestamp until its sequence values are used
+    /// up, then fail as with `Error`
+    Saturate,
+    /// Call the handler with the timestamp which did not fit, then fail as
+    /// with `Error`, such as to alert before `next` panics
+    Handler(Arc<dyn Fn(u64) + Send + Sync>),
+}
+
+impl TsOverflow {
+    /// Call `handler` on overflow, see [`TsOverflow::Handler`]
+    pub fn handler<F: Fn(u64) + Send + Sync + 'static>(handler: F) -> TsOverflow {
+        TsOverflow::Handler(Arc::new(handler))
+    }
+}
+
+impl fmt::Debug for TsOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TsOverflow::Wrap => write!(f, "Wrap"),
+            TsOverflow::Error => write!(f, "Error"),
+            TsOverflow::Saturate => write!(f, "Saturate"),
+            TsOverflow::Handler(_) => write!(f, "Handler"),
+        }
+    }
+}
+
+impl Flaken {
+    /// Set how generation handles the clock running past the latest
+    /// timestamp the layout can encode, wrapping around by default
+    pub fn on_ts_overflow(mut self, overflow: TsOverflow) -> Flaken {
+        self.overflow = overflow;
+        self
+    }
+
+    /// The latest tick the layout can encode, counted like `elapsed_ticks`,
+    /// when saturating and the generator was built before it
+    pub(crate) fn saturate_at(&self) -> Option<u64> {
+        if !matches!(self.overflow, TsOverflow::Saturate) {
+            return None;
+        }
+        let first = self.tick_ts(0);
+        let last = self.layout.tick_floor(self.layout.max_ts());
+        last.checked_sub(first).map(|span| span / self.layout.tick_ms)
+    }
+
+    /// Encode a generated id of the generator's node, failing when the node
+    /// does not fit the layout or the timestamp overflows without wrapping
+    pub(crate) fn encode_generated(&self, ts: u64, seq: u64) -> Result<u64, Error> {
+        if self.node > field_max(self.layout.node_bits) {
+            return Err(Error::NodeOverflow(self.node));
+        }
+        if ts > self.layout.max_ts() && matches!(self.overflow, TsOverflow::Wrap) && !self.strict {
+            trace_event!(warn, node = self.node, ts, "timestamp overflowed the layout, wrapping around");
+            return Ok(self.layout.masks().encode(ts, self.node, seq));
+        }
+        self.layout.try_encode(ts, self.node, seq)
+    }
+}
+
+#[test]
+fn test_saturate() {
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::skew::wall_ms;
+    use crate::Error;
+
+    // 20 timestamp bits run out a millisecond from now, with 16 ids a tick
+    let mut flake = Flaken::default()
+        .epoch(wall_ms() - (1 << 20) + 2)
+        .bitwidths(20, 40)
+        .on_ts_overflow(TsOverflow::Saturate);
+    let max_ts = flake.layout.max_ts();
+    thread::sleep(Duration::from_millis(5));
+    for _ in 0..16 {
+        let id = flake.try_next().unwrap();
+        assert_eq!(flake.decode(id).0, max_ts);
+    }
+    assert!(matches!(flake.try_next(), Err(Error::OutOfRange(_))));
+}
+
+#[test]
+fn test_overflow_handler() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let overflowed = Arc::new(AtomicU64::new(0));
+    let mut flake = {
+        let overflowed = overflowed.clone();
+        Flaken::default()
+            .epoch(0)
+            .bitwidths(40, 10)
+            .on_ts_overflow(TsOverflow::handler(move |ts| overflowed.store(ts, Ordering::Relaxed)))
+    };
+    assert!(flake.try_next().is_err());
+    assert!(overflowed.load(Ordering::Relaxed) >= 1 << 40);
+}
+
+#[test]
+fn test_wrap() {
+    // 40 timestamp bits from 1970 ran out in 2004
+    let mut flake = Flaken::default().epoch(0).bitwidths(40, 10);
+    let id = flake.next();
+    assert!(flake.decode(id).0 < 1 << 40);
+    let mut flake = Flaken::default().epoch(0).bitwidths(40, 10).strict_order(true);
+    assert!(matches!(flake.try_next(), Err(Error::OutOfRange(_))));
+}
+
+#[test]
+fn test_node_overflow() {
+    let mut flake = Flaken::default().bitwidths(40, 2).node(4);
+    assert!(matches!(flake.try_next(), Err(Error::NodeOverflow(4))));
+}
@@ -0,0 +1,42 @@
+//! postgres column types for ids, behind the `postgres` feature
+//!
+//! Ids are stored as `INT8` for the synchronous `postgres` client and
+//! `tokio-postgres`, as signed integers the way
+//! [`Id`](../struct.Id.html#databases) describes.
+
+use std::error;
+
+use bytes::BytesMut;
+use postgres_types::{accepts, to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+use crate::Id;
+
+impl<T> ToSql for Id<T> {
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn error::Error + Sync + Send>> {
+        self.to_signed()?.to_sql(ty, out)
+    }
+
+    accepts!(INT8);
+
+    to_sql_checked!();
+}
+
+impl<'a, T> FromSql<'a> for Id<T> {
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Id<T>, Box<dyn error::Error + Sync + Send>> {
+        Ok(Id::from_signed(i64::from_sql(ty, raw)?)?)
+    }
+
+    accepts!(INT8);
+}
+
+#[test]
+fn test_postgres_round_trip() {
+    let id: Id = Id::new(0x0102_0304_0506_0708);
+    let mut buf = BytesMut::new();
+    id.to_sql_checked(&Type::INT8, &mut buf).unwrap();
+    assert_eq!(&buf[..], &[1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(Id::<()>::from_sql(&Type::INT8, &buf).unwrap(), id);
+    assert!(id.to_sql_checked(&Type::INT4, &mut buf).is_err());
+    assert!(Id::<()>::new(1 << 63).to_sql(&Type::INT8, &mut buf).is_err());
+    assert!(Id::<()>::from_sql(&Type::INT8, &[0xFF; 8]).is_err());
+}
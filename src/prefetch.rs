@@ -0,0 +1,91 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use crate::Flaken;
+
+/// A generator running on a background thread, keeping a bounded channel
+/// topped up with ready ids
+///
+/// Consumers take ids out of the channel with next to no latency, even while
+/// the sequence of the current millisecond is used up and the filler thread
+/// waits for the next one.
+///
+/// ```
+/// use flaken::{Flaken, PrefetchedFlaken};
+///
+/// let ids = PrefetchedFlaken::new(Flaken::default().node(3), 1024);
+/// let id0 = ids.recv();
+/// let id1 = ids.recv();
+/// assert!(id1 > id0);
+/// ```
+#[derive(Debug)]
+pub struct PrefetchedFlaken {
+    ids: Option<Receiver<u64>>,
+    filler: Option<thread::JoinHandle<()>>,
+}
+
+impl PrefetchedFlaken {
+    /// Move `flake` onto a filler thread keeping up to `capacity` ids ready
+    pub fn new(mut flake: Flaken, capacity: usize) -> PrefetchedFlaken {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let filler = thread::Builder::new()
+            .name("flaken-prefetch".to_string())
+            .spawn(move || while tx.send(flake.next()).is_ok() {})
+            .expect("failed to spawn flaken prefetch thread");
+        PrefetchedFlaken {
+            ids: Some(rx),
+            filler: Some(filler),
+        }
+    }
+
+    fn ids(&self) -> &Receiver<u64> {
+        self.ids.as_ref().unwrap()
+    }
+
+    /// Take the next id, waiting for the filler if none is ready
+    pub fn recv(&self) -> u64 {
+        self.ids().recv().expect("flaken prefetch thread stopped")
+    }
+
+    /// Take the next id if one is ready
+    pub fn try_recv(&self) -> Option<u64> {
+        match self.ids().try_recv() {
+            Ok(id) => Some(id),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => panic!("flaken prefetch thread stopped"),
+        }
+    }
+
+    /// Take the next id, waiting up to `timeout` for the filler if none is ready
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<u64> {
+        match self.ids().recv_timeout(timeout) {
+            Ok(id) => Some(id),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => panic!("flaken prefetch thread stopped"),
+        }
+    }
+}
+
+impl Drop for PrefetchedFlaken {
+    fn drop(&mut self) {
+        // Dropping the receiver fails the filler's next send which stops it
+        self.ids.take();
+        if let Some(filler) = self.filler.take() {
+            let _ = filler.join();
+        }
+    }
+}
+
+#[test]
+fn test_prefetch() {
+    let ids = PrefetchedFlaken::new(Flaken::default().bitwidths(40, 22), 16);
+    let mut last = ids.recv();
+    for _ in 0..100 {
+        let id = ids.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(id > last);
+        last = id;
+    }
+    thread::sleep(Duration::from_millis(10));
+    assert!(ids.try_recv().unwrap() > last);
+}
@@ -0,0 +1,62 @@
+//! A small non cryptographic random number generator
+//!
+//! Seeded from the per process random keys of the standard library hasher so
+//! no extra dependency is needed for picking random node ids and the like.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// SplitMix64 generator
+#[derive(Clone, Debug)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    /// Build a generator from a random seed
+    pub(crate) fn new() -> Rng {
+        let mut hasher = RandomState::new().build_hasher();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        hasher.write_u64(now.as_secs());
+        hasher.write_u32(now.subsec_nanos());
+        Rng(hasher.finish())
+    }
+
+    /// The next 64 random bits
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        mix(self.0)
+    }
+
+    /// A random value in `0..n`
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        assert!(n > 0);
+        // Reject the top partial range so every value is equally likely
+        let zone = u64::MAX - (u64::MAX - n + 1) % n;
+        loop {
+            let v = self.next_u64();
+            if v <= zone {
+                return v % n;
+            }
+        }
+    }
+}
+
+/// SplitMix64's finalizer, spreading nearby values over all 64 bits
+///
+/// Bucket assignment depends on it, so it must never change.
+pub(crate) fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+#[test]
+fn test_below() {
+    let mut rng = Rng::new();
+    let mut seen = [false; 7];
+    for _ in 0..1000 {
+        seen[rng.below(7) as usize] = true;
+    }
+    assert!(seen.iter().all(|&s| s));
+    assert_eq!(rng.below(1), 0);
+}
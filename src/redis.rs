@@ -0,0 +1,95 @@
+//! redis values for ids, behind the `redis` feature
+//!
+//! Ids are written as decimal arguments and read back from integer or
+//! string replies, so they may be used as keys, members, and values.
+//!
+//! Sorted set scores are 64 bit floats which hold integers exactly only up
+//! to 2^53, so [`Id::to_score`] fails on larger ids rather than letting
+//! neighbouring ids share a score. Ids from the default layout exceed that
+//! within days of the epoch, so score by
+//! [`Flaken::score_of`](struct.Flaken.html#method.score_of) instead.
+
+use redis::{FromRedisValue, NumericBehavior, ParsingError, RedisWrite, ToRedisArgs, Value};
+
+use crate::{Error, Flaken, Id};
+
+/// The largest integer a 64 bit float holds exactly, along with everything below it
+const MAX_EXACT_SCORE: u64 = 1 << 53;
+
+impl<T> ToRedisArgs for Id<T> {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        self.get().write_redis_args(out)
+    }
+
+    fn describe_numeric_behavior(&self) -> NumericBehavior {
+        NumericBehavior::NumberIsInteger
+    }
+}
+
+impl<T> FromRedisValue for Id<T> {
+    fn from_redis_value(v: Value) -> Result<Id<T>, ParsingError> {
+        match v {
+            Value::Int(id) => Id::from_signed(id).map_err(|err| ParsingError::from(err.to_string())),
+            v => u64::from_redis_value(v).map(Id::new),
+        }
+    }
+}
+
+impl<T> Id<T> {
+    /// The id as a sorted set score, failing when it is not exactly representable
+    pub fn to_score(self) -> Result<f64, Error> {
+        if self.get() > MAX_EXACT_SCORE {
+            Err(Error::Imprecise(self.get()))
+        } else {
+            Ok(self.get() as f64)
+        }
+    }
+
+    /// An id from a sorted set score, failing on fractional, negative, or
+    /// inexact scores
+    pub fn from_score(score: f64) -> Result<Id<T>, Error> {
+        if score.fract() == 0.0 && score >= 0.0 && score <= MAX_EXACT_SCORE as f64 {
+            Ok(Id::new(score as u64))
+        } else {
+            Err(Error::Imprecise(score as u64))
+        }
+    }
+}
+
+impl Flaken {
+    /// A sorted set score ordering ids by their timestamp
+    ///
+    /// The score is the milliseconds since the epoch, which stays exact for
+    /// hundreds of thousands of years. Ids of the same millisecond share a
+    /// score and are ordered by member, which for decimal ids of the same
+    /// length is their order.
+    pub fn score_of(&self, id: u64) -> f64 {
+        (self.decode(id).0 - self.layout.epoch) as f64
+    }
+}
+
+#[test]
+fn test_redis_args() {
+    let id: Id = Id::new(u64::MAX);
+    assert_eq!(id.to_redis_args(), vec![b"18446744073709551615".to_vec()]);
+    assert_eq!(Id::<()>::from_redis_value(Value::BulkString(id.to_redis_args().remove(0))).unwrap(), id);
+    assert_eq!(Id::<()>::from_redis_value(Value::Int(42)).unwrap(), Id::new(42));
+    assert!(Id::<()>::from_redis_value(Value::Int(-1)).is_err());
+}
+
+#[test]
+fn test_redis_score() {
+    let exact: Id = Id::new(MAX_EXACT_SCORE);
+    assert_eq!(Id::<()>::from_score(exact.to_score().unwrap()).unwrap(), exact);
+    assert!(Id::<()>::new(MAX_EXACT_SCORE + 1).to_score().is_err());
+    assert!(Id::<()>::from_score(1.5).is_err());
+    assert!(Id::<()>::from_score(-1.0).is_err());
+    let mut flake = Flaken::default();
+    let id0 = flake.next();
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    let id1 = flake.next();
+    assert!(flake.score_of(id0) < flake.score_of(id1));
+}
@@ -0,0 +1,345 @@
+//! Claiming extra node ids when one node's sequence space is not enough
+//!
+//! A generator whose ticks keep running out of sequence values waits for
+//! the next tick every time. With node rotation it instead claims another
+//! node id from an allocator once saturation crosses a threshold, and moves
+//! on to the next node id within a tick rather than waiting. Node ids are
+//! used in ascending order within each tick, so ids still increase.
+//!
+//! Sequence borrowing is the same within a single node id: the low bits of
+//! the node field are reserved for sub-ids, and a saturating generator
+//! borrows sub-ids from a registry, handing them back once the load drops.
+//! The layout stays the same, decoders only see more node ids.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::node::{Lease, NodeAllocator, NodeRegistry};
+use crate::{Error, Flaken};
+
+/// Clock time over which saturation is measured before claiming another
+/// node id
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Claims a node id for generating ids from a timestamp on
+type Claim = Box<dyn FnMut(u64) -> Result<Box<dyn Lease + Send + Sync>, Error> + Send + Sync>;
+
+pub(crate) struct NodeRotation {
+    claim: Claim,
+    threshold: f64,
+    /// Node ids in use, ascending, filled with the generator's own on first use
+    nodes: Vec<u64>,
+    current: usize,
+    leases: Vec<Box<dyn Lease + Send + Sync>>,
+    /// Tick the current window started at
+    window_start: u64,
+    window_saturated: u64,
+    /// Give back the latest claimed node id once a window does not saturate
+    release: bool,
+}
+
+impl NodeRotation {
+    pub(crate) fn nodes(&self) -> &[u64] {
+        &self.nodes
+    }
+
+    /// The node id a generator whose tick ran out on the current node id
+    /// moves on to
+    pub(crate) fn next_node(&self) -> Option<u64> {
+        self.nodes.get(self.current + 1).copied()
+    }
+
+    /// Keep the claimed node ids from being reused before `ts` once released
+    pub(crate) fn hold_leases(&mut self, ts: u64) {
+        for lease in self.leases.iter_mut() {
+            lease.hold_until(ts);
+        }
+    }
+}
+
+impl fmt::Debug for NodeRotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NodeRotation")
+            .field("threshold", &self.threshold)
+            .field("nodes", &self.nodes)
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+impl Flaken {
+    /// Claim extra node ids from `allocator` while more than `threshold` of
+    /// the ticks in a second of clock time run out of sequence values
+    ///
+    /// Ticks in which no ids were generated count as not running out, so
+    /// short bursts on an otherwise idle generator do not claim node ids.
+    /// Claimed node ids are held until the generator is dropped, and go back
+    /// to the allocator held until the tick of its latest id has passed, see
+    /// [`Lease::hold_until`](node/trait.Lease.html#method.hold_until). A claim
+    /// failing, for instance with every node id taken, is retried after the
+    /// next second of saturation.
+    ///
+    /// ```
+    /// use flaken::node::NodeRegistry;
+    /// use flaken::Flaken;
+    ///
+    /// let registry = NodeRegistry::new(1..8);
+    /// let mut flake = Flaken::default().node(0).node_rotation(registry, 0.5);
+    /// flake.next();
+    /// ```
+    pub fn node_rotation<A>(mut self, allocator: A, threshold: f64) -> Flaken
+    where
+        A: NodeAllocator + Send + Sync + 'static,
+        A::Lease: Send + Sync + 'static,
+    {
+        let claim: Claim = Box::new(move |ts| allocator.claim_at(ts).map(|lease| Box::new(lease) as Box<dyn Lease + Send + Sync>));
+        self.rotation = Some(NodeRotation {
+            claim,
+            threshold,
+            nodes: Vec::new(),
+            current: 0,
+            leases: Vec::new(),
+            window_start: 0,
+            window_saturated: 0,
+            release: false,
+        });
+        self
+    }
+
+    /// Borrow sub-ids from `registry` into the low `sub_bits` of the node
+    /// field while more than `threshold` of the ticks in a second of clock
+    /// time run out of sequence values
+    ///
+    /// The generator's node id, set before calling this, moves up by
+    /// `sub_bits` and uses sub-id 0 itself, so the registry holds sub-ids
+    /// from 1 up to `2^sub_bits - 1` and is shared by the generators with
+    /// the same node id within a process. A borrowed sub-id is handed back
+    /// after a second without saturation.
+    ///
+    /// Panics when the node id shifted by `sub_bits` does not fit the node
+    /// field.
+    ///
+    /// ```
+    /// use flaken::node::NodeRegistry;
+    /// use flaken::Flaken;
+    ///
+    /// let registry = NodeRegistry::new(1..4);
+    /// let mut flake = Flaken::default().node(5).sequence_borrowing(registry, 2, 0.5);
+    /// let id = flake.next();
+    /// assert_eq!(flake.decode(id).1, 5 << 2);
+    /// ```
+    pub fn sequence_borrowing(mut self, registry: NodeRegistry, sub_bits: u64, threshold: f64) -> Flaken {
+        let node_bits = self.layout.node_bits;
+        if sub_bits > node_bits || node_bits - sub_bits < 64 - self.node.leading_zeros() as u64 {
+            panic!("node id {} with {} sub-id bits does not fit {} node bits", self.node, sub_bits, node_bits);
+        }
+        let base = self.node << sub_bits;
+        self.node = base;
+        let claim: Claim = Box::new(move |ts| {
+            let lease = registry.claim_at(ts)?;
+            match lease.node() {
+                0 => Err(Error::NodeOverflow(0)),
+                sub if sub >> sub_bits != 0 => Err(Error::NodeOverflow(sub)),
+                sub => Ok(Box::new(Borrowed { node: base | sub, lease }) as Box<dyn Lease + Send + Sync>),
+            }
+        });
+        self.rotation = Some(NodeRotation {
+            claim,
+            threshold,
+            nodes: Vec::new(),
+            current: 0,
+            leases: Vec::new(),
+            window_start: 0,
+            window_saturated: 0,
+            release: true,
+        });
+        self
+    }
+
+    /// Move on to the next node id of the tick, false when there is none
+    pub(crate) fn switch_node(&mut self) -> bool {
+        let next = match self.rotation {
+            Some(ref mut rotation) if rotation.current + 1 < rotation.nodes.len() => {
+                rotation.current += 1;
+                rotation.nodes[rotation.current]
+            }
+            _ => return false,
+        };
+        self.node = next;
+        self.seq = self.first_seq();
+        true
+    }
+
+    /// Start `tick` on the lowest node id, claiming another one if the
+    /// last window saturated too often
+    ///
+    /// A window spans a second of the clock, and its saturation is the share
+    /// of all its ticks that ran out of sequence values, so ticks that pass
+    /// without any ids count as not saturated.
+    pub(crate) fn rotate_new_tick(&mut self, tick: u64) {
+        let saturated = self.saturated;
+        let window = self.duration_to_ticks(WINDOW).max(1);
+        let ts = self.tick_ts(tick);
+        let rotation = match self.rotation {
+            Some(ref mut rotation) => rotation,
+            None => return,
+        };
+        if rotation.nodes.is_empty() {
+            rotation.nodes.push(self.node);
+            rotation.window_start = tick;
+            rotation.window_saturated = saturated;
+        }
+        let elapsed = tick.saturating_sub(rotation.window_start);
+        if elapsed >= window {
+            let ratio = (saturated - rotation.window_saturated) as f64 / elapsed as f64;
+            if ratio > rotation.threshold {
+                match (rotation.claim)(ts) {
+                    Ok(lease) => {
+                        let node = lease.node();
+                        trace_event!(info, node, saturation = ratio, "claimed another node id");
+                        if let Err(idx) = rotation.nodes.binary_search(&node) {
+                            rotation.nodes.insert(idx, node);
+                        }
+                        rotation.leases.push(lease);
+                    }
+                    Err(_err) => {
+                        trace_event!(warn, node = self.node, error = %_err, "failed to claim another node id");
+                    }
+                }
+            } else if rotation.release && ratio == 0.0 {
+                if let Some(mut lease) = rotation.leases.pop() {
+                    // Its last ids were in an earlier tick
+                    lease.hold_until(ts);
+                    let node = lease.node();
+                    trace_event!(info, node, "released a borrowed node id");
+                    rotation.nodes.retain(|&n| n != node);
+                }
+            }
+            rotation.window_start = tick;
+            rotation.window_saturated = saturated;
+        }
+        rotation.current = 0;
+        self.node = rotation.nodes[0];
+    }
+}
+
+/// A sub-id leased from a registry, as a node id of the generator
+struct Borrowed {
+    node: u64,
+    lease: crate::node::NodeLease,
+}
+
+impl Lease for Borrowed {
+    fn node(&self) -> u64 {
+        self.node
+    }
+
+    fn hold_until(&mut self, ts: u64) {
+        self.lease.hold_until(ts);
+    }
+}
+
+#[test]
+fn test_node_rotation() {
+    use std::collections::HashSet;
+
+    use crate::clock::ManualClock;
+    use crate::node::NodeRegistry;
+
+    let registry = NodeRegistry::new(1..4);
+    let mut flake = Flaken::default()
+        .bitwidths(40, 20)
+        .node(0)
+        // A millisecond every 64 reads
+        .clock(ManualClock::stepping(64))
+        .node_rotation(registry.clone(), 0.5);
+    let mut ids = HashSet::new();
+    let mut last = 0;
+    for _ in 0..40_000 {
+        let id = flake.next();
+        assert!(id > last);
+        assert!(ids.insert(id));
+        last = id;
+    }
+    let status = flake.status();
+    assert!(status.nodes > 1);
+    assert_eq!(registry.available(), 4 - status.nodes);
+    let last_ts = flake.decode(last).0;
+    drop(flake);
+    assert_eq!(registry.available(), 3);
+    // The node ids it claimed are held past its last tick
+    let leases: Vec<_> = (0..3).map_while(|_| registry.claim_at(last_ts).ok()).collect();
+    assert_eq!(leases.len(), 4 - status.nodes);
+}
+
+#[test]
+fn test_sequence_borrowing() {
+    use crate::clock::ManualClock;
+
+    let clock = ManualClock::stepping(64);
+    let registry = NodeRegistry::new(1..4);
+    let mut flake = Flaken::default()
+        .bitwidths(40, 20)
+        .node(3)
+        .clock(clock.clone())
+        .sequence_borrowing(registry.clone(), 2, 0.5);
+    let mut last = 0;
+    let mut next = |flake: &mut Flaken| {
+        let id = flake.next();
+        assert!(id > last);
+        assert_eq!(flake.decode(id).1 >> 2, 3);
+        last = id;
+    };
+    for _ in 0..40_000 {
+        next(&mut flake);
+    }
+    assert!(registry.available() < 3);
+    // Once ticks stop saturating the borrowed sub-ids go back
+    clock.set_reads_per_ms(1);
+    for _ in 0..10_000 {
+        next(&mut flake);
+    }
+    assert_eq!(registry.available(), 3);
+}
+
+#[test]
+fn test_node_rotation_idle() {
+    use crate::clock::ManualClock;
+    use crate::node::NodeRegistry;
+
+    let clock = ManualClock::stepping(64);
+    let registry = NodeRegistry::new(1..4);
+    let mut flake = Flaken::default()
+        .bitwidths(40, 20)
+        .node(0)
+        .clock(clock.clone())
+        .node_rotation(registry.clone(), 0.5);
+    // Saturated bursts of a few ticks, each followed by 100ms without ids
+    for _ in 0..50 {
+        for _ in 0..40 {
+            flake.next();
+        }
+        clock.advance(Duration::from_millis(100));
+    }
+    assert_eq!(flake.status().nodes, 1);
+    assert_eq!(registry.available(), 3);
+}
+
+#[test]
+fn test_peek_rotation() {
+    use crate::clock::ManualClock;
+    use crate::node::NodeRegistry;
+
+    let mut flake = Flaken::default()
+        .bitwidths(40, 22)
+        .node(0)
+        .clock(ManualClock::new())
+        .node_rotation(NodeRegistry::new(1..4), 0.5);
+    flake.next();
+    // As if node id 5 had been claimed
+    flake.rotation.as_mut().unwrap().nodes = vec![0, 5];
+    for _ in 0..7 {
+        assert_eq!(flake.peek(), flake.next());
+    }
+    assert_eq!(flake.decode(flake.last_id().unwrap()).1, 5);
+}
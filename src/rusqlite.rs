@@ -0,0 +1,37 @@
+//! rusqlite column types for ids, behind the `rusqlite` feature
+//!
+//! SQLite integers are signed 64 bit, so ids are stored the way
+//! [`Id`](../struct.Id.html#databases) describes.
+
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+
+use crate::Id;
+
+impl<T> ToSql for Id<T> {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        let id = self.to_signed().map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?;
+        Ok(ToSqlOutput::from(id))
+    }
+}
+
+impl<T> FromSql for Id<T> {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Id<T>> {
+        let id = value.as_i64()?;
+        Id::from_signed(id).map_err(|_| FromSqlError::OutOfRange(id))
+    }
+}
+
+#[test]
+fn test_rusqlite_round_trip() {
+    use rusqlite::Connection;
+
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY)", []).unwrap();
+    let id: Id = Id::new(i64::MAX as u64);
+    conn.execute("INSERT INTO users (id) VALUES (?1)", [id]).unwrap();
+    let stored: Id = conn.query_row("SELECT id FROM users", [], |row| row.get(0)).unwrap();
+    assert_eq!(stored, id);
+    assert!(conn.execute("INSERT INTO users (id) VALUES (?1)", [Id::<()>::new(1 << 63)]).is_err());
+    let negative: rusqlite::Result<Id> = conn.query_row("SELECT -1", [], |row| row.get(0));
+    assert!(negative.is_err());
+}
@@ -0,0 +1,78 @@
+//! SeaORM column types for ids, behind the `sea-orm` feature
+//!
+//! Entities may declare `Id` columns, which are stored as `BIGINT`, as
+//! signed integers the way [`Id`](../struct.Id.html#databases) describes.
+//!
+//! SeaORM converts values infallibly, so an id with the top bit set
+//! converts to an unsigned [`Value`] instead, which the database rejects
+//! for a `BIGINT` column when the query runs.
+
+use sea_orm::sea_query::{ArrayType, ColumnType, Nullable, Value, ValueType, ValueTypeErr};
+use sea_orm::{ColIdx, DbErr, QueryResult, TryFromU64, TryGetError, TryGetable};
+
+use crate::Id;
+
+impl<T> From<Id<T>> for Value {
+    fn from(id: Id<T>) -> Value {
+        match id.to_signed() {
+            Ok(id) => Value::BigInt(Some(id)),
+            Err(_) => Value::BigUnsigned(Some(id.get())),
+        }
+    }
+}
+
+impl<T> Nullable for Id<T> {
+    fn null() -> Value {
+        Value::BigInt(None)
+    }
+}
+
+impl<T> ValueType for Id<T> {
+    fn try_from(value: Value) -> Result<Id<T>, ValueTypeErr> {
+        match value {
+            Value::BigInt(Some(id)) => Id::from_signed(id).map_err(|_| ValueTypeErr),
+            Value::BigUnsigned(Some(id)) => Ok(Id::new(id)),
+            _ => Err(ValueTypeErr),
+        }
+    }
+
+    fn type_name() -> String {
+        "Id".to_string()
+    }
+
+    fn array_type() -> ArrayType {
+        ArrayType::BigInt
+    }
+
+    fn column_type() -> ColumnType {
+        ColumnType::BigInteger
+    }
+}
+
+impl<T> TryGetable for Id<T> {
+    fn try_get_by<I: ColIdx>(res: &QueryResult, index: I) -> Result<Id<T>, TryGetError> {
+        let id = i64::try_get_by(res, index)?;
+        Id::from_signed(id).map_err(|err| TryGetError::DbErr(DbErr::Type(err.to_string())))
+    }
+}
+
+impl<T> TryFromU64 for Id<T> {
+    fn try_from_u64(id: u64) -> Result<Id<T>, DbErr> {
+        Ok(Id::new(id))
+    }
+}
+
+#[test]
+fn test_sea_orm_value() {
+    let id: Id = Id::new(i64::MAX as u64);
+    let value = Value::from(id);
+    assert_eq!(value, Value::BigInt(Some(i64::MAX)));
+    assert_eq!(<Id as ValueType>::try_from(value).unwrap(), id);
+    assert!(<Id as ValueType>::try_from(Value::BigInt(Some(-1))).is_err());
+    assert!(<Id as ValueType>::try_from(Value::BigInt(None)).is_err());
+
+    let top: Id = Id::new(1 << 63);
+    let value = Value::from(top);
+    assert_eq!(value, Value::BigUnsigned(Some(1 << 63)));
+    assert_eq!(<Id as ValueType>::try_from(value).unwrap(), top);
+}
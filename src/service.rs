@@ -0,0 +1,196 @@
+//! A generator owned by a background thread, shared through cloneable handles
+//!
+//! Components of an application each hold an [`IdHandle`] and ask the
+//! service thread for ids over a channel, so one generator serves them all
+//! without any of them locking it. Handles wait for ids either blocking the
+//! thread or as a future, which needs no particular async runtime.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::Flaken;
+
+/// Where the service thread puts the id asked for
+#[derive(Debug, Default)]
+struct Reply {
+    slot: Mutex<Slot>,
+    ready: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+    ids: Option<Vec<u64>>,
+    /// The service dropped the request without answering it
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl Reply {
+    fn fill(&self, ids: Option<Vec<u64>>) {
+        let mut slot = self.slot.lock().unwrap();
+        slot.closed = ids.is_none();
+        slot.ids = ids;
+        self.ready.notify_one();
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A request for a number of ids, closing its reply if dropped unanswered,
+/// such as when the service thread panics
+#[derive(Debug)]
+struct Request(Option<Arc<Reply>>, usize);
+
+impl Request {
+    fn answer(mut self, ids: Vec<u64>) {
+        if let Some(reply) = self.0.take() {
+            reply.fill(Some(ids));
+        }
+    }
+}
+
+impl Drop for Request {
+    fn drop(&mut self) {
+        if let Some(reply) = self.0.take() {
+            reply.fill(None);
+        }
+    }
+}
+
+/// A generator running on its own thread, see [`IdHandle`] for taking ids
+///
+/// The thread stops once the service and every handle have been dropped.
+///
+/// ```
+/// use flaken::{Flaken, IdService};
+///
+/// let service = IdService::spawn(Flaken::default().node(4));
+/// let handle = service.handle();
+/// let worker = {
+///     let handle = handle.clone();
+///     std::thread::spawn(move || handle.next_blocking())
+/// };
+/// let id = handle.next_blocking();
+/// assert_ne!(worker.join().unwrap(), id);
+/// ```
+#[derive(Debug)]
+pub struct IdService {
+    handle: IdHandle,
+}
+
+impl IdService {
+    /// Move `flake` onto a service thread
+    pub fn spawn(mut flake: Flaken) -> IdService {
+        let (tx, rx) = mpsc::channel::<Request>();
+        thread::Builder::new()
+            .name("flaken-service".to_string())
+            .spawn(move || {
+                for request in rx {
+                    let ids = (0..request.1).map(|_| flake.next()).collect();
+                    request.answer(ids);
+                }
+            })
+            .expect("failed to spawn flaken service thread");
+        IdService { handle: IdHandle { requests: tx } }
+    }
+
+    /// A handle taking ids from the service
+    pub fn handle(&self) -> IdHandle {
+        self.handle.clone()
+    }
+}
+
+/// A cloneable handle to an [`IdService`]
+#[derive(Clone, Debug)]
+pub struct IdHandle {
+    requests: Sender<Request>,
+}
+
+impl IdHandle {
+    /// Ask the service for `count` ids, none when it stopped
+    pub(crate) fn request(&self, count: usize) -> Option<Allocation> {
+        let reply = Arc::new(Reply::default());
+        self.requests.send(Request(Some(reply.clone()), count)).ok()?;
+        Some(Allocation(reply))
+    }
+
+    /// Take the next id, blocking the thread until the service answers
+    ///
+    /// Panics when the service thread stopped, which it does when its
+    /// generator panics.
+    pub fn next_blocking(&self) -> u64 {
+        let reply = self.request(1).expect("flaken service thread stopped").0;
+        let mut slot = reply.slot.lock().unwrap();
+        loop {
+            match (slot.ids.take(), slot.closed) {
+                (Some(ids), _) => return ids[0],
+                (None, true) => panic!("flaken service thread stopped"),
+                (None, false) => slot = reply.ready.wait(slot).unwrap(),
+            }
+        }
+    }
+
+    /// Take the next id without blocking the thread, as
+    /// [`next_blocking`](#method.next_blocking)
+    pub async fn next(&self) -> u64 {
+        match self.request(1) {
+            Some(allocation) => allocation.await.expect("flaken service thread stopped")[0],
+            None => panic!("flaken service thread stopped"),
+        }
+    }
+}
+
+/// The future of ids asked of the service, none when it stopped
+#[derive(Debug)]
+pub(crate) struct Allocation(Arc<Reply>);
+
+impl Future for Allocation {
+    type Output = Option<Vec<u64>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Vec<u64>>> {
+        let mut slot = self.0.slot.lock().unwrap();
+        match (slot.ids.take(), slot.closed) {
+            (Some(ids), _) => Poll::Ready(Some(ids)),
+            (None, true) => Poll::Ready(None),
+            (None, false) => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[test]
+fn test_service_threads() {
+    let service = IdService::spawn(Flaken::default().bitwidths(40, 20));
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let handle = service.handle();
+            thread::spawn(move || (0..500).map(|_| handle.next_blocking()).collect::<Vec<u64>>())
+        })
+        .collect();
+    let mut ids: Vec<u64> = Vec::new();
+    for worker in workers {
+        let got = worker.join().unwrap();
+        assert!(got.windows(2).all(|w| w[0] < w[1]));
+        ids.extend(got);
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), 2000);
+}
+
+#[cfg(all(test, not(loom)))]
+#[tokio::test]
+async fn test_service_async() {
+    let service = IdService::spawn(Flaken::default());
+    let handle = service.handle();
+    let id0 = handle.next().await;
+    let id1 = handle.next().await;
+    assert!(id1 > id0);
+}
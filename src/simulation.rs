@@ -0,0 +1,360 @@
+//! Simulated clusters for validating layouts before deploying them
+//!
+//! A [`Simulation`] builds a generator per simulated node and has a number of
+//! callers per node take turns generating ids from it, on a virtual clock the
+//! simulation steps itself. Each node's clock is ahead of the virtual time by
+//! a fixed amount of up to the configured skew. Running it checks that no id
+//! was issued twice, that the ids each caller got always increased, and that
+//! no id's timestamp strays from the virtual time by more than the skew.
+//!
+//! Everything runs on the calling thread without waiting on real time, so a
+//! simulation takes the same course every time it is run. Contention between
+//! threads sharing a generator is not simulated.
+//!
+//! ```
+//! use std::time::Duration;
+//! use flaken::simulation::Simulation;
+//! use flaken::Flaken;
+//!
+//! let report = Simulation::new(|node| Flaken::default().bitwidths(41, 4).node(node))
+//!     .nodes(4)
+//!     .callers(2)
+//!     .ids_per_caller(1000)
+//!     .max_skew(Duration::from_millis(5))
+//!     .run()
+//!     .unwrap();
+//! assert_eq!(report.ids, 8000);
+//! ```
+
+use std::error;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::clock::Clock;
+use crate::layout::field_max;
+use crate::{AsMillis, Flaken, WaitStrategy};
+
+/// Virtual time shared by the nodes of a simulation, plus a node's own skew
+///
+/// A generator reads its clock once for an id that does not have to wait.
+/// Every further read within the same call is the generator waiting for a
+/// tick, and moves the virtual time forward by a step so the wait ends.
+#[derive(Debug)]
+struct NodeClock {
+    virtual_nanos: Arc<AtomicU64>,
+    step: u64,
+    skew: Duration,
+    reads: AtomicU64,
+}
+
+impl Clock for NodeClock {
+    fn now(&self) -> Duration {
+        if self.reads.fetch_add(1, Ordering::Relaxed) > 0 {
+            self.virtual_nanos.fetch_add(self.step, Ordering::Relaxed);
+        }
+        Duration::from_nanos(self.virtual_nanos.load(Ordering::Relaxed)) + self.skew
+    }
+}
+
+/// A check a simulation failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// Two nodes, or one node twice, issued the same id
+    Collision {
+        /// The id issued twice
+        id: u64,
+        /// The simulated nodes which issued the id
+        nodes: (u64, u64),
+    },
+    /// A caller got an id no larger than one it got before
+    OutOfOrder {
+        /// The simulated node the caller used
+        node: u64,
+        /// The id the caller got before
+        previous: u64,
+        /// The id the caller got afterwards
+        id: u64,
+    },
+    /// An id's timestamp is further from the virtual time than the skew
+    Skew {
+        /// The simulated node which issued the id
+        node: u64,
+        /// The id issued
+        id: u64,
+        /// How far the timestamp was from the virtual time, in milliseconds
+        skew_ms: i64,
+    },
+    /// A node's generator has a node id its layout can not encode
+    NodeOverflow {
+        /// The simulated node
+        node: u64,
+        /// The node id of its generator
+        node_id: u64,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Violation::Collision { id, nodes: (a, b) } => {
+                write!(f, "id {} issued by node {} and node {}", id, a, b)
+            }
+            Violation::OutOfOrder { node, previous, id } => {
+                write!(f, "node {} issued id {} after id {}", node, id, previous)
+            }
+            Violation::Skew { node, id, skew_ms } => {
+                write!(f, "node {} issued id {} with a timestamp {}ms off", node, id, skew_ms)
+            }
+            Violation::NodeOverflow { node, node_id } => {
+                write!(f, "node {} has node id {}, which does not fit in its layout", node, node_id)
+            }
+        }
+    }
+}
+
+impl error::Error for Violation {}
+
+/// What a passing simulation did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Report {
+    /// Ids issued by all nodes together
+    pub ids: u64,
+    /// Virtual time the simulation took
+    pub elapsed: Duration,
+    /// The furthest an id's timestamp was from the virtual time, in milliseconds
+    pub max_skew_ms: i64,
+}
+
+/// A simulated cluster of generators
+pub struct Simulation {
+    build: Box<dyn Fn(u64) -> Flaken>,
+    nodes: u64,
+    callers: usize,
+    ids: usize,
+    step: Duration,
+    max_skew: Duration,
+}
+
+impl fmt::Debug for Simulation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Simulation")
+            .field("nodes", &self.nodes)
+            .field("callers", &self.callers)
+            .field("ids", &self.ids)
+            .field("step", &self.step)
+            .field("max_skew", &self.max_skew)
+            .finish()
+    }
+}
+
+impl Simulation {
+    /// Simulate a cluster whose node `n` uses the generator `build(n)`
+    ///
+    /// Defaults to 3 nodes of 2 callers each generating 10000 ids, a virtual
+    /// clock advancing a millisecond at a time, and no skew.
+    pub fn new<F: Fn(u64) -> Flaken + 'static>(build: F) -> Simulation {
+        Simulation {
+            build: Box::new(build),
+            nodes: 3,
+            callers: 2,
+            ids: 10000,
+            step: Duration::from_millis(1),
+            max_skew: Duration::from_millis(0),
+        }
+    }
+
+    /// Set the number of simulated nodes
+    pub fn nodes(mut self, nodes: u64) -> Simulation {
+        self.nodes = nodes;
+        self
+    }
+
+    /// Set the number of callers taking turns on each node's generator
+    pub fn callers(mut self, callers: usize) -> Simulation {
+        self.callers = callers;
+        self
+    }
+
+    /// Set the number of ids each caller generates
+    pub fn ids_per_caller(mut self, ids: usize) -> Simulation {
+        self.ids = ids;
+        self
+    }
+
+    /// Set how far the virtual clock advances at a time
+    ///
+    /// It advances a step once every caller got an id, and again whenever a
+    /// generator waits for its next tick.
+    pub fn clock_step(mut self, step: Duration) -> Simulation {
+        self.step = step;
+        self
+    }
+
+    /// Set how far ahead of the virtual time node clocks may be
+    ///
+    /// The skew is spread evenly over the nodes, the first node has none and
+    /// the last the whole skew.
+    pub fn max_skew(mut self, skew: Duration) -> Simulation {
+        self.max_skew = skew;
+        self
+    }
+
+    /// Run the simulation, returning the first violated check
+    pub fn run(&self) -> Result<Report, Violation> {
+        let virtual_nanos = Arc::new(AtomicU64::new(0));
+        let step = self.step.as_nanos() as u64;
+        // Starting on the hour starts ticks which divide an hour together,
+        // so every run of the simulation sees the same tick boundaries
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+        let start_ts = now - now % 3_600_000;
+        let mut layouts = Vec::new();
+        let mut clocks = Vec::new();
+        let mut generators: Vec<Flaken> = (0..self.nodes)
+            .map(|node| {
+                let skew = if self.nodes > 1 {
+                    self.max_skew * node as u32 / (self.nodes - 1) as u32
+                } else {
+                    Duration::from_millis(0)
+                };
+                let clock = Arc::new(NodeClock {
+                    virtual_nanos: virtual_nanos.clone(),
+                    step,
+                    skew,
+                    reads: AtomicU64::new(0),
+                });
+                let mut flake = (self.build)(node);
+                // Every node starts at the same time, skew only applies from there
+                flake.start_ts = start_ts;
+                flake.start = Duration::from_millis(0);
+                flake.duration = 0;
+                flake.seq = 0;
+                flake.clock = clock.clone();
+                // A pause must not wait on real time
+                flake.wait = WaitStrategy::Spin;
+                layouts.push(flake.layout);
+                clocks.push(clock);
+                flake
+            })
+            .collect();
+        // Checked up front, as generating would panic on every id
+        for (node, flake) in generators.iter().enumerate() {
+            if flake.node > field_max(flake.layout.node_bits) {
+                return Err(Violation::NodeOverflow { node: node as u64, node_id: flake.node });
+            }
+        }
+
+        let virtual_ms = || Duration::from_nanos(virtual_nanos.load(Ordering::Relaxed)).as_millis();
+        let callers = generators.len() * self.callers;
+        let mut issued: Vec<Vec<(u64, u64, u64, u64)>> = vec![Vec::with_capacity(self.ids); callers];
+        for _ in 0..self.ids {
+            for (caller, ids) in issued.iter_mut().enumerate() {
+                let node = caller / self.callers;
+                clocks[node].reads.store(0, Ordering::Relaxed);
+                let before = virtual_ms();
+                let id = generators[node].next();
+                ids.push((node as u64, id, before, virtual_ms()));
+            }
+            virtual_nanos.fetch_add(step, Ordering::Relaxed);
+        }
+
+        let max_skew_ms = self.max_skew.as_millis() as i64;
+        let mut observed_skew = 0;
+        for caller_ids in &issued {
+            for pair in caller_ids.windows(2) {
+                let ((node, previous, _, _), (_, id, _, _)) = (pair[0], pair[1]);
+                if id <= previous {
+                    return Err(Violation::OutOfOrder { node, previous, id });
+                }
+            }
+            for &(node, id, before, after) in caller_ids {
+                let layout = &layouts[node as usize];
+                let (ts, _, _) = layout.decode(id);
+                // Timestamps are rounded down to the start of their tick
+                let earliest = layout.tick_floor(start_ts + before);
+                let skew_ms = if ts < earliest {
+                    ts as i64 - earliest as i64
+                } else {
+                    (ts as i64 - (start_ts + after) as i64).max(0)
+                };
+                if skew_ms > max_skew_ms || skew_ms < 0 {
+                    return Err(Violation::Skew { node, id, skew_ms });
+                }
+                observed_skew = observed_skew.max(skew_ms);
+            }
+        }
+
+        let mut all: Vec<(u64, u64)> = issued.iter().flatten().map(|&(node, id, _, _)| (id, node)).collect();
+        all.sort_unstable();
+        for pair in all.windows(2) {
+            if pair[0].0 == pair[1].0 {
+                return Err(Violation::Collision { id: pair[0].0, nodes: (pair[0].1, pair[1].1) });
+            }
+        }
+
+        Ok(Report {
+            ids: all.len() as u64,
+            elapsed: Duration::from_nanos(virtual_nanos.load(Ordering::Relaxed)),
+            max_skew_ms: observed_skew,
+        })
+    }
+}
+
+#[test]
+fn test_simulation() {
+    let simulation = Simulation::new(|node| Flaken::default().bitwidths(41, 3).node(node))
+        .nodes(4)
+        .callers(3)
+        .ids_per_caller(5000)
+        .max_skew(Duration::from_millis(3));
+    let report = simulation.run().unwrap();
+    assert_eq!(report.ids, 60000);
+    assert!(report.max_skew_ms <= 3);
+    assert_eq!(simulation.run().unwrap(), report);
+}
+
+#[test]
+fn test_simulation_saturated() {
+    // Three sequence bits allow eight ids per tick for twelve callers
+    let report = Simulation::new(|node| Flaken::default().bitwidths(41, 20).node(node))
+        .nodes(2)
+        .callers(12)
+        .ids_per_caller(100)
+        .run()
+        .unwrap();
+    assert_eq!(report.ids, 2400);
+    // Waiting for the next tick moved the clock beyond a step per round
+    assert!(report.elapsed > Duration::from_millis(100));
+}
+
+#[test]
+fn test_simulation_collision() {
+    // Sharing a node id, two nodes issue the same ids
+    let result = Simulation::new(|node| Flaken::default().bitwidths(41, 1).node(node % 2))
+        .nodes(4)
+        .ids_per_caller(1000)
+        .run();
+    match result {
+        Err(Violation::Collision { nodes: (a, b), .. }) => assert_eq!(a % 2, b % 2),
+        other => panic!("expected a collision, got {:?}", other),
+    }
+
+    // One node bit can not tell four nodes apart
+    let result = Simulation::new(|node| Flaken::default().bitwidths(41, 1).node(node))
+        .nodes(4)
+        .ids_per_caller(1000)
+        .run();
+    assert_eq!(result, Err(Violation::NodeOverflow { node: 2, node_id: 2 }));
+}
+
+#[test]
+fn test_simulation_seconds() {
+    let report = Simulation::new(|node| Flaken::seconds().node(node))
+        .nodes(2)
+        .ids_per_caller(2000)
+        .clock_step(Duration::from_millis(100))
+        .run()
+        .unwrap();
+    assert_eq!(report.ids, 8000);
+}
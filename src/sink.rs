@@ -0,0 +1,140 @@
+//! Logs of issued ids, for reconstructing what a generator issued after an
+//! incident
+
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{Sender, SyncSender};
+
+#[cfg(test)]
+use crate::Flaken;
+
+/// Where a generator records the ids it issues
+pub trait Sink: Debug + Send + Sync {
+    /// Record an issued id
+    fn record(&mut self, id: u64) -> io::Result<()>;
+}
+
+/// Which issued ids are recorded into a sink
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SinkMode {
+    /// Every id
+    Every,
+    /// The last id of each tick, which bounds every id issued in it
+    ///
+    /// The last id of the final tick is recorded when the generator is
+    /// dropped.
+    HighWater,
+}
+
+/// How far each record written to a file is pushed before it returns
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Durability {
+    /// Kept in a buffer, so records since the last full buffer are lost
+    /// if the process crashes
+    #[default]
+    Buffered,
+    /// Written to the operating system, surviving a process crash but not
+    /// a power loss
+    Flushed,
+    /// Written and synced to the disk with `sync_data`, surviving a power
+    /// loss at the cost of a disk sync per record
+    Synced,
+}
+
+/// An append-only file of issued ids, one decimal id per line
+///
+/// Writes are [`Durability::Buffered`] unless set otherwise, so ids issued
+/// shortly before a crash may be lost; pair [`Durability::Synced`] with
+/// [`SinkMode::HighWater`] to keep the cost at one sync per tick.
+#[derive(Debug)]
+pub struct FileSink {
+    out: BufWriter<File>,
+    durability: Durability,
+}
+
+impl FileSink {
+    /// Open `path` for appending, creating it if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<FileSink> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileSink { out: BufWriter::new(file), durability: Durability::Buffered })
+    }
+
+    /// Set how far each record is pushed before recording returns
+    pub fn durability(mut self, durability: Durability) -> FileSink {
+        self.durability = durability;
+        self
+    }
+}
+
+impl Sink for FileSink {
+    fn record(&mut self, id: u64) -> io::Result<()> {
+        writeln!(self.out, "{}", id)?;
+        match self.durability {
+            Durability::Buffered => Ok(()),
+            Durability::Flushed => self.out.flush(),
+            Durability::Synced => {
+                self.out.flush()?;
+                self.out.get_ref().sync_data()
+            }
+        }
+    }
+}
+
+impl Sink for Sender<u64> {
+    fn record(&mut self, id: u64) -> io::Result<()> {
+        self.send(id).map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "sink receiver dropped"))
+    }
+}
+
+/// Blocks while the channel is full, so backpressure slows the generator
+impl Sink for SyncSender<u64> {
+    fn record(&mut self, id: u64) -> io::Result<()> {
+        self.send(id).map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "sink receiver dropped"))
+    }
+}
+
+#[test]
+fn test_sink_modes() {
+    use std::sync::mpsc;
+
+    let (tx, rx) = mpsc::channel();
+    let mut flake = Flaken::default().sink(tx, SinkMode::Every);
+    let ids: Vec<u64> = (0..5).map(|_| flake.next()).collect();
+    drop(flake);
+    assert_eq!(rx.iter().collect::<Vec<u64>>(), ids);
+
+    let (tx, rx) = mpsc::channel();
+    let mut flake = Flaken::default().bitwidths(40, 21).sink(tx, SinkMode::HighWater);
+    let ids: Vec<u64> = (0..20).map(|_| flake.next()).collect();
+    drop(flake);
+    let marks: Vec<u64> = rx.iter().collect();
+    assert_eq!(marks.last(), ids.last());
+    // Eight sequence values per tick, so twenty ids span at least three ticks
+    assert!(marks.len() >= 3);
+    assert!(marks.windows(2).all(|w| w[0] < w[1]));
+}
+
+#[test]
+fn test_file_sink() {
+    let path = std::env::temp_dir().join(format!("flaken-sink-{}.log", std::process::id()));
+    let mut flake = Flaken::default().sink(FileSink::open(&path).unwrap(), SinkMode::Every);
+    let id = flake.next();
+    drop(flake);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), format!("{}\n", id));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_file_sink_durability() {
+    let path = std::env::temp_dir().join(format!("flaken-sink-durable-{}.log", std::process::id()));
+    for &durability in [Durability::Flushed, Durability::Synced].iter() {
+        let mut sink = FileSink::open(&path).unwrap().durability(durability);
+        sink.record(7).unwrap();
+        // Readable before the sink is dropped
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "7\n");
+        drop(sink);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,170 @@
+//! Watching for the wall clock drifting away from generated timestamps
+//!
+//! Generators read the wall clock once when built and then follow their
+//! monotonic clock, so NTP corrections and clock jumps are not followed and
+//! generated timestamps slowly drift from real time. Comparing the two now
+//! and then shows when they diverged too far.
+
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::clock::CHECK_INTERVAL_MS;
+use crate::{AsMillis, Flaken};
+
+/// The wall clock and a generator's time at one moment, in milliseconds
+/// since the unix epoch
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Skew {
+    /// The wall clock
+    pub wall_ms: u64,
+    /// The generator's time, as embedded in ids generated now
+    pub generator_ms: u64,
+}
+
+impl Skew {
+    /// Milliseconds the wall clock is ahead of the generator, negative when behind
+    pub fn offset_ms(&self) -> i64 {
+        self.wall_ms as i64 - self.generator_ms as i64
+    }
+
+    /// How far apart the two clocks are
+    pub fn magnitude(&self) -> Duration {
+        Duration::from_millis(self.offset_ms().unsigned_abs())
+    }
+}
+
+/// A skew threshold and the callback to call beyond it
+pub(crate) struct SkewWatch {
+    threshold: Duration,
+    next_check: u64,
+    callback: Box<dyn FnMut(Skew) + Send + Sync>,
+}
+
+impl fmt::Debug for SkewWatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SkewWatch")
+            .field("threshold", &self.threshold)
+            .field("next_check", &self.next_check)
+            .finish()
+    }
+}
+
+/// Milliseconds since the unix epoch on the wall clock
+pub(crate) fn wall_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis()
+}
+
+impl Flaken {
+    /// Call `callback` when the wall clock and the generator's time diverge
+    /// by more than `threshold`
+    ///
+    /// The clocks are compared at most once a second while ids are being
+    /// generated, and the callback is called on every comparison beyond the
+    /// threshold until they converge again.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use flaken::Flaken;
+    ///
+    /// let mut flake = Flaken::default().on_skew(Duration::from_millis(500), |skew| {
+    ///     eprintln!("wall clock is {}ms ahead of flaken", skew.offset_ms());
+    /// });
+    /// flake.next();
+    /// ```
+    pub fn on_skew<F: FnMut(Skew) + Send + Sync + 'static>(mut self, threshold: Duration, callback: F) -> Flaken {
+        self.skew = Some(SkewWatch {
+            threshold,
+            next_check: 0,
+            callback: Box::new(callback),
+        });
+        self
+    }
+
+    /// The wall clock and the generator's time now
+    pub fn skew(&self) -> Skew {
+        Skew {
+            wall_ms: wall_ms(),
+            generator_ms: self.start_ts + self.elapsed().as_millis(),
+        }
+    }
+
+    /// Nanoseconds the wall clock is ahead of the generator's time now,
+    /// negative when behind
+    ///
+    /// A signed count rather than a `Duration`, for exporting as a gauge of
+    /// how far the timestamps embedded in ids are from real time. The
+    /// generator's time is anchored to the wall clock to the millisecond
+    /// when built, so drift under a millisecond is noise.
+    pub fn drift(&self) -> i64 {
+        let wall = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as i128;
+        let generator = i128::from(self.start_ts) * 1_000_000 + self.elapsed().as_nanos() as i128;
+        (wall - generator).clamp(i64::MIN.into(), i64::MAX.into()) as i64
+    }
+
+    /// Compare the clocks if a check is due at `tick`
+    pub(crate) fn watch_skew(&mut self, tick: u64) {
+        let generator_ms = self.tick_ts(tick);
+        let interval = CHECK_INTERVAL_MS.div_ceil(self.layout.tick_ms);
+        if let Some(ref mut watch) = self.skew {
+            if tick < watch.next_check {
+                return;
+            }
+            watch.next_check = tick + interval;
+            let skew = Skew {
+                wall_ms: wall_ms(),
+                generator_ms,
+            };
+            if skew.magnitude() > watch.threshold {
+                trace_event!(warn, node = self.node, offset_ms = skew.offset_ms(), "wall clock and generator time diverged");
+                crate::metrics::clock_skew();
+                (watch.callback)(skew);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_on_skew() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use crate::clock::Clock;
+
+    /// Real time plus an adjustable jump
+    #[derive(Debug)]
+    struct Jumpy(Instant, Arc<AtomicU64>);
+
+    impl Clock for Jumpy {
+        fn now(&self) -> Duration {
+            self.0.elapsed() + Duration::from_millis(self.1.load(Ordering::Relaxed))
+        }
+    }
+
+    let jump = Arc::new(AtomicU64::new(0));
+    let fired = Arc::new(AtomicU64::new(0));
+    let mut flake = {
+        let fired = fired.clone();
+        Flaken::default()
+            .clock(Jumpy(Instant::now(), jump.clone()))
+            .on_skew(Duration::from_secs(1), move |skew| {
+                assert!(skew.offset_ms() < -4000);
+                fired.fetch_add(1, Ordering::Relaxed);
+            })
+    };
+    flake.next();
+    assert_eq!(fired.load(Ordering::Relaxed), 0);
+    jump.store(5000, Ordering::Relaxed);
+    flake.next();
+    assert_eq!(fired.load(Ordering::Relaxed), 1);
+    assert!(flake.skew().magnitude() >= Duration::from_millis(4900));
+    assert!(flake.drift() <= -4_900_000_000);
+}
+
+#[test]
+fn test_drift() {
+    let mut flake = Flaken::default();
+    assert!(flake.drift().abs() < 5_000_000);
+    flake.start_ts -= 3000;
+    assert!((2_995_000_000..3_005_000_000).contains(&flake.drift()));
+}
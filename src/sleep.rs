@@ -0,0 +1,143 @@
+//! Sleeping in async code without assuming an async runtime
+//!
+//! The async generator methods only need to wait, for the next tick or the
+//! maximum rate, so they take a [`Sleep`] for whichever runtime the
+//! application uses. Any function from a duration to a future is one, such
+//! as `async_std::task::sleep`, and the `tokio` feature adds
+//! [`next_async`](struct.Flaken.html#method.next_async) sleeping on the
+//! tokio timer.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::{Error, Flaken};
+
+/// The timer of an async runtime
+pub trait Sleep {
+    /// The future of a single sleep
+    type Future: Future<Output = ()>;
+
+    /// Sleep for `duration`
+    fn sleep(&self, duration: Duration) -> Self::Future;
+
+    /// The runtime's current instant, used by the maximum rate
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+impl<F, Fut> Sleep for F
+where
+    F: Fn(Duration) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    type Future = Fut;
+
+    fn sleep(&self, duration: Duration) -> Fut {
+        self(duration)
+    }
+}
+
+impl Flaken {
+    /// Generate the next id, sleeping with `sleep` instead of blocking the
+    /// thread while waiting for the next tick or the maximum rate
+    ///
+    /// ```
+    /// # fn block_on<F: std::future::Future>(f: F) -> F::Output {
+    /// #     let mut f = std::pin::pin!(f);
+    /// #     let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    /// #     loop {
+    /// #         if let std::task::Poll::Ready(out) = f.as_mut().poll(&mut cx) {
+    /// #             return out;
+    /// #         }
+    /// #     }
+    /// # }
+    /// use std::time::Duration;
+    /// use flaken::Flaken;
+    ///
+    /// // With async-std this would be `async_std::task::sleep`
+    /// async fn sleep(duration: Duration) {
+    ///     std::thread::sleep(duration)
+    /// }
+    ///
+    /// let mut flake = Flaken::default();
+    /// let id = block_on(flake.next_async_with(sleep));
+    /// assert!(block_on(flake.next_async_with(sleep)) > id);
+    /// ```
+    ///
+    /// Waits for the wall clock with [`real_time`](#method.real_time) and
+    /// for a strict ordering fence by sleeping too. Node rotation still
+    /// blocks when no other node id is free. Panics as
+    /// [`next`](#method.next) does.
+    pub async fn next_async_with<S: Sleep>(&mut self, sleep: S) -> u64 {
+        if let Some(ref mut limit) = self.limit {
+            while let Err(wait) = limit.take(sleep.now()) {
+                sleep.sleep(wait).await;
+            }
+        }
+        match self.generate_sleeping(&sleep, None).await {
+            Ok(id) => id,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Generate the next id unless that would exceed the maximum rate, as
+    /// [`try_next`](#method.try_next) but sleeping with `sleep` while waiting
+    /// for the next tick
+    pub async fn try_next_async_with<S: Sleep>(&mut self, sleep: S) -> Result<u64, Error> {
+        if let Some(ref mut limit) = self.limit {
+            limit.take(sleep.now()).map_err(Error::RateLimited)?;
+        }
+        let deadline = sleep.now() + self.tick_unit();
+        self.generate_sleeping(&sleep, Some(deadline)).await
+    }
+
+    /// Generate an id, sleeping while the tick is used up, the fence is past
+    /// it, or the wall clock is behind, the latter until `deadline` if set
+    async fn generate_sleeping<S: Sleep>(&mut self, sleep: &S, deadline: Option<Instant>) -> Result<u64, Error> {
+        loop {
+            self.sleep_for_tick(sleep).await;
+            match self.generate_fenced(false) {
+                // The fence used up the tick, sleep until the next one
+                Err(Error::SequenceExhausted(_)) => {}
+                Err(Error::AheadOfWall(wait)) if deadline.is_none_or(|deadline| sleep.now() + wait <= deadline) => {
+                    sleep.sleep(wait).await
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Sleep until the next tick while the sequence values of the current one are used up
+    async fn sleep_for_tick<S: Sleep>(&self, sleep: &S) {
+        while self.rotation.is_none()
+            && self.elapsed_ticks() <= self.duration
+            && self.seq > self.max_seq()
+            && !self.can_run_ahead()
+        {
+            let next_tick = self.tick_start((self.duration + 1).saturating_sub(self.ahead));
+            sleep.sleep(next_tick.saturating_sub(self.elapsed())).await;
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+#[tokio::test]
+async fn test_next_async_with() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let sleeps = AtomicUsize::new(0);
+    let sleep = |duration| {
+        sleeps.fetch_add(1, Ordering::Relaxed);
+        std::thread::sleep(duration);
+        std::future::ready(())
+    };
+    // Two sequence values per millisecond, so generating ten ids has to wait
+    let mut flake = Flaken::default().bitwidths(41, 21);
+    let mut ids = Vec::new();
+    for _ in 0..10 {
+        ids.push(flake.next_async_with(&sleep).await);
+    }
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    assert!(sleeps.load(Ordering::Relaxed) > 0);
+}
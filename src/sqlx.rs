@@ -0,0 +1,66 @@
+//! sqlx column types for ids, behind the `sqlx` feature
+//!
+//! Ids are stored as `BIGINT`, so work in any database sqlx supports, as
+//! signed integers the way [`Id`](../struct.Id.html#databases) describes.
+
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::{Database, Decode, Encode, Type};
+
+use crate::Id;
+
+impl<DB: Database, T> Type<DB> for Id<T>
+where
+    i64: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <i64 as Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <i64 as Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'q, DB: Database, T> Encode<'q, DB> for Id<T>
+where
+    i64: Encode<'q, DB>,
+{
+    fn encode_by_ref(&self, buf: &mut DB::ArgumentBuffer<'q>) -> Result<IsNull, BoxDynError> {
+        self.to_signed()?.encode_by_ref(buf)
+    }
+
+    fn size_hint(&self) -> usize {
+        8
+    }
+}
+
+impl<'r, DB: Database, T> Decode<'r, DB> for Id<T>
+where
+    i64: Decode<'r, DB>,
+{
+    fn decode(value: DB::ValueRef<'r>) -> Result<Id<T>, BoxDynError> {
+        Ok(Id::from_signed(<i64 as Decode<DB>>::decode(value)?)?)
+    }
+}
+
+#[test]
+fn test_sqlx_type() {
+    use sqlx::{MySql, Postgres};
+    assert_eq!(<Id as Type<Postgres>>::type_info(), <i64 as Type<Postgres>>::type_info());
+    assert_eq!(<Id as Type<MySql>>::type_info(), <i64 as Type<MySql>>::type_info());
+}
+
+#[test]
+fn test_sqlx_sign_boundary() {
+    use sqlx::postgres::PgArgumentBuffer;
+    use sqlx::Postgres;
+
+    let id: Id = Id::new(i64::MAX as u64);
+    let mut buf = PgArgumentBuffer::default();
+    assert!(matches!(<Id as Encode<Postgres>>::encode_by_ref(&id, &mut buf), Ok(IsNull::No)));
+    let stored = i64::from_be_bytes(buf[..8].try_into().unwrap());
+    assert_eq!(Id::<()>::from_signed(stored).unwrap(), id);
+    let mut buf = PgArgumentBuffer::default();
+    assert!(<Id as Encode<Postgres>>::encode_by_ref(&Id::new(1 << 63), &mut buf).is_err());
+}
@@ -0,0 +1,99 @@
+//! Snapshots of a generator's counters for health and debug endpoints
+//!
+//! A [`Status`] copies what a generator has done so far, such as how many
+//! ids it issued and how often its ticks ran out of sequence values, along
+//! with how long its layout has left, without changing its state.
+
+use std::time::Duration;
+
+use crate::{AsMillis, Flaken};
+
+/// A snapshot of a generator's state, for health and debug endpoints
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Status {
+    /// Node id embedded in generated ids
+    pub node: u64,
+    /// Time since the generator was built
+    pub uptime: Duration,
+    /// Timestamp of the last generated id in milliseconds since the unix
+    /// epoch, none before the first
+    pub last_ts: Option<u64>,
+    /// Number of ids generated
+    pub issued: u64,
+    /// Number of times the sequence values of a tick ran out and the
+    /// generator waited for the next one
+    pub rollovers: u64,
+    /// Time left until the timestamp bits of the layout run out
+    pub remaining: Duration,
+    /// Number of ticks ids were generated in
+    pub ticks: u64,
+    /// Number of times the sequence values of a node ran out within a tick
+    pub saturated: u64,
+    /// Number of node ids the generator issues ids with
+    pub nodes: usize,
+    /// How far the timestamp of the last generated id is ahead of the
+    /// generator's clock, with [`generate_ahead`]
+    ///
+    /// [`generate_ahead`]: struct.Flaken.html#method.generate_ahead
+    pub ahead: Duration,
+}
+
+impl Status {
+    /// The share of ticks whose sequence values ran out, from 0 to 1
+    ///
+    /// With extra node ids from [`node_rotation`] a tick may saturate once
+    /// per node, so the share may exceed 1.
+    ///
+    /// [`node_rotation`]: struct.Flaken.html#method.node_rotation
+    pub fn saturation(&self) -> f64 {
+        if self.ticks == 0 {
+            0.0
+        } else {
+            self.saturated as f64 / self.ticks as f64
+        }
+    }
+}
+
+impl Flaken {
+    /// Report the generator's state
+    pub fn status(&self) -> Status {
+        let uptime = self.uptime();
+        let now = self.start_ts + self.elapsed().as_millis();
+        let end = self.layout.max_ts().saturating_add(1);
+        Status {
+            node: self.node,
+            uptime,
+            last_ts: if self.issued > 0 { Some(self.tick_ts(self.duration)) } else { None },
+            issued: self.issued,
+            rollovers: self.rollovers,
+            remaining: Duration::from_millis(end.saturating_sub(now)),
+            ticks: self.ticks,
+            saturated: self.saturated,
+            nodes: self.rotation.as_ref().map_or(1, |rotation| rotation.nodes().len().max(1)),
+            ahead: match self.issued {
+                0 => Duration::from_millis(0),
+                _ => Duration::from_millis(self.tick_ts(self.duration).saturating_sub(now)),
+            },
+        }
+    }
+}
+
+#[test]
+fn test_status() {
+    let mut flake = Flaken::default().node(3).bitwidths(40, 22);
+    assert_eq!(flake.status().last_ts, None);
+    let mut id = 0;
+    for _ in 0..10 {
+        id = flake.next();
+    }
+    let status = flake.status();
+    assert_eq!(status.node, 3);
+    assert_eq!(status.issued, 10);
+    // Two sequence bits allow four ids per millisecond
+    assert!(status.rollovers >= 2);
+    assert!(status.saturation() > 0.0 && status.saturation() <= 1.0);
+    assert_eq!(status.last_ts, Some(flake.decode(id).0));
+    // 2^40 milliseconds from 2013 run out in 2047
+    let years = status.remaining.as_secs() / (365 * 24 * 3600);
+    assert!(years > 10 && years < 35);
+}
@@ -0,0 +1,41 @@
+//! Serializing u64 values as decimal strings
+//!
+//! JSON parsers which keep numbers as doubles, JavaScript's among them,
+//! round integers above 2^53, so ids and other full width values are
+//! written as strings. Numbers are still accepted when deserializing.
+
+#[cfg(feature = "vectors")]
+use std::fmt;
+
+#[cfg(feature = "vectors")]
+use serde::de::{self, Deserializer, Visitor};
+use serde::Serializer;
+
+pub(crate) fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+#[cfg(feature = "vectors")]
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    deserializer.deserialize_any(U64Visitor)
+}
+
+#[cfg(feature = "vectors")]
+struct U64Visitor;
+
+#[cfg(feature = "vectors")]
+impl Visitor<'_> for U64Visitor {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an unsigned 64 bit integer or a string of one")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<u64, E> {
+        Ok(value)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<u64, E> {
+        value.parse().map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
@@ -0,0 +1,60 @@
+use std::time::{Duration, Instant};
+
+use crate::Flaken;
+
+/// How many ids a generator produced over some time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Throughput {
+    /// Number of ids generated
+    pub ids: u64,
+    /// Time spent generating them
+    pub elapsed: Duration,
+}
+
+impl Throughput {
+    /// Average ids generated per second
+    pub fn ids_per_sec(&self) -> f64 {
+        self.ids as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl Flaken {
+    /// Generate ids for `duration` and report how many were generated
+    ///
+    /// This consumes ids from the generator just like calling
+    /// [`next`](#method.next) would, so the attainable rate of a given
+    /// configuration can be checked on the hardware it runs on.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use flaken::Flaken;
+    ///
+    /// let mut flake = Flaken::default();
+    /// let throughput = flake.measure_throughput(Duration::from_millis(10));
+    /// assert!(throughput.ids_per_sec() > 0.0);
+    /// ```
+    pub fn measure_throughput(&mut self, duration: Duration) -> Throughput {
+        let start = Instant::now();
+        let mut ids = 0;
+        loop {
+            // Checking the time in batches keeps it out of the measurement
+            for _ in 0..1024 {
+                std::hint::black_box(self.next());
+            }
+            ids += 1024;
+            let elapsed = start.elapsed();
+            if elapsed >= duration {
+                return Throughput { ids, elapsed };
+            }
+        }
+    }
+}
+
+#[test]
+fn test_measure_throughput() {
+    let mut flake = Flaken::default().bitwidths(40, 20);
+    let throughput = flake.measure_throughput(Duration::from_millis(5));
+    assert!(throughput.elapsed >= Duration::from_millis(5));
+    // Four sequence bits allow at most sixteen ids per millisecond
+    assert!(throughput.ids_per_sec() < 16500.0);
+}
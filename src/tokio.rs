@@ -0,0 +1,126 @@
+//! Generating ids in async code, behind the `tokio` feature
+
+use std::time::{Duration, Instant};
+
+use ::tokio::runtime::Builder;
+
+use crate::global;
+use crate::node::{Lease, NodeAllocator, NodeRegistry};
+use crate::{Error, Flaken, Sleep};
+
+/// Give every thread of the runtime its own generator for
+/// [`next`](fn.next.html), so async handlers take ids without contending
+///
+/// Each thread the runtime starts leases a node id from `registry` and uses
+/// `build(node)` as its generator, returning the node id when the thread
+/// stops. A node id returned is not leased again until the tick of its
+/// latest id has passed, so a thread starting right after does not reissue
+/// its ids. Threads of the blocking pool lease one too, so the registry needs
+/// room for them. A thread finding the registry exhausted falls back to the
+/// process-wide generator.
+///
+/// ```
+/// use flaken::node::NodeRegistry;
+/// use flaken::Flaken;
+///
+/// let mut builder = tokio::runtime::Builder::new_multi_thread();
+/// flaken::per_worker(&mut builder, NodeRegistry::new(0..64), |node| Flaken::default().node(node));
+/// let runtime = builder.build().unwrap();
+/// let id = runtime.block_on(async { tokio::spawn(async { flaken::next() }).await.unwrap() });
+/// assert!(Flaken::default().decode(id).1 < 64);
+/// ```
+pub fn per_worker<F>(builder: &mut Builder, registry: NodeRegistry, build: F) -> &mut Builder
+where
+    F: Fn(u64) -> Flaken + Send + Sync + 'static,
+{
+    builder
+        .on_thread_start(move || {
+            if let Ok(lease) = registry.claim() {
+                global::install_local(build(lease.node()), lease);
+            }
+        })
+        .on_thread_stop(global::remove_local)
+}
+
+impl Flaken {
+    /// Generate the next id, sleeping on the tokio timer instead of blocking
+    /// the thread while waiting for the next tick or the maximum rate
+    ///
+    /// With a [`TokioClock`](clock/struct.TokioClock.html) the generator
+    /// follows tokio's time, so paused tests run deterministically. Node
+    /// rotation still blocks when no other node id is free. Panics once the
+    /// clock has run past the latest timestamp the layout can encode.
+    pub async fn next_async(&mut self) -> u64 {
+        self.next_async_with(TokioSleep).await
+    }
+
+    /// Generate the next id unless that would exceed the maximum rate, as
+    /// [`try_next`](#method.try_next) but sleeping on the tokio timer while
+    /// waiting for the next tick
+    pub async fn try_next_async(&mut self) -> Result<u64, Error> {
+        self.try_next_async_with(TokioSleep).await
+    }
+}
+
+/// The tokio timer, following tokio's paused time in tests
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioSleep;
+
+impl Sleep for TokioSleep {
+    type Future = ::tokio::time::Sleep;
+
+    fn sleep(&self, duration: Duration) -> ::tokio::time::Sleep {
+        ::tokio::time::sleep(duration)
+    }
+
+    fn now(&self) -> Instant {
+        ::tokio::time::Instant::now().into_std()
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+#[test]
+fn test_per_worker() {
+    let registry = NodeRegistry::new(10..20);
+    let mut builder = Builder::new_multi_thread();
+    per_worker(builder.worker_threads(2), registry.clone(), |node| Flaken::default().bitwidths(40, 10).node(node));
+    let runtime = builder.build().unwrap();
+    let ids = runtime.block_on(async {
+        let tasks: Vec<_> = (0..8).map(|_| ::tokio::spawn(async { crate::next() })).collect();
+        let mut ids = Vec::new();
+        for task in tasks {
+            ids.push(task.await.unwrap());
+        }
+        ids
+    });
+    let layout = Flaken::default().bitwidths(40, 10);
+    assert!(ids.iter().all(|&id| (10..20).contains(&layout.decode(id).1)));
+    assert_eq!(registry.available(), 8);
+    drop(runtime);
+    assert_eq!(registry.available(), 10);
+}
+
+#[cfg(all(test, feature = "tokio"))]
+#[tokio::test(start_paused = true)]
+async fn test_next_async_paused() {
+    use crate::clock::TokioClock;
+
+    let mut flake = Flaken::default().bitwidths(40, 22).clock(TokioClock::new());
+    let mut ids = Vec::new();
+    for _ in 0..12 {
+        ids.push(flake.next_async().await);
+    }
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    let ts0 = flake.decode(ids[0]).0;
+    // Four sequence values per millisecond, and the paused clock only moves
+    // when the generator sleeps for the next one
+    for (i, &id) in ids.iter().enumerate() {
+        assert_eq!(flake.decode(id), (ts0 + i as u64 / 4, 0, i as u64 % 4));
+    }
+
+    let mut flake = Flaken::default().bitwidths(40, 22).clock(TokioClock::new());
+    let id = flake.next_async().await;
+    ::tokio::time::advance(Duration::from_secs(10)).await;
+    let later = flake.next_async().await;
+    assert_eq!(flake.decode(later).0, flake.decode(id).0 + 10_000);
+}
@@ -0,0 +1,104 @@
+//! Taking ids through tower middleware, behind the `tower` feature
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::{Error, IdHandle};
+
+/// A request for ids from an [`IdHandle`] used as a tower service
+///
+/// ```
+/// use flaken::{AllocateRequest, Flaken, IdService};
+/// use tower::ServiceExt;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let service = IdService::spawn(Flaken::default());
+/// let ids = service.handle().oneshot(AllocateRequest::new(3)).await.unwrap();
+/// assert_eq!(ids.len(), 3);
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocateRequest {
+    /// How many ids to take
+    pub count: usize,
+}
+
+impl AllocateRequest {
+    /// The most ids one request may take, so a single request does not
+    /// hold up the service thread for everyone else
+    pub const MAX_COUNT: usize = 4096;
+
+    /// Ask for `count` ids
+    pub fn new(count: usize) -> AllocateRequest {
+        AllocateRequest { count }
+    }
+}
+
+impl Default for AllocateRequest {
+    /// Ask for a single id
+    fn default() -> AllocateRequest {
+        AllocateRequest::new(1)
+    }
+}
+
+/// Takes the ids of a request in order, failing with [`Error::Stopped`] once
+/// the service thread stopped
+///
+/// The handle is always ready, requests queue on the service thread.
+/// Requests for more than [`AllocateRequest::MAX_COUNT`] ids fail with
+/// [`Error::TooMany`] without reaching it.
+impl tower_service::Service<AllocateRequest> for IdHandle {
+    type Response = Vec<u64>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Vec<u64>, Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: AllocateRequest) -> Self::Future {
+        if req.count > AllocateRequest::MAX_COUNT {
+            return Box::pin(async move { Err(Error::TooMany(req.count)) });
+        }
+        let allocation = self.request(req.count);
+        Box::pin(async move {
+            match allocation {
+                Some(allocation) => allocation.await.ok_or(Error::Stopped),
+                None => Err(Error::Stopped),
+            }
+        })
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+#[tokio::test]
+async fn test_tower_service() {
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    use crate::{Flaken, IdService};
+
+    let service = IdService::spawn(Flaken::default());
+    let ids = tower::timeout::Timeout::new(service.handle(), Duration::from_secs(5))
+        .oneshot(AllocateRequest::new(4))
+        .await
+        .unwrap();
+    assert_eq!(ids.len(), 4);
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+}
+
+#[cfg(all(test, not(loom)))]
+#[tokio::test]
+async fn test_tower_too_many() {
+    use tower::ServiceExt;
+
+    use crate::{Flaken, IdService};
+
+    let service = IdService::spawn(Flaken::default());
+    let result = service.handle().oneshot(AllocateRequest::new(usize::MAX)).await;
+    assert!(matches!(result, Err(Error::TooMany(usize::MAX))));
+    let ids = service.handle().oneshot(AllocateRequest::new(AllocateRequest::MAX_COUNT)).await.unwrap();
+    assert_eq!(ids.len(), AllocateRequest::MAX_COUNT);
+}
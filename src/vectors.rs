@@ -0,0 +1,256 @@
+//! Test vectors for implementations of a layout in other languages
+//!
+//! Services written in several languages sharing a layout each carry their
+//! own encoder. Generating canonical vectors here, a layout along with
+//! fields and the id they encode to, and checking every implementation
+//! against them proves they agree bit for bit. Behind the `vectors` feature.
+//!
+//! The epoch and the fields and ids of vectors are written as JSON strings,
+//! since many JSON parsers round integers above 2^53; bit widths are numbers.
+//!
+//! ```
+//! use flaken::vectors::TestVectors;
+//! use flaken::Flaken;
+//!
+//! let json = TestVectors::generate(&Flaken::default().bitwidths(41, 10)).to_json();
+//! let vectors = TestVectors::from_json(&json).unwrap();
+//! assert!(vectors.verify().is_ok());
+//! ```
+
+use std::error;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::layout::{field_max, Layout};
+use crate::Flaken;
+
+/// A layout as written to test vectors
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LayoutConfig {
+    /// Milliseconds since the unix epoch
+    #[serde(with = "crate::string")]
+    pub epoch: u64,
+    /// Milliseconds per unit of the timestamp field
+    #[serde(with = "crate::string")]
+    pub tick_ms: u64,
+    /// Bits reserved at the top of ids
+    pub reserved_bits: u64,
+    /// Bits of the version field
+    pub version_bits: u64,
+    /// The version embedded in ids
+    #[serde(with = "crate::string")]
+    pub version: u64,
+    /// Bits of the timestamp field
+    pub timestamp_bits: u64,
+    /// Bits of the node field
+    pub node_bits: u64,
+    /// Bits of the sequence field
+    pub sequence_bits: u64,
+}
+
+impl LayoutConfig {
+    /// The layout of a generator
+    pub fn of(flake: &Flaken) -> LayoutConfig {
+        let layout = &flake.layout;
+        LayoutConfig {
+            epoch: layout.epoch,
+            tick_ms: layout.tick_ms,
+            reserved_bits: layout.reserved_bits,
+            version_bits: layout.version_bits,
+            version: layout.version,
+            timestamp_bits: layout.ts_bits,
+            node_bits: layout.node_bits,
+            sequence_bits: layout.seq_bits,
+        }
+    }
+
+    /// Build a generator of the layout, none when the fields do not make up
+    /// a valid layout
+    pub fn build(&self) -> Option<Flaken> {
+        let bits = [self.reserved_bits, self.version_bits, self.timestamp_bits, self.node_bits, self.sequence_bits]
+            .iter()
+            .try_fold(0u64, |bits, &field| bits.checked_add(field));
+        if bits != Some(64) || self.timestamp_bits == 0 || self.tick_ms == 0 || self.version > field_max(self.version_bits) {
+            return None;
+        }
+        let mut flake = Flaken::default();
+        flake.layout = Layout {
+            epoch: self.epoch,
+            reserved_bits: self.reserved_bits,
+            version_bits: self.version_bits,
+            version: self.version,
+            ts_bits: self.timestamp_bits,
+            node_bits: self.node_bits,
+            seq_bits: self.sequence_bits,
+            tick_ms: self.tick_ms,
+        };
+        Some(flake)
+    }
+}
+
+/// Fields and the id they encode to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Vector {
+    /// Milliseconds since the unix epoch, at the start of a tick
+    #[serde(with = "crate::string")]
+    pub timestamp: u64,
+    /// The node id
+    #[serde(with = "crate::string")]
+    pub node: u64,
+    /// The sequence value
+    #[serde(with = "crate::string")]
+    pub sequence: u64,
+    /// The id
+    #[serde(with = "crate::string")]
+    pub id: u64,
+}
+
+/// A layout and vectors of it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestVectors {
+    /// The layout the vectors are encoded with
+    pub layout: LayoutConfig,
+    /// Fields and their ids
+    pub vectors: Vec<Vector>,
+}
+
+/// A check of test vectors which failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// The layout's fields do not make up a valid layout
+    Layout,
+    /// A vector's fields do not fit the layout
+    Fields {
+        /// The index of the vector
+        index: usize,
+    },
+    /// A vector's fields encoded to another id
+    Encode {
+        /// The index of the vector
+        index: usize,
+        /// The id of the vector
+        expected: u64,
+        /// The id the fields encoded to
+        actual: u64,
+    },
+    /// A vector's id decoded to other fields
+    Decode {
+        /// The index of the vector
+        index: usize,
+        /// The timestamp, node, and sequence of the vector
+        expected: (u64, u64, u64),
+        /// The fields the id decoded to
+        actual: (u64, u64, u64),
+    },
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Failure::Layout => write!(f, "test vectors have an invalid layout"),
+            Failure::Fields { index } => write!(f, "vector {} has fields outside the layout", index),
+            Failure::Encode { index, expected, actual } => {
+                write!(f, "vector {} encoded to {} instead of {}", index, actual, expected)
+            }
+            Failure::Decode { index, expected, actual } => {
+                write!(f, "vector {} decoded to {:?} instead of {:?}", index, actual, expected)
+            }
+        }
+    }
+}
+
+impl error::Error for Failure {}
+
+impl TestVectors {
+    /// Vectors of the generator's layout, covering the first and last
+    /// timestamps, the smallest and largest node ids and sequence values,
+    /// and values in between
+    pub fn generate(flake: &Flaken) -> TestVectors {
+        let layout = &flake.layout;
+        let last = layout.tick_floor(layout.max_ts());
+        let timestamps = [
+            layout.epoch,
+            layout.epoch + layout.tick_ms,
+            layout.tick_floor(layout.epoch + (last - layout.epoch) / 3),
+            last,
+        ];
+        let spread = |max: u64| [0, max / 2 + u64::from(max > 1), max];
+        let mut vectors = Vec::new();
+        for &timestamp in timestamps.iter() {
+            for &node in spread(field_max(layout.node_bits)).iter() {
+                for &sequence in spread(layout.max_seq()).iter() {
+                    let id = layout.encode(timestamp, node, sequence);
+                    vectors.push(Vector { timestamp, node, sequence, id });
+                }
+            }
+        }
+        vectors.dedup();
+        TestVectors {
+            layout: LayoutConfig::of(flake),
+            vectors,
+        }
+    }
+
+    /// Serialize as pretty printed JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("test vectors serialize")
+    }
+
+    /// Parse vectors from JSON
+    pub fn from_json(json: &str) -> serde_json::Result<TestVectors> {
+        serde_json::from_str(json)
+    }
+
+    /// Check this implementation against the vectors, both encoding each
+    /// vector's fields and decoding its id
+    pub fn verify(&self) -> Result<(), Failure> {
+        let flake = self.layout.build().ok_or(Failure::Layout)?;
+        for (index, vector) in self.vectors.iter().enumerate() {
+            let expected = (vector.timestamp, vector.node, vector.sequence);
+            let actual = flake
+                .layout
+                .try_encode(vector.timestamp, vector.node, vector.sequence)
+                .map_err(|_| Failure::Fields { index })?;
+            if actual != vector.id {
+                return Err(Failure::Encode { index, expected: vector.id, actual });
+            }
+            let actual = flake.decode(vector.id);
+            if actual != expected {
+                return Err(Failure::Decode { index, expected, actual });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_vectors() {
+    let flake = Flaken::seconds().version_bits(1).version(1);
+    let vectors = TestVectors::generate(&flake);
+    assert_eq!(vectors.vectors.len(), 36);
+    let mut parsed = TestVectors::from_json(&vectors.to_json()).unwrap();
+    assert_eq!(parsed, vectors);
+    assert_eq!(parsed.verify(), Ok(()));
+    assert!(parsed.layout.build().unwrap().compatible_with(&flake).is_compatible());
+    parsed.vectors[5].id ^= 1;
+    assert!(matches!(parsed.verify(), Err(Failure::Encode { index: 5, .. })));
+    parsed.vectors[5].id ^= 1;
+    parsed.vectors[6].node = u64::MAX;
+    assert_eq!(parsed.verify(), Err(Failure::Fields { index: 6 }));
+    parsed.layout.node_bits += 1;
+    assert_eq!(parsed.verify(), Err(Failure::Layout));
+}
+
+#[test]
+fn test_vectors_json() {
+    let vectors = TestVectors::generate(&Flaken::default());
+    let json: serde_json::Value = serde_json::from_str(&vectors.to_json()).unwrap();
+    let last = vectors.vectors.last().unwrap();
+    assert_eq!(json["vectors"][vectors.vectors.len() - 1]["id"], last.id.to_string());
+    assert_eq!(json["layout"]["epoch"], vectors.layout.epoch.to_string());
+    assert_eq!(json["layout"]["node_bits"], 10);
+    // Vectors written with numbers still parse
+    let numbers = r#"{"timestamp": 1, "node": 2, "sequence": 3, "id": 4}"#;
+    assert_eq!(serde_json::from_str::<Vector>(numbers).unwrap().id, 4);
+}
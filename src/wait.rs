@@ -0,0 +1,57 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How a generator waits for the next tick once the sequence of the current
+/// tick is used up
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WaitStrategy {
+    /// Busy spin on the clock, lowest latency at the cost of a busy core
+    Spin,
+    /// Yield to the scheduler between reads of the clock
+    #[default]
+    Yield,
+    /// Sleep for the given granularity between reads of the clock
+    Sleep(Duration),
+    /// Park the thread until the next tick is due
+    Park,
+}
+
+impl WaitStrategy {
+    /// Block until `deadline` has passed
+    pub(crate) fn wait_until(self, deadline: Instant) {
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return;
+            }
+            self.pause(deadline - now);
+        }
+    }
+
+    /// Wait once before checking the clock again, `remaining` being the
+    /// expected time until the awaited tick
+    pub(crate) fn pause(self, remaining: Duration) {
+        match self {
+            WaitStrategy::Spin => std::hint::spin_loop(),
+            WaitStrategy::Yield => thread::yield_now(),
+            WaitStrategy::Sleep(granularity) => thread::sleep(granularity),
+            WaitStrategy::Park if remaining > Duration::from_millis(0) => thread::park_timeout(remaining),
+            WaitStrategy::Park => thread::yield_now(),
+        }
+    }
+}
+
+#[test]
+fn test_wait_until() {
+    let strategies = [
+        WaitStrategy::Spin,
+        WaitStrategy::Yield,
+        WaitStrategy::Sleep(Duration::from_micros(100)),
+        WaitStrategy::Park,
+    ];
+    for strategy in strategies.iter() {
+        let deadline = Instant::now() + Duration::from_millis(2);
+        strategy.wait_until(deadline);
+        assert!(Instant::now() >= deadline);
+    }
+}
@@ -0,0 +1,85 @@
+//! Decoding ids of well known snowflake schemes
+//!
+//! For quickly reading the time and origin out of ids issued by other
+//! services, without building a generator of their layout.
+//!
+//! ```
+//! use flaken::wellknown::{decode, Kind};
+//!
+//! // The example from Discord's API documentation
+//! let decoded = decode(Kind::Discord, 175928847299117063);
+//! assert_eq!(decoded.ts, 1462015105796);
+//! assert_eq!((decoded.node, decoded.seq), (1 << 5, 7));
+//! ```
+
+use crate::layout::Layout;
+use crate::{DecodedId, Epoch};
+
+/// A well known snowflake scheme
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Kind {
+    /// Twitter's snowflakes, a clear sign bit, 41 bits of milliseconds
+    /// since 2010-11-04T01:42:54.657Z, 10 bits of datacenter and worker,
+    /// and 12 sequence bits
+    Twitter,
+    /// Discord's snowflakes, 42 bits of milliseconds since
+    /// 2015-01-01T00:00:00Z, 10 bits of worker and process, and 12 bits of
+    /// increment
+    Discord,
+    /// Instagram's ids, 41 bits of milliseconds since
+    /// 2011-08-24T21:07:01.721Z, 13 shard bits, and 10 sequence bits
+    Instagram,
+    /// Mastodon's ids, 48 bits of milliseconds since the unix epoch and 16
+    /// bits of sequence data, without a node
+    Mastodon,
+}
+
+impl Kind {
+    /// The epoch ids of the scheme count from
+    pub fn epoch(self) -> Epoch {
+        match self {
+            Kind::Twitter => Epoch::Twitter2010,
+            Kind::Discord => Epoch::Discord2015,
+            Kind::Instagram => Epoch::Custom(1314220021721),
+            Kind::Mastodon => Epoch::Unix,
+        }
+    }
+
+    fn layout(self) -> Layout {
+        let (reserved_bits, ts_bits, node_bits, seq_bits) = match self {
+            Kind::Twitter => (1, 41, 10, 12),
+            Kind::Discord => (0, 42, 10, 12),
+            Kind::Instagram => (0, 41, 13, 10),
+            Kind::Mastodon => (0, 48, 0, 16),
+        };
+        Layout {
+            epoch: self.epoch().as_millis(),
+            reserved_bits,
+            version_bits: 0,
+            version: 0,
+            ts_bits,
+            node_bits,
+            seq_bits,
+            tick_ms: 1,
+        }
+    }
+}
+
+/// Decode an id of a well known scheme
+///
+/// The node of Twitter and Discord ids packs two 5 bit fields, datacenter
+/// and worker or worker and process, the first in the upper bits.
+pub fn decode(kind: Kind, id: u64) -> DecodedId {
+    let (ts, node, seq) = kind.layout().decode(id);
+    DecodedId { id, ts, node, seq }
+}
+
+#[test]
+fn test_wellknown() {
+    let twitter = ((1_600_000_000_000 - 1288834974657) << 22) | (3 << 17) | (9 << 12) | 42;
+    assert_eq!(decode(Kind::Twitter, twitter), DecodedId { id: twitter, ts: 1_600_000_000_000, node: 3 << 5 | 9, seq: 42 });
+    let instagram = ((1_400_000_000_000 - 1314220021721) << 23) | (1341 << 10) | 7;
+    assert_eq!((decode(Kind::Instagram, instagram).node, decode(Kind::Instagram, instagram).seq), (1341, 7));
+    let mastodon = (1_700_000_000_000 << 16) | 0xbeef;
+    assert_eq!(decode(Kind::Mastodon, mastodon), DecodedId { id: mastodon, ts: 1_700_000_000_000, node: 0, seq: 0xbeef });
+}
@@ -0,0 +1,79 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flaken::Flaken;
+use proptest::prelude::*;
+
+/// Valid (timestamp bits, node bits) pairs, leaving any number of sequence bits
+fn bitwidths() -> impl Strategy<Value = (u64, u64)> {
+    (1u64..64).prop_flat_map(|ts_bits| (Just(ts_bits), 0..=(64 - ts_bits)))
+}
+
+fn mask(bits: u64) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+fn now_ms() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    now.as_secs() * 1000 + u64::from(now.subsec_millis())
+}
+
+proptest! {
+    #[test]
+    fn encode_decode_round_trip(
+        (ts_bits, node_bits) in bitwidths(),
+        epoch in 0u64..(1 << 42),
+        raw in any::<(u64, u64, u64)>(),
+    ) {
+        let seq_bits = 64 - ts_bits - node_bits;
+        let flake = Flaken::default().epoch(epoch).bitwidths(ts_bits, node_bits);
+        let ts = epoch + (raw.0 & mask(ts_bits));
+        let node = raw.1 & mask(node_bits);
+        let seq = raw.2 & mask(seq_bits);
+        let id = flake.encode(ts, node, seq);
+        prop_assert_eq!(flake.decode(id), (ts, node, seq));
+    }
+
+    #[test]
+    fn masks_are_consistent(
+        (ts_bits, node_bits) in bitwidths(),
+        id in any::<u64>(),
+        raw in any::<(u64, u64, u64)>(),
+    ) {
+        let seq_bits = 64 - ts_bits - node_bits;
+        let flake = Flaken::default().epoch(0).bitwidths(ts_bits, node_bits);
+
+        // Every id decodes to fields fitting their widths and back to itself
+        let (ts, node, seq) = flake.decode(id);
+        prop_assert!(ts <= mask(ts_bits));
+        prop_assert!(node <= mask(node_bits));
+        prop_assert!(seq <= mask(seq_bits));
+        prop_assert_eq!(flake.encode(ts, node, seq), id);
+
+        // Bits beyond a field's width never leak into the other fields
+        let masked = flake.encode(raw.0 & mask(ts_bits), raw.1 & mask(node_bits), raw.2 & mask(seq_bits));
+        prop_assert_eq!(flake.encode(raw.0, raw.1, raw.2), masked);
+    }
+
+    #[test]
+    fn next_is_monotonic(
+        ts_bits in 24u64..50,
+        node_bits in 0u64..10,
+        node in any::<u64>(),
+        count in 1usize..2000,
+    ) {
+        let node = node & mask(node_bits);
+        let epoch = now_ms() - 1000;
+        let mut flake = Flaken::default().epoch(epoch).bitwidths(ts_bits, node_bits).node(node);
+        let mut last = flake.next();
+        for _ in 0..count {
+            let id = flake.next();
+            prop_assert!(id > last);
+            prop_assert_eq!(flake.decode(id).1, node);
+            last = id;
+        }
+    }
+}